@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+use super::sea_orm_active_enums::AcStatus;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -9,24 +10,44 @@ pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i64,
     #[sea_orm(column_type = "Text")]
+    #[serde(skip_serializing)]
     pub pwd: String,
     pub name: String,
+    pub status: AcStatus,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::entitlement::Entity")]
+    Entitlement,
     #[sea_orm(has_many = "super::msg::Entity")]
     Msg,
+    #[sea_orm(has_many = "super::position::Entity")]
+    Position,
     #[sea_orm(has_many = "super::req::Entity")]
     Req,
 }
 
+impl Related<super::entitlement::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Entitlement.def()
+    }
+}
+
 impl Related<super::msg::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Msg.def()
     }
 }
 
+impl Related<super::position::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Position.def()
+    }
+}
+
 impl Related<super::req::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Req.def()
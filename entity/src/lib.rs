@@ -3,8 +3,14 @@
 pub mod prelude;
 
 pub mod ac;
+pub mod candle;
+pub mod corp_action;
+pub mod entitlement;
 pub mod msg;
 pub mod order;
+pub mod order_history;
+pub mod position;
+pub mod projection;
 pub mod rec;
 pub mod req;
 pub mod sea_orm_active_enums;
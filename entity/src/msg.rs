@@ -12,6 +12,8 @@ pub struct Model {
     pub event_type: String,
     pub data: Json,
     pub happened_at: DateTimeWithTimeZone,
+    pub delivered_at: Option<DateTimeWithTimeZone>,
+    pub acked_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,8 +1,14 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
 pub use super::ac::Entity as Ac;
+pub use super::candle::Entity as Candle;
+pub use super::corp_action::Entity as CorpAction;
+pub use super::entitlement::Entity as Entitlement;
 pub use super::msg::Entity as Msg;
 pub use super::order::Entity as Order;
+pub use super::order_history::Entity as OrderHistory;
+pub use super::position::Entity as Position;
+pub use super::projection::Entity as Projection;
 pub use super::rec::Entity as Rec;
 pub use super::req::Entity as Req;
 pub use super::security::Entity as Security;
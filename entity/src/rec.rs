@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+use super::sea_orm_active_enums::Dir;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -15,6 +16,10 @@ pub struct Model {
     pub price: Decimal,
     pub quantity: i64,
     pub created_at: DateTimeWithTimeZone,
+    pub seq_bid: Option<i64>,
+    pub seq_offer: Option<i64>,
+    pub aggressor: Option<Dir>,
+    pub auction: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
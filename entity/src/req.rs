@@ -11,6 +11,7 @@ pub struct Model {
     pub id: i64,
     pub body: Json,
     pub created_at: DateTimeWithTimeZone,
+    pub cl_ord_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
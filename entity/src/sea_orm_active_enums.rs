@@ -3,6 +3,17 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, Copy)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "ac_status")]
+pub enum AcStatus {
+    #[sea_orm(string_value = "Active")]
+    Active,
+    #[sea_orm(string_value = "Suspended")]
+    Suspended,
+    #[sea_orm(string_value = "Closed")]
+    Closed,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, Copy)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "dir")]
 pub enum Dir {
@@ -11,3 +22,43 @@ pub enum Dir {
     #[sea_orm(string_value = "Sell")]
     Sell,
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, Copy)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "security_status")]
+pub enum SecurityStatus {
+    #[sea_orm(string_value = "Listed")]
+    Listed,
+    #[sea_orm(string_value = "Suspended")]
+    Suspended,
+    #[sea_orm(string_value = "Delisted")]
+    Delisted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize, Copy)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "ord_status")]
+pub enum OrdStatus {
+    #[sea_orm(string_value = "New")]
+    New,
+    #[sea_orm(string_value = "PartiallyFilled")]
+    PartiallyFilled,
+    #[sea_orm(string_value = "Filled")]
+    Filled,
+    /// Taken off the book by its owner, or because the account stopped trading.
+    #[sea_orm(string_value = "Canceled")]
+    Canceled,
+    /// Taken off the book by the venue, as when the security is delisted.
+    #[sea_orm(string_value = "Expired")]
+    Expired,
+    #[sea_orm(string_value = "Rejected")]
+    Rejected,
+    /// Taken off the book to be replaced by an amended order.
+    #[sea_orm(string_value = "Replaced")]
+    Replaced,
+}
+
+impl OrdStatus {
+    /// Whether the order is done and will not be reported on again.
+    pub fn is_final(self) -> bool {
+        !matches!(self, OrdStatus::New | OrdStatus::PartiallyFilled)
+    }
+}
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.15
 
+use super::sea_orm_active_enums::SecurityStatus;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -9,22 +10,65 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub code: String,
     pub name: String,
+    pub currency: String,
+    #[sea_orm(column_type = "Decimal(Some((1000, 2)))")]
+    pub tick_size: Decimal,
+    pub lot_size: i64,
+    #[sea_orm(column_type = "Decimal(Some((1000, 2)))", nullable)]
+    pub ref_price: Option<Decimal>,
+    #[sea_orm(column_type = "Decimal(Some((6, 4)))", nullable)]
+    pub price_band: Option<Decimal>,
+    pub segment: String,
+    pub status: SecurityStatus,
+    pub updated_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::candle::Entity")]
+    Candle,
+    #[sea_orm(has_many = "super::corp_action::Entity")]
+    CorpAction,
+    #[sea_orm(has_many = "super::entitlement::Entity")]
+    Entitlement,
     #[sea_orm(has_many = "super::order::Entity")]
     Order,
+    #[sea_orm(has_many = "super::position::Entity")]
+    Position,
     #[sea_orm(has_many = "super::rec::Entity")]
     Rec,
 }
 
+impl Related<super::candle::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Candle.def()
+    }
+}
+
+impl Related<super::corp_action::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CorpAction.def()
+    }
+}
+
+impl Related<super::entitlement::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Entitlement.def()
+    }
+}
+
 impl Related<super::order::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Order.def()
     }
 }
 
+impl Related<super::position::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Position.def()
+    }
+}
+
 impl Related<super::rec::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Rec.def()
@@ -0,0 +1,248 @@
+//! A FIX 4.4 load test for the order entry gateway.
+//!
+//! `flood <addr> <account> <pwd> <code> <sessions> <orders> <price>` logs `sessions` sessions on
+//! for the account at once, and on each sends `orders` limit orders of one lot at `price`, buying
+//! and selling in turn, without waiting for the answers. It then prints how many were accepted,
+//! refused as busy or rejected otherwise, and how long the answers took.
+
+use fix::{msg_type, tag, timestamp, Message};
+use std::collections::HashMap;
+use std::process::exit;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::time;
+
+const TARGET: &str = "SECURITY_MATCHING";
+
+/// How the orders of a session were answered.
+#[derive(Default)]
+struct Tally {
+    accepted: usize,
+    busy: usize,
+    rejected: HashMap<String, usize>,
+    /// From sending each order to its first answer.
+    latencies: Vec<Duration>,
+}
+
+impl Tally {
+    fn add(&mut self, other: Tally) {
+        self.accepted += other.accepted;
+        self.busy += other.busy;
+        for (text, n) in other.rejected {
+            *self.rejected.entry(text).or_default() += n;
+        }
+        self.latencies.extend(other.latencies);
+    }
+}
+
+struct Args {
+    addr: String,
+    account: String,
+    pwd: String,
+    code: String,
+    orders: usize,
+    price: String,
+}
+
+async fn session(n: usize, args: &Args) -> Result<Tally, String> {
+    let sender = format!("FLOOD{n}");
+    let (read, mut write) = TcpStream::connect(args.addr.as_str())
+        .await
+        .map_err(|err| err.to_string())?
+        .into_split();
+    let (tx, mut rx) = unbounded_channel();
+    tokio::spawn(async move {
+        let mut read = BufReader::new(read);
+        while let Ok(Some(frame)) = fix::read_frame(&mut read).await {
+            if let Ok(message) = Message::decode(&frame) {
+                if tx.send((Instant::now(), message)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    let mut seq = 1;
+    let mut stamp = |mut message: Message| {
+        message.stamp(&sender, TARGET, seq, &timestamp());
+        seq += 1;
+        message.encode()
+    };
+
+    let logon = Message::new(msg_type::LOGON)
+        .with(tag::ENCRYPT_METHOD, 0)
+        .with(tag::HEART_BT_INT, 30)
+        .with(tag::RESET_SEQ_NUM_FLAG, "Y")
+        .with(tag::USERNAME, &args.account)
+        .with(tag::PASSWORD, &args.pwd);
+    write
+        .write_all(&stamp(logon))
+        .await
+        .map_err(|err| err.to_string())?;
+    match time::timeout(Duration::from_secs(30), rx.recv()).await {
+        Ok(Some((_, message))) if message.msg_type() == msg_type::LOGON => {}
+        Ok(Some((_, message))) => {
+            return Err(format!(
+                "logon refused: {}",
+                message.get(tag::TEXT).unwrap_or_default()
+            ))
+        }
+        _ => return Err("no answer to logon".to_owned()),
+    }
+
+    let mut tally = Tally::default();
+    let mut pending = HashMap::new();
+    let mut sent = 0;
+    while sent < args.orders || !pending.is_empty() {
+        if sent < args.orders {
+            let cl_ord_id = format!("{sender}-{}-{sent}", chrono::Utc::now().timestamp_millis());
+            let order = Message::new(msg_type::NEW_ORDER_SINGLE)
+                .with(tag::CL_ORD_ID, &cl_ord_id)
+                .with(tag::SYMBOL, &args.code)
+                .with(tag::SIDE, if sent % 2 == 0 { 1 } else { 2 })
+                .with(tag::TRANSACT_TIME, timestamp())
+                .with(tag::ORD_TYPE, 2)
+                .with(tag::PRICE, &args.price)
+                .with(tag::ORDER_QTY, 100);
+            pending.insert(cl_ord_id, Instant::now());
+            write
+                .write_all(&stamp(order))
+                .await
+                .map_err(|err| err.to_string())?;
+            sent += 1;
+        }
+        loop {
+            let (at, message) = if sent < args.orders {
+                match rx.try_recv() {
+                    Ok(received) => received,
+                    Err(_) => break,
+                }
+            } else {
+                match time::timeout(Duration::from_secs(30), rx.recv()).await {
+                    Ok(Some(received)) => received,
+                    Ok(None) => return Err("disconnected".to_owned()),
+                    Err(_) => return Err(format!("{} orders unanswered", pending.len())),
+                }
+            };
+            match message.msg_type() {
+                msg_type::TEST_REQUEST => {
+                    let id = message
+                        .get(tag::TEST_REQ_ID)
+                        .unwrap_or_default()
+                        .to_string();
+                    let heartbeat = Message::new(msg_type::HEARTBEAT).with(tag::TEST_REQ_ID, id);
+                    write
+                        .write_all(&stamp(heartbeat))
+                        .await
+                        .map_err(|err| err.to_string())?;
+                }
+                msg_type::EXECUTION_REPORT => {
+                    let Some(sent_at) = message
+                        .get(tag::CL_ORD_ID)
+                        .and_then(|id| pending.remove(id))
+                    else {
+                        continue;
+                    };
+                    tally.latencies.push(at - sent_at);
+                    match message.get(tag::EXEC_TYPE) {
+                        Some("8") => {
+                            let text = message.get(tag::TEXT).unwrap_or_default();
+                            if text.contains("busy") {
+                                tally.busy += 1;
+                            } else {
+                                *tally.rejected.entry(text.to_owned()).or_default() += 1;
+                            }
+                        }
+                        _ => tally.accepted += 1,
+                    }
+                    if pending.is_empty() {
+                        break;
+                    }
+                }
+                msg_type::LOGOUT => {
+                    return Err(format!(
+                        "logged out: {}",
+                        message.get(tag::TEXT).unwrap_or_default()
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+    write
+        .write_all(&stamp(Message::new(msg_type::LOGOUT)))
+        .await
+        .unwrap_or_default();
+    Ok(tally)
+}
+
+/// The latency below which `q` of the answers came.
+fn quantile(latencies: &[Duration], q: f64) -> Duration {
+    match latencies.len() {
+        0 => Duration::ZERO,
+        n => latencies[((n - 1) as f64 * q) as usize],
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [addr, account, pwd, code, sessions, orders, price] = args.as_slice() else {
+        eprintln!("usage: flood <addr> <account> <pwd> <code> <sessions> <orders> <price>");
+        exit(2);
+    };
+    let sessions: usize = sessions.parse().expect("sessions must be an integer");
+    let args = Args {
+        addr: addr.clone(),
+        account: account.clone(),
+        pwd: pwd.clone(),
+        code: code.clone(),
+        orders: orders.parse().expect("orders must be an integer"),
+        price: price.clone(),
+    };
+
+    let start = Instant::now();
+    let args = std::sync::Arc::new(args);
+    let tasks: Vec<_> = (1..=sessions)
+        .map(|n| {
+            let args = args.clone();
+            tokio::spawn(async move { session(n, &args).await })
+        })
+        .collect();
+    let mut tally = Tally::default();
+    let mut failed = 0;
+    for (n, task) in tasks.into_iter().enumerate() {
+        match task.await.expect("session panicked") {
+            Ok(session) => tally.add(session),
+            Err(err) => {
+                eprintln!("FLOOD{}: {err}", n + 1);
+                failed += 1;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let answered = tally.latencies.len();
+    tally.latencies.sort();
+    println!(
+        "{answered} orders answered in {:.2}s, {:.0}/s, over {} sessions",
+        elapsed.as_secs_f64(),
+        answered as f64 / elapsed.as_secs_f64(),
+        sessions - failed
+    );
+    println!("accepted  {}", tally.accepted);
+    println!("busy      {}", tally.busy);
+    for (text, n) in &tally.rejected {
+        println!("rejected  {n} ({text})");
+    }
+    println!(
+        "latency   p50 {:?}  p99 {:?}  max {:?}",
+        quantile(&tally.latencies, 0.5),
+        quantile(&tally.latencies, 0.99),
+        quantile(&tally.latencies, 1.0)
+    );
+    if failed > 0 {
+        exit(1);
+    }
+}
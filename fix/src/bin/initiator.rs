@@ -0,0 +1,219 @@
+//! A FIX 4.4 test initiator for the order entry gateway.
+//!
+//! `initiator <addr> <account> <pwd> <code> <1|2> <price> <quantity>` logs on, enters a limit order
+//! (side 1 buys, 2 sells), replaces it with one lot less, cancels what is left, asks for everything
+//! to be resent and logs out. Every message is printed as it goes by.
+
+use fix::{msg_type, tag, timestamp, Message};
+use std::process::exit;
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time;
+
+const SENDER: &str = "INITIATOR";
+const TARGET: &str = "SECURITY_MATCHING";
+
+struct Client {
+    write: OwnedWriteHalf,
+    seq: u64,
+    rx: UnboundedReceiver<Message>,
+}
+
+impl Client {
+    async fn send(&mut self, mut message: Message) {
+        message.stamp(SENDER, TARGET, self.seq, &timestamp());
+        self.seq += 1;
+        println!("-> {message}");
+        self.write.write_all(&message.encode()).await.unwrap();
+    }
+
+    /// Waits for a message matching `f`, answering test requests on the way.
+    async fn expect(&mut self, what: &str, f: impl Fn(&Message) -> bool) -> Message {
+        loop {
+            let message = match time::timeout(Duration::from_secs(10), self.rx.recv()).await {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    eprintln!("disconnected while waiting for {what}");
+                    exit(1);
+                }
+                Err(_) => {
+                    eprintln!("timed out waiting for {what}");
+                    exit(1);
+                }
+            };
+            if message.msg_type() == msg_type::TEST_REQUEST {
+                let id = message
+                    .get(tag::TEST_REQ_ID)
+                    .unwrap_or_default()
+                    .to_string();
+                self.send(Message::new(msg_type::HEARTBEAT).with(tag::TEST_REQ_ID, id))
+                    .await;
+            }
+            if f(&message) {
+                return message;
+            }
+        }
+    }
+
+    /// Waits for the execution report or cancel reject answering `cl_ord_id`.
+    async fn answer(&mut self, cl_ord_id: &str) -> Message {
+        self.expect(cl_ord_id, |message| {
+            matches!(
+                message.msg_type(),
+                msg_type::EXECUTION_REPORT | msg_type::ORDER_CANCEL_REJECT
+            ) && message.get(tag::CL_ORD_ID) == Some(cl_ord_id)
+                && !message.flag(tag::POSS_DUP_FLAG)
+        })
+        .await
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [addr, account, pwd, code, side, price, quantity] = args.as_slice() else {
+        eprintln!("usage: initiator <addr> <account> <pwd> <code> <1|2> <price> <quantity>");
+        exit(2);
+    };
+    let quantity: i64 = quantity.parse().expect("quantity must be an integer");
+
+    let (read, write) = TcpStream::connect(addr.as_str())
+        .await
+        .unwrap()
+        .into_split();
+    let (tx, rx) = unbounded_channel();
+    tokio::spawn(async move {
+        let mut read = BufReader::new(read);
+        while let Ok(Some(frame)) = fix::read_frame(&mut read).await {
+            match Message::decode(&frame) {
+                Ok(message) => {
+                    println!("<- {message}");
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("<- {err}"),
+            }
+        }
+    });
+    let mut client = Client { write, seq: 1, rx };
+
+    client
+        .send(
+            Message::new(msg_type::LOGON)
+                .with(tag::ENCRYPT_METHOD, 0)
+                .with(tag::HEART_BT_INT, 5)
+                .with(tag::RESET_SEQ_NUM_FLAG, "Y")
+                .with(tag::USERNAME, account)
+                .with(tag::PASSWORD, pwd),
+        )
+        .await;
+    let logon = client
+        .expect("Logon", |message| {
+            matches!(message.msg_type(), msg_type::LOGON | msg_type::LOGOUT)
+        })
+        .await;
+    if logon.msg_type() == msg_type::LOGOUT {
+        eprintln!(
+            "logon refused: {}",
+            logon.get(tag::TEXT).unwrap_or_default()
+        );
+        exit(1);
+    }
+
+    let stamp = chrono::Utc::now().timestamp_millis();
+    let (new, replace, cancel) = (
+        format!("{stamp}-N"),
+        format!("{stamp}-R"),
+        format!("{stamp}-C"),
+    );
+    client
+        .send(
+            Message::new(msg_type::NEW_ORDER_SINGLE)
+                .with(tag::CL_ORD_ID, &new)
+                .with(tag::SYMBOL, code)
+                .with(tag::SIDE, side)
+                .with(tag::TRANSACT_TIME, timestamp())
+                .with(tag::ORD_TYPE, 2)
+                .with(tag::PRICE, price)
+                .with(tag::ORDER_QTY, quantity),
+        )
+        .await;
+    let report = client.answer(&new).await;
+    let mut open = (report.get(tag::ORD_STATUS) == Some("0")).then(|| {
+        (
+            new.clone(),
+            report.get(tag::ORDER_ID).unwrap_or_default().to_string(),
+        )
+    });
+
+    if let Some((orig, order_id)) = open.take() {
+        client
+            .send(
+                Message::new(msg_type::ORDER_CANCEL_REPLACE_REQUEST)
+                    .with(tag::ORDER_ID, order_id)
+                    .with(tag::ORIG_CL_ORD_ID, &orig)
+                    .with(tag::CL_ORD_ID, &replace)
+                    .with(tag::SYMBOL, code)
+                    .with(tag::SIDE, side)
+                    .with(tag::TRANSACT_TIME, timestamp())
+                    .with(tag::ORD_TYPE, 2)
+                    .with(tag::PRICE, price)
+                    .with(tag::ORDER_QTY, quantity - 100),
+            )
+            .await;
+        let report = client.answer(&replace).await;
+        open = match report.msg_type() {
+            msg_type::EXECUTION_REPORT if report.get(tag::EXEC_TYPE) == Some("5") => Some((
+                replace.clone(),
+                report.get(tag::ORDER_ID).unwrap_or_default().to_string(),
+            )),
+            _ => Some((
+                orig,
+                report.get(tag::ORDER_ID).unwrap_or_default().to_string(),
+            )),
+        };
+    }
+
+    if let Some((orig, order_id)) = open {
+        client
+            .send(
+                Message::new(msg_type::ORDER_CANCEL_REQUEST)
+                    .with(tag::ORDER_ID, order_id)
+                    .with(tag::ORIG_CL_ORD_ID, orig)
+                    .with(tag::CL_ORD_ID, &cancel)
+                    .with(tag::SYMBOL, code)
+                    .with(tag::SIDE, side)
+                    .with(tag::TRANSACT_TIME, timestamp()),
+            )
+            .await;
+        client.answer(&cancel).await;
+    }
+
+    client
+        .send(
+            Message::new(msg_type::RESEND_REQUEST)
+                .with(tag::BEGIN_SEQ_NO, 1)
+                .with(tag::END_SEQ_NO, 0),
+        )
+        .await;
+    client
+        .expect("resent messages", |message| {
+            message.flag(tag::POSS_DUP_FLAG)
+                && message
+                    .get(tag::MSG_SEQ_NUM)
+                    .and_then(|seq| seq.parse::<u64>().ok())
+                    >= report
+                        .get(tag::MSG_SEQ_NUM)
+                        .and_then(|seq| seq.parse().ok())
+        })
+        .await;
+
+    client.send(Message::new(msg_type::LOGOUT)).await;
+    client
+        .expect("Logout", |message| message.msg_type() == msg_type::LOGOUT)
+        .await;
+}
@@ -0,0 +1,270 @@
+//! A minimal FIX 4.4 tag=value codec shared by the order entry gateway and its test initiator.
+
+use chrono::Utc;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+pub const SOH: u8 = 0x01;
+/// The longest frame read; a counterparty sending more is cut off.
+pub const MAX_FRAME: usize = 64 * 1024;
+
+pub mod tag {
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const ORIG_SENDING_TIME: u32 = 122;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const USERNAME: u32 = 553;
+    pub const PASSWORD: u32 = 554;
+}
+
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+
+    /// Session level messages, which are gap filled rather than resent.
+    pub fn is_admin(msg_type: &str) -> bool {
+        matches!(msg_type, "0" | "1" | "2" | "3" | "4" | "5" | "A")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Garbled(&'static str),
+    BodyLength { declared: usize, actual: usize },
+    CheckSum { declared: u8, actual: u8 },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Garbled(reason) => write!(f, "garbled message: {reason}"),
+            Error::BodyLength { declared, actual } => {
+                write!(f, "body length {declared} declared, {actual} received")
+            }
+            Error::CheckSum { declared, actual } => {
+                write!(f, "checksum {declared:03} declared, {actual:03} computed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The body of a message as ordered tag/value pairs, starting with `MsgType`.
+/// `BeginString`, `BodyLength` and `CheckSum` are added on encoding and dropped on decoding.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Message {
+    pub fields: Vec<(u32, String)>,
+}
+
+/// The `UTCTimestamp` format with milliseconds.
+pub fn timestamp() -> String {
+    Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+impl Message {
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tag::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tag::MSG_TYPE).unwrap_or_default()
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn parse<T: FromStr>(&self, tag: u32) -> Option<T> {
+        self.get(tag).and_then(|value| value.parse().ok())
+    }
+
+    pub fn flag(&self, tag: u32) -> bool {
+        self.get(tag) == Some("Y")
+    }
+
+    /// Inserts the standard header right after `MsgType`, replacing any previous one.
+    pub fn stamp(&mut self, sender: &str, target: &str, seq: u64, sending_time: &str) {
+        self.fields.retain(|(t, _)| {
+            !matches!(
+                *t,
+                tag::SENDER_COMP_ID | tag::TARGET_COMP_ID | tag::MSG_SEQ_NUM | tag::SENDING_TIME
+            )
+        });
+        let at = self.fields.len().min(1);
+        self.fields.splice(
+            at..at,
+            [
+                (tag::SENDER_COMP_ID, sender.to_string()),
+                (tag::TARGET_COMP_ID, target.to_string()),
+                (tag::MSG_SEQ_NUM, seq.to_string()),
+                (tag::SENDING_TIME, sending_time.to_string()),
+            ],
+        );
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (tag, value) in &self.fields {
+            body.extend_from_slice(format!("{tag}={value}").as_bytes());
+            body.push(SOH);
+        }
+        let mut frame = format!("8={BEGIN_STRING}\x019={}\x01", body.len()).into_bytes();
+        frame.append(&mut body);
+        let sum = checksum(&frame);
+        frame.extend_from_slice(format!("10={sum:03}\x01").as_bytes());
+        frame
+    }
+
+    pub fn decode(frame: &[u8]) -> Result<Self, Error> {
+        let fields = frame
+            .strip_suffix(&[SOH])
+            .ok_or(Error::Garbled("missing trailing delimiter"))?
+            .split(|b| *b == SOH)
+            .map(|field| {
+                let field = std::str::from_utf8(field).map_err(|_| Error::Garbled("not utf-8"))?;
+                let (tag, value) = field.split_once('=').ok_or(Error::Garbled("missing '='"))?;
+                let tag = tag.parse().map_err(|_| Error::Garbled("bad tag"))?;
+                Ok((tag, value.to_string()))
+            })
+            .collect::<Result<Vec<(u32, String)>, Error>>()?;
+        match fields.as_slice() {
+            [(tag::BEGIN_STRING, begin), (tag::BODY_LENGTH, length), .., (tag::CHECK_SUM, sum)] => {
+                if begin != BEGIN_STRING {
+                    return Err(Error::Garbled("unsupported BeginString"));
+                }
+                let header = format!("8={begin}\x019={length}\x01").len();
+                let trailer = format!("10={sum}\x01").len();
+                let declared = length
+                    .parse()
+                    .map_err(|_| Error::Garbled("bad BodyLength"))?;
+                let actual = frame.len() - header - trailer;
+                if declared != actual {
+                    return Err(Error::BodyLength { declared, actual });
+                }
+                let declared = sum.parse().map_err(|_| Error::Garbled("bad CheckSum"))?;
+                let actual = checksum(&frame[..frame.len() - trailer]);
+                if declared != actual {
+                    return Err(Error::CheckSum { declared, actual });
+                }
+            }
+            _ => {
+                return Err(Error::Garbled(
+                    "missing BeginString, BodyLength or CheckSum",
+                ))
+            }
+        }
+        let fields: Vec<_> = fields[2..fields.len() - 1].to_vec();
+        if fields.first().map(|(t, _)| *t) != Some(tag::MSG_TYPE) {
+            return Err(Error::Garbled("MsgType must be the third field"));
+        }
+        Ok(Self { fields })
+    }
+}
+
+impl Display for Message {
+    /// Renders the body with `|` as delimiter, for logs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (tag, value) in &self.fields {
+            write!(f, "{tag}={value}|")?;
+        }
+        Ok(())
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Reads one raw frame, up to and including the `CheckSum` field. Returns `None` on a clean end of
+/// stream, and fails on a frame longer than [`MAX_FRAME`].
+pub async fn read_frame<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut frame = Vec::new();
+    loop {
+        let start = frame.len();
+        let room = (MAX_FRAME - start) as u64;
+        if (&mut *reader)
+            .take(room)
+            .read_until(SOH, &mut frame)
+            .await?
+            == 0
+        {
+            return if frame.is_empty() {
+                Ok(None)
+            } else {
+                Err(std::io::ErrorKind::UnexpectedEof.into())
+            };
+        }
+        if frame[start..].starts_with(b"10=") {
+            return Ok(Some(frame));
+        }
+        if frame.len() >= MAX_FRAME {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "frame too long",
+            ));
+        }
+    }
+}
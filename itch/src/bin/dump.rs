@@ -0,0 +1,114 @@
+//! Prints a binary market data feed as it arrives.
+//!
+//! `dump tcp <addr>` reads the TCP stream. `dump udp <group:port> <retransmitter> [from]` joins the
+//! multicast group on loopback and fills every gap from the retransmission service, starting from
+//! feed sequence `from` when given (1 replays as much of the session as the server keeps).
+
+use itch::{Message, Packet, Request};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::process::exit;
+use std::time::Duration;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time;
+
+fn print(seq: u64, message: &[u8]) {
+    match Message::decode(message) {
+        Ok(message) => println!("{seq} {message}"),
+        Err(err) => println!("{seq} <{err}>"),
+    }
+}
+
+/// Asks for `[from, to)` until all of it still kept came back, printing as it goes.
+async fn fill(
+    socket: &UdpSocket,
+    retransmitter: SocketAddr,
+    session: [u8; 10],
+    from: u64,
+    to: u64,
+) {
+    let mut next = from;
+    let mut buf = vec![0; 65536];
+    while next < to {
+        let request = Request {
+            session,
+            seq: next,
+            count: (to - next).min(u16::MAX as u64) as u16,
+        };
+        socket
+            .send_to(&request.encode(), retransmitter)
+            .await
+            .unwrap();
+        let Ok(Ok(len)) = time::timeout(Duration::from_secs(1), socket.recv(&mut buf)).await else {
+            eprintln!("retransmission of {next}.. timed out, retrying");
+            continue;
+        };
+        let Ok(packet) = Packet::decode(&buf[..len]) else {
+            continue;
+        };
+        if packet.seq > next {
+            eprintln!("{next} to {} are no longer kept", packet.seq - 1);
+            next = packet.seq;
+        }
+        if packet.seq != next || packet.is_empty() {
+            continue;
+        }
+        for message in &packet.messages {
+            print(next, message);
+            next += 1;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["tcp", addr] => {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            while let Some(packet) = Packet::read(&mut stream).await.unwrap() {
+                for (i, message) in packet.messages.iter().enumerate() {
+                    print(packet.seq + i as u64, message);
+                }
+            }
+        }
+        ["udp", group, retransmitter, from @ ..] => {
+            let group: SocketAddrV4 = group.parse().expect("group must be ip:port");
+            let retransmitter: SocketAddr = retransmitter
+                .parse()
+                .expect("retransmitter must be ip:port");
+            let mut expected: Option<u64> = from
+                .first()
+                .map(|from| from.parse().expect("from must be a number"));
+            let socket = itch::join(*group.ip(), group.port(), Ipv4Addr::LOCALHOST).unwrap();
+            let requester = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let mut buf = vec![0; 65536];
+            loop {
+                let len = socket.recv(&mut buf).await.unwrap();
+                let Ok(packet) = Packet::decode(&buf[..len]) else {
+                    continue;
+                };
+                let next = expected.get_or_insert(packet.seq);
+                if packet.seq > *next {
+                    fill(&requester, retransmitter, packet.session, *next, packet.seq).await;
+                    *next = packet.seq;
+                }
+                for (i, message) in packet.messages.iter().enumerate() {
+                    let seq = packet.seq + i as u64;
+                    if seq == *next {
+                        print(seq, message);
+                        *next += 1;
+                    }
+                }
+            }
+        }
+        _ => {
+            eprintln!("usage: dump tcp <addr> | dump udp <group:port> <retransmitter> [from]");
+            exit(2);
+        }
+    }
+}
@@ -0,0 +1,654 @@
+//! A compact binary market data protocol in the spirit of NASDAQ ITCH, framed MoldUDP64-style.
+//!
+//! Every message is `type u8, timestamp u64 (ns since the epoch), code [u8; 8] (space padded),
+//! book_seq u64` followed by a fixed body; integers are big-endian and prices carry four implied
+//! decimals. Messages travel in packets of `session [u8; 10], seq u64, count u16` followed by
+//! `count` messages, each prefixed with its `u16` length. `seq` numbers messages across the whole
+//! feed; a packet with no messages is a heartbeat announcing the next `seq`.
+
+use std::fmt::{self, Display};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::net::UdpSocket;
+
+pub const PRICE_SCALE: i64 = 10_000;
+/// Packets are kept under this size so a datagram never fragments.
+pub const MAX_PAYLOAD: usize = 1400;
+pub const HEADER_LEN: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Prepare,
+    Call,
+    Continuous,
+    Halted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Body {
+    /// `A`: an order joined the book.
+    AddOrder {
+        order: u64,
+        side: Side,
+        shares: u64,
+        price: i64,
+    },
+    /// `E`: part or all of a resting order traded.
+    OrderExecuted {
+        order: u64,
+        shares: u64,
+        price: i64,
+        match_number: u64,
+    },
+    /// `X`: part or all of a resting order was taken off the book.
+    OrderCancel { order: u64, shares: u64 },
+    /// `P`: a continuous trade, with the side that took liquidity.
+    Trade {
+        side: Side,
+        shares: u64,
+        price: i64,
+        match_number: u64,
+    },
+    /// `Q`: the uncrossing trade of a call auction.
+    CrossTrade {
+        shares: u64,
+        price: i64,
+        match_number: u64,
+    },
+    /// `I`: where the call auction would uncross now, and what would be left over.
+    Imbalance {
+        paired: u64,
+        imbalance: u64,
+        direction: Option<Side>,
+        price: i64,
+    },
+    /// `H`: the trading phase changed.
+    TradingState { state: State },
+    /// `Z`: the book was rewritten; forget its orders, the current ones follow as `A` messages.
+    BookReset,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub timestamp: u64,
+    pub code: String,
+    pub book_seq: u64,
+    pub body: Body,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    Truncated,
+    UnknownType(u8),
+    Invalid(&'static str),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated => write!(f, "truncated"),
+            Error::UnknownType(t) => write!(f, "unknown message type {:?}", *t as char),
+            Error::Invalid(what) => write!(f, "invalid {what}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let (head, tail) = self.0.split_first_chunk().ok_or(Error::Truncated)?;
+        self.0 = tail;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_be_bytes(self.take()?))
+    }
+
+    /// Fails unless every byte was read.
+    fn end(&self) -> Result<(), Error> {
+        match self.0.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Invalid("length")),
+        }
+    }
+
+    fn side(&mut self) -> Result<Side, Error> {
+        match self.u8()? {
+            b'B' => Ok(Side::Buy),
+            b'S' => Ok(Side::Sell),
+            _ => Err(Error::Invalid("side")),
+        }
+    }
+}
+
+impl Side {
+    fn byte(self) -> u8 {
+        match self {
+            Side::Buy => b'B',
+            Side::Sell => b'S',
+        }
+    }
+}
+
+impl State {
+    fn byte(self) -> u8 {
+        match self {
+            State::Prepare => b'P',
+            State::Call => b'C',
+            State::Continuous => b'T',
+            State::Halted => b'H',
+        }
+    }
+}
+
+impl Message {
+    /// Codes longer than eight bytes are cut short.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(64);
+        buf.push(match self.body {
+            Body::AddOrder { .. } => b'A',
+            Body::OrderExecuted { .. } => b'E',
+            Body::OrderCancel { .. } => b'X',
+            Body::Trade { .. } => b'P',
+            Body::CrossTrade { .. } => b'Q',
+            Body::Imbalance { .. } => b'I',
+            Body::TradingState { .. } => b'H',
+            Body::BookReset => b'Z',
+        });
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        let mut code = [b' '; 8];
+        let len = self.code.len().min(8);
+        code[..len].copy_from_slice(&self.code.as_bytes()[..len]);
+        buf.extend_from_slice(&code);
+        buf.extend_from_slice(&self.book_seq.to_be_bytes());
+        match self.body {
+            Body::AddOrder {
+                order,
+                side,
+                shares,
+                price,
+            } => {
+                buf.extend_from_slice(&order.to_be_bytes());
+                buf.push(side.byte());
+                buf.extend_from_slice(&shares.to_be_bytes());
+                buf.extend_from_slice(&price.to_be_bytes());
+            }
+            Body::OrderExecuted {
+                order,
+                shares,
+                price,
+                match_number,
+            } => {
+                buf.extend_from_slice(&order.to_be_bytes());
+                buf.extend_from_slice(&shares.to_be_bytes());
+                buf.extend_from_slice(&price.to_be_bytes());
+                buf.extend_from_slice(&match_number.to_be_bytes());
+            }
+            Body::OrderCancel { order, shares } => {
+                buf.extend_from_slice(&order.to_be_bytes());
+                buf.extend_from_slice(&shares.to_be_bytes());
+            }
+            Body::Trade {
+                side,
+                shares,
+                price,
+                match_number,
+            } => {
+                buf.push(side.byte());
+                buf.extend_from_slice(&shares.to_be_bytes());
+                buf.extend_from_slice(&price.to_be_bytes());
+                buf.extend_from_slice(&match_number.to_be_bytes());
+            }
+            Body::CrossTrade {
+                shares,
+                price,
+                match_number,
+            } => {
+                buf.extend_from_slice(&shares.to_be_bytes());
+                buf.extend_from_slice(&price.to_be_bytes());
+                buf.extend_from_slice(&match_number.to_be_bytes());
+            }
+            Body::Imbalance {
+                paired,
+                imbalance,
+                direction,
+                price,
+            } => {
+                buf.extend_from_slice(&paired.to_be_bytes());
+                buf.extend_from_slice(&imbalance.to_be_bytes());
+                buf.push(direction.map_or(b'N', Side::byte));
+                buf.extend_from_slice(&price.to_be_bytes());
+            }
+            Body::TradingState { state } => buf.push(state.byte()),
+            Body::BookReset => {}
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader(buf);
+        let kind = r.u8()?;
+        let timestamp = r.u64()?;
+        let code = r.take::<8>()?;
+        let code = std::str::from_utf8(&code)
+            .map_err(|_| Error::Invalid("code"))?
+            .trim_end()
+            .to_string();
+        let book_seq = r.u64()?;
+        let body = match kind {
+            b'A' => Body::AddOrder {
+                order: r.u64()?,
+                side: r.side()?,
+                shares: r.u64()?,
+                price: r.i64()?,
+            },
+            b'E' => Body::OrderExecuted {
+                order: r.u64()?,
+                shares: r.u64()?,
+                price: r.i64()?,
+                match_number: r.u64()?,
+            },
+            b'X' => Body::OrderCancel {
+                order: r.u64()?,
+                shares: r.u64()?,
+            },
+            b'P' => Body::Trade {
+                side: r.side()?,
+                shares: r.u64()?,
+                price: r.i64()?,
+                match_number: r.u64()?,
+            },
+            b'Q' => Body::CrossTrade {
+                shares: r.u64()?,
+                price: r.i64()?,
+                match_number: r.u64()?,
+            },
+            b'I' => Body::Imbalance {
+                paired: r.u64()?,
+                imbalance: r.u64()?,
+                direction: match r.u8()? {
+                    b'B' => Some(Side::Buy),
+                    b'S' => Some(Side::Sell),
+                    b'N' => None,
+                    _ => return Err(Error::Invalid("imbalance direction")),
+                },
+                price: r.i64()?,
+            },
+            b'H' => Body::TradingState {
+                state: match r.u8()? {
+                    b'P' => State::Prepare,
+                    b'C' => State::Call,
+                    b'T' => State::Continuous,
+                    b'H' => State::Halted,
+                    _ => return Err(Error::Invalid("trading state")),
+                },
+            },
+            b'Z' => Body::BookReset,
+            kind => return Err(Error::UnknownType(kind)),
+        };
+        r.end()?;
+        Ok(Self {
+            timestamp,
+            code,
+            book_seq,
+            body,
+        })
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}#{} {:?}",
+            self.timestamp, self.code, self.book_seq, self.body
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub session: [u8; 10],
+    /// The feed sequence number of the first message, or of the next one for a heartbeat.
+    pub seq: u64,
+    /// Encoded messages; decode each with [`Message::decode`].
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl Packet {
+    pub fn len(&self) -> usize {
+        HEADER_LEN + self.messages.iter().map(|m| 2 + m.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.len());
+        buf.extend_from_slice(&self.session);
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&(self.messages.len() as u16).to_be_bytes());
+        for message in &self.messages {
+            buf.extend_from_slice(&(message.len() as u16).to_be_bytes());
+            buf.extend_from_slice(message);
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader(buf);
+        let session = r.take()?;
+        let seq = r.u64()?;
+        let count = r.u16()?;
+        let mut messages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = r.u16()? as usize;
+            if r.0.len() < len {
+                return Err(Error::Truncated);
+            }
+            let (message, rest) = r.0.split_at(len);
+            messages.push(message.to_vec());
+            r.0 = rest;
+        }
+        r.end()?;
+        Ok(Self {
+            session,
+            seq,
+            messages,
+        })
+    }
+
+    /// Reads one packet off a stream. Returns `None` on a clean end of stream.
+    pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut header = [0; HEADER_LEN];
+        match reader.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let Request {
+            session,
+            seq,
+            count,
+        } = Request::decode(&header)?;
+        let mut messages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = reader.read_u16().await? as usize;
+            let mut message = vec![0; len];
+            reader.read_exact(&mut message).await?;
+            messages.push(message);
+        }
+        Ok(Some(Self {
+            session,
+            seq,
+            messages,
+        }))
+    }
+}
+
+/// Asks the retransmission service for `count` messages starting at `seq`.
+/// The answer is a packet holding as many of them as fit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub session: [u8; 10],
+    pub seq: u64,
+    pub count: u16,
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN);
+        buf.extend_from_slice(&self.session);
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+        buf.extend_from_slice(&self.count.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader(buf);
+        let request = Self {
+            session: r.take()?,
+            seq: r.u64()?,
+            count: r.u16()?,
+        };
+        r.end()?;
+        Ok(request)
+    }
+}
+
+/// A socket sending to multicast groups through `interface`, looping packets back to local receivers.
+pub fn sender(interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    socket.set_multicast_if_v4(&interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV4::new(interface, 0).into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// A socket receiving `group:port` on `interface`; several receivers may share the port.
+pub fn join(group: Ipv4Addr, port: u16, interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port).into())?;
+    socket.join_multicast_v4(&group, &interface)?;
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: [u8; 10] = *b"SESSION001";
+
+    /// One message of each type.
+    fn messages() -> Vec<Message> {
+        [
+            Body::AddOrder {
+                order: 1,
+                side: Side::Buy,
+                shares: 100,
+                price: 10 * PRICE_SCALE,
+            },
+            Body::OrderExecuted {
+                order: 1,
+                shares: 40,
+                price: 10 * PRICE_SCALE,
+                match_number: 7,
+            },
+            Body::OrderCancel {
+                order: 1,
+                shares: 60,
+            },
+            Body::Trade {
+                side: Side::Sell,
+                shares: 200,
+                price: 99_995,
+                match_number: 8,
+            },
+            Body::CrossTrade {
+                shares: 300,
+                price: -1,
+                match_number: u64::MAX,
+            },
+            Body::Imbalance {
+                paired: 500,
+                imbalance: 0,
+                direction: None,
+                price: 10 * PRICE_SCALE,
+            },
+            Body::Imbalance {
+                paired: 500,
+                imbalance: 100,
+                direction: Some(Side::Buy),
+                price: 10 * PRICE_SCALE,
+            },
+            Body::TradingState {
+                state: State::Continuous,
+            },
+            Body::TradingState {
+                state: State::Halted,
+            },
+            Body::BookReset,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, body)| Message {
+            timestamp: 1_700_000_000_000_000_000 + i as u64,
+            code: "600000".to_owned(),
+            book_seq: i as u64 + 1,
+            body,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn round_trips_every_message_type() {
+        for message in messages() {
+            let buf = message.encode();
+            assert_eq!(Message::decode(&buf), Ok(message.clone()), "{message}");
+            for len in 0..buf.len() {
+                assert_eq!(Message::decode(&buf[..len]), Err(Error::Truncated));
+            }
+            let mut longer = buf.clone();
+            longer.push(0);
+            assert_eq!(Message::decode(&longer), Err(Error::Invalid("length")));
+        }
+    }
+
+    #[test]
+    fn cuts_codes_to_eight_bytes() {
+        let message = Message {
+            code: "ABCDEFGHIJ".to_owned(),
+            ..messages().remove(0)
+        };
+        let decoded = Message::decode(&message.encode()).unwrap();
+        assert_eq!(decoded.code, "ABCDEFGH");
+    }
+
+    #[test]
+    fn refuses_unknown_types_and_values() {
+        let mut buf = messages().remove(0).encode();
+        buf[0] = b'?';
+        assert_eq!(Message::decode(&buf), Err(Error::UnknownType(b'?')));
+        // The side of an `A` message follows the common fields and its order number.
+        buf[0] = b'A';
+        buf[1 + 8 + 8 + 8 + 8] = b'?';
+        assert_eq!(Message::decode(&buf), Err(Error::Invalid("side")));
+    }
+
+    #[test]
+    fn round_trips_packets() {
+        let messages: Vec<_> = messages().iter().map(Message::encode).collect();
+        for packet in [
+            Packet {
+                session: SESSION,
+                seq: 42,
+                messages: messages.clone(),
+            },
+            // A heartbeat.
+            Packet {
+                session: SESSION,
+                seq: 52,
+                messages: Vec::new(),
+            },
+        ] {
+            let buf = packet.encode();
+            assert_eq!(buf.len(), packet.len());
+            assert_eq!(Packet::decode(&buf), Ok(packet.clone()));
+            for len in 0..buf.len() {
+                assert_eq!(Packet::decode(&buf[..len]), Err(Error::Truncated));
+            }
+            let mut longer = buf.clone();
+            longer.extend_from_slice(&[0, 0]);
+            assert_eq!(Packet::decode(&longer), Err(Error::Invalid("length")));
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_packets_off_a_stream() {
+        let packets = [
+            Packet {
+                session: SESSION,
+                seq: 1,
+                messages: messages().iter().map(Message::encode).collect(),
+            },
+            Packet {
+                session: SESSION,
+                seq: 11,
+                messages: Vec::new(),
+            },
+        ];
+        let buf: Vec<u8> = packets.iter().flat_map(Packet::encode).collect();
+        let mut reader = buf.as_slice();
+        for packet in &packets {
+            assert_eq!(
+                Packet::read(&mut reader).await.unwrap().as_ref(),
+                Some(packet)
+            );
+        }
+        assert_eq!(Packet::read(&mut reader).await.unwrap(), None);
+
+        // Cut off within the first message.
+        let mut torn = &buf[..HEADER_LEN + 3];
+        assert!(Packet::read(&mut torn).await.is_err());
+    }
+
+    #[test]
+    fn round_trips_requests() {
+        let request = Request {
+            session: SESSION,
+            seq: u64::MAX - 1,
+            count: 100,
+        };
+        let buf = request.encode();
+        assert_eq!(buf.len(), HEADER_LEN);
+        assert_eq!(Request::decode(&buf), Ok(request));
+        assert_eq!(
+            Request::decode(&buf[..HEADER_LEN - 1]),
+            Err(Error::Truncated)
+        );
+        let mut longer = buf.clone();
+        longer.push(0);
+        assert_eq!(Request::decode(&longer), Err(Error::Invalid("length")));
+    }
+}
@@ -1,12 +1,37 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20261019_000002_alter_ac;
+mod m20261019_000003_alter_security;
+mod m20261019_000004_create_corp_action;
+mod m20261019_000005_create_candle;
+mod m20261019_000006_alter_req;
+mod m20261019_000007_alter_msg;
+mod m20261019_000008_create_order_history;
+mod m20261019_000009_alter_rec;
+mod m20261019_000010_create_projection;
+mod m20261019_000011_alter_projection;
+mod m20261019_000012_alter_msg;
+mod portable;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20261019_000002_alter_ac::Migration),
+            Box::new(m20261019_000003_alter_security::Migration),
+            Box::new(m20261019_000004_create_corp_action::Migration),
+            Box::new(m20261019_000005_create_candle::Migration),
+            Box::new(m20261019_000006_alter_req::Migration),
+            Box::new(m20261019_000007_alter_msg::Migration),
+            Box::new(m20261019_000008_create_order_history::Migration),
+            Box::new(m20261019_000009_alter_rec::Migration),
+            Box::new(m20261019_000010_create_projection::Migration),
+            Box::new(m20261019_000011_alter_projection::Migration),
+            Box::new(m20261019_000012_alter_msg::Migration),
+        ]
     }
 }
@@ -1,7 +1,7 @@
 use sea_orm_migration::prelude::*;
-use sea_orm_migration::sea_orm::{
-    ActiveEnum, DbBackend, DeriveActiveEnum, EnumIter, Iterable, Schema,
-};
+use sea_orm_migration::sea_orm::{ActiveEnum, DeriveActiveEnum, EnumIter, Iterable};
+
+use crate::portable;
 
 #[derive(DeriveMigrationName)]
 pub struct Migration;
@@ -62,9 +62,7 @@ impl MigrationTrait for Migration {
                     .to_owned(),
             )
             .await?;
-        manager
-            .create_type(Schema::new(DbBackend::Postgres).create_enum_from_active_enum::<Dir>())
-            .await?;
+        portable::create_enum::<Dir>(manager).await?;
         manager
             .create_table(
                 Table::create()
@@ -87,7 +85,7 @@ impl MigrationTrait for Migration {
                             .enumeration(Dir::name(), Dir::iter())
                             .not_null(),
                     )
-                    .col(ColumnDef::new(Order::Price).decimal_len(1000, 2).not_null())
+                    .col(portable::decimal(manager, Order::Price, 1000, 2).not_null())
                     .col(ColumnDef::new(Order::Quantity).big_integer().not_null())
                     .to_owned(),
             )
@@ -121,7 +119,7 @@ impl MigrationTrait for Migration {
                             .from(Rec::Table, Rec::SellerId)
                             .to(Ac::Table, Ac::Id),
                     )
-                    .col(ColumnDef::new(Rec::Price).decimal_len(1000, 2).not_null())
+                    .col(portable::decimal(manager, Rec::Price, 1000, 2).not_null())
                     .col(ColumnDef::new(Rec::Quantity).big_integer().not_null())
                     .col(
                         ColumnDef::new(Rec::CreatedAt)
@@ -181,6 +179,7 @@ impl MigrationTrait for Migration {
         manager
             .drop_table(Table::drop().table(Ac::Table).to_owned())
             .await?;
+        portable::drop_enum::<Dir>(manager).await?;
         Ok(())
     }
 }
@@ -0,0 +1,67 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ActiveEnum, DeriveActiveEnum, EnumIter, Iterable};
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        portable::create_enum::<AcStatus>(manager).await?;
+        portable::add_columns(
+            manager,
+            Ac::Table,
+            [
+                ColumnDef::new(Ac::Status)
+                    .enumeration(AcStatus::name(), AcStatus::iter())
+                    .not_null()
+                    .default("Active")
+                    .to_owned(),
+                ColumnDef::new(Ac::CreatedAt)
+                    .timestamp_with_time_zone()
+                    .not_null()
+                    .default(portable::now(manager))
+                    .to_owned(),
+                ColumnDef::new(Ac::UpdatedAt)
+                    .timestamp_with_time_zone()
+                    .not_null()
+                    .default(portable::now(manager))
+                    .to_owned(),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        portable::drop_columns(
+            manager,
+            Ac::Table,
+            [Ac::Status, Ac::CreatedAt, Ac::UpdatedAt],
+        )
+        .await?;
+        portable::drop_enum::<AcStatus>(manager).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Ac {
+    Table,
+    Status,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "ac_status")]
+enum AcStatus {
+    #[sea_orm(string_value = "Active")]
+    Active,
+    #[sea_orm(string_value = "Suspended")]
+    Suspended,
+    #[sea_orm(string_value = "Closed")]
+    Closed,
+}
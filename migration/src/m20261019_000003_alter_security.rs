@@ -0,0 +1,97 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ActiveEnum, DeriveActiveEnum, EnumIter, Iterable};
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        portable::create_enum::<SecurityStatus>(manager).await?;
+        portable::add_columns(
+            manager,
+            Security::Table,
+            [
+                ColumnDef::new(Security::Currency)
+                    .string()
+                    .not_null()
+                    .default("CNY")
+                    .to_owned(),
+                portable::decimal(manager, Security::TickSize, 1000, 2)
+                    .not_null()
+                    .default(Expr::val("0.01"))
+                    .to_owned(),
+                ColumnDef::new(Security::LotSize)
+                    .big_integer()
+                    .not_null()
+                    .default(100)
+                    .to_owned(),
+                portable::decimal(manager, Security::RefPrice, 1000, 2).to_owned(),
+                portable::decimal(manager, Security::PriceBand, 6, 4).to_owned(),
+                ColumnDef::new(Security::Segment)
+                    .string()
+                    .not_null()
+                    .default("Main")
+                    .to_owned(),
+                ColumnDef::new(Security::Status)
+                    .enumeration(SecurityStatus::name(), SecurityStatus::iter())
+                    .not_null()
+                    .default("Listed")
+                    .to_owned(),
+                ColumnDef::new(Security::UpdatedAt)
+                    .timestamp_with_time_zone()
+                    .not_null()
+                    .default(portable::now(manager))
+                    .to_owned(),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        portable::drop_columns(
+            manager,
+            Security::Table,
+            [
+                Security::Currency,
+                Security::TickSize,
+                Security::LotSize,
+                Security::RefPrice,
+                Security::PriceBand,
+                Security::Segment,
+                Security::Status,
+                Security::UpdatedAt,
+            ],
+        )
+        .await?;
+        portable::drop_enum::<SecurityStatus>(manager).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Security {
+    Table,
+    Currency,
+    TickSize,
+    LotSize,
+    RefPrice,
+    PriceBand,
+    Segment,
+    Status,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "security_status")]
+enum SecurityStatus {
+    #[sea_orm(string_value = "Listed")]
+    Listed,
+    #[sea_orm(string_value = "Suspended")]
+    Suspended,
+    #[sea_orm(string_value = "Delisted")]
+    Delisted,
+}
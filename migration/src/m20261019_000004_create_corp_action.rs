@@ -0,0 +1,164 @@
+use sea_orm_migration::prelude::*;
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Position::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Position::Id).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Position::Table, Position::Id)
+                            .to(Ac::Table, Ac::Id),
+                    )
+                    .col(ColumnDef::new(Position::Code).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Position::Table, Position::Code)
+                            .to(Security::Table, Security::Code),
+                    )
+                    .col(ColumnDef::new(Position::Quantity).big_integer().not_null())
+                    .primary_key(Index::create().col(Position::Id).col(Position::Code))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"INSERT INTO "position" ("id", "code", "quantity")
+                SELECT "id", "code", SUM("quantity") FROM (
+                    SELECT "buyer_id" AS "id", "code", "quantity" FROM "rec"
+                    UNION ALL
+                    SELECT "seller_id" AS "id", "code", -"quantity" FROM "rec"
+                ) AS "t" GROUP BY "id", "code""#,
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(CorpAction::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CorpAction::Seq)
+                            .big_integer()
+                            .primary_key()
+                            .auto_increment(),
+                    )
+                    .col(ColumnDef::new(CorpAction::Code).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(CorpAction::Table, CorpAction::Code)
+                            .to(Security::Table, Security::Code),
+                    )
+                    .col(ColumnDef::new(CorpAction::Action).json().not_null())
+                    .col(
+                        ColumnDef::new(CorpAction::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Entitlement::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Entitlement::Ack)
+                            .big_integer()
+                            .primary_key()
+                            .auto_increment(),
+                    )
+                    .col(ColumnDef::new(Entitlement::Seq).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Entitlement::Table, Entitlement::Seq)
+                            .to(CorpAction::Table, CorpAction::Seq),
+                    )
+                    .col(ColumnDef::new(Entitlement::Id).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Entitlement::Table, Entitlement::Id)
+                            .to(Ac::Table, Ac::Id),
+                    )
+                    .col(ColumnDef::new(Entitlement::Code).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Entitlement::Table, Entitlement::Code)
+                            .to(Security::Table, Security::Code),
+                    )
+                    .col(
+                        ColumnDef::new(Entitlement::Quantity)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(portable::decimal(manager, Entitlement::Amount, 1000, 2).not_null())
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Entitlement::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(CorpAction::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Position::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Ac {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Security {
+    Table,
+    Code,
+}
+
+#[derive(DeriveIden)]
+enum Position {
+    Table,
+    Id,
+    Code,
+    Quantity,
+}
+
+#[derive(DeriveIden)]
+enum CorpAction {
+    Table,
+    Seq,
+    Code,
+    Action,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Entitlement {
+    Table,
+    Ack,
+    Seq,
+    Id,
+    Code,
+    Quantity,
+    Amount,
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::prelude::*;
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Candle::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Candle::Code).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Candle::Table, Candle::Code)
+                            .to(Security::Table, Security::Code),
+                    )
+                    .col(ColumnDef::new(Candle::Span).big_integer().not_null())
+                    .col(
+                        ColumnDef::new(Candle::Start)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(portable::decimal(manager, Candle::Open, 1000, 2).not_null())
+                    .col(portable::decimal(manager, Candle::High, 1000, 2).not_null())
+                    .col(portable::decimal(manager, Candle::Low, 1000, 2).not_null())
+                    .col(portable::decimal(manager, Candle::Close, 1000, 2).not_null())
+                    .col(ColumnDef::new(Candle::Volume).big_integer().not_null())
+                    .col(portable::decimal(manager, Candle::Turnover, 1000, 2).not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(Candle::Code)
+                            .col(Candle::Span)
+                            .col(Candle::Start),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Candle::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Security {
+    Table,
+    Code,
+}
+
+#[derive(DeriveIden)]
+enum Candle {
+    Table,
+    Code,
+    Span,
+    Start,
+    Open,
+    High,
+    Low,
+    Close,
+    Volume,
+    Turnover,
+}
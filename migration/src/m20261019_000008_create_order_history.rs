@@ -0,0 +1,157 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ActiveEnum, DeriveActiveEnum, EnumIter, Iterable};
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        portable::create_enum::<OrdStatus>(manager).await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(OrderHistory::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(OrderHistory::Seq)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(OrderHistory::Table, OrderHistory::Seq)
+                            .to(Req::Table, Req::Seq),
+                    )
+                    .col(ColumnDef::new(OrderHistory::Id).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(OrderHistory::Table, OrderHistory::Id)
+                            .to(Ac::Table, Ac::Id),
+                    )
+                    .col(ColumnDef::new(OrderHistory::ClOrdId).string())
+                    .col(ColumnDef::new(OrderHistory::Code).string().not_null())
+                    .col(
+                        ColumnDef::new(OrderHistory::Dir)
+                            .enumeration(Dir::name(), Dir::iter())
+                            .not_null(),
+                    )
+                    .col(portable::decimal(manager, OrderHistory::Price, 1000, 2).not_null())
+                    .col(
+                        ColumnDef::new(OrderHistory::Quantity)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrderHistory::CumQuantity)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(portable::decimal(manager, OrderHistory::AvgPrice, 1000, 4).not_null())
+                    .col(
+                        ColumnDef::new(OrderHistory::Status)
+                            .enumeration(OrdStatus::name(), OrdStatus::iter())
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrderHistory::ReportSeq)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(OrderHistory::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(OrderHistory::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-order_history-id-created_at")
+                    .table(OrderHistory::Table)
+                    .col(OrderHistory::Id)
+                    .col(OrderHistory::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OrderHistory::Table).to_owned())
+            .await?;
+        portable::drop_enum::<OrdStatus>(manager).await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Ac {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Req {
+    Table,
+    Seq,
+}
+
+#[derive(DeriveIden)]
+enum OrderHistory {
+    Table,
+    Seq,
+    Id,
+    ClOrdId,
+    Code,
+    Dir,
+    Price,
+    Quantity,
+    CumQuantity,
+    AvgPrice,
+    Status,
+    ReportSeq,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "dir")]
+enum Dir {
+    #[sea_orm(string_value = "Buy")]
+    Buy,
+    #[sea_orm(string_value = "Sell")]
+    Sell,
+}
+
+#[derive(DeriveIden, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "ord_status")]
+enum OrdStatus {
+    #[sea_orm(string_value = "New")]
+    New,
+    #[sea_orm(string_value = "PartiallyFilled")]
+    PartiallyFilled,
+    #[sea_orm(string_value = "Filled")]
+    Filled,
+    #[sea_orm(string_value = "Canceled")]
+    Canceled,
+    #[sea_orm(string_value = "Expired")]
+    Expired,
+    #[sea_orm(string_value = "Rejected")]
+    Rejected,
+    #[sea_orm(string_value = "Replaced")]
+    Replaced,
+}
@@ -0,0 +1,107 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ActiveEnum, DeriveActiveEnum, EnumIter, Iterable};
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Trades booked before this migration keep null seqs and aggressor.
+        portable::add_columns(
+            manager,
+            Rec::Table,
+            [
+                ColumnDef::new(Rec::SeqBid).big_integer().to_owned(),
+                ColumnDef::new(Rec::SeqOffer).big_integer().to_owned(),
+                ColumnDef::new(Rec::Aggressor)
+                    .enumeration(Dir::name(), Dir::iter())
+                    .to_owned(),
+                ColumnDef::new(Rec::Auction)
+                    .boolean()
+                    .not_null()
+                    .default(false)
+                    .to_owned(),
+            ],
+        )
+        .await?;
+        portable::add_foreign_key(
+            manager,
+            ForeignKey::create()
+                .name("fk-rec-seq_bid")
+                .from(Rec::Table, Rec::SeqBid)
+                .to(Req::Table, Req::Seq)
+                .to_owned(),
+        )
+        .await?;
+        portable::add_foreign_key(
+            manager,
+            ForeignKey::create()
+                .name("fk-rec-seq_offer")
+                .from(Rec::Table, Rec::SeqOffer)
+                .to(Req::Table, Req::Seq)
+                .to_owned(),
+        )
+        .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-rec-seq_bid")
+                    .table(Rec::Table)
+                    .col(Rec::SeqBid)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-rec-seq_offer")
+                    .table(Rec::Table)
+                    .col(Rec::SeqOffer)
+                    .to_owned(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for name in ["idx-rec-seq_bid", "idx-rec-seq_offer"] {
+            manager
+                .drop_index(Index::drop().name(name).table(Rec::Table).to_owned())
+                .await?;
+        }
+        portable::drop_columns(
+            manager,
+            Rec::Table,
+            [Rec::SeqBid, Rec::SeqOffer, Rec::Aggressor, Rec::Auction],
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Req {
+    Table,
+    Seq,
+}
+
+#[derive(DeriveIden)]
+enum Rec {
+    Table,
+    SeqBid,
+    SeqOffer,
+    Aggressor,
+    Auction,
+}
+
+#[derive(DeriveIden, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "dir")]
+enum Dir {
+    #[sea_orm(string_value = "Buy")]
+    Buy,
+    #[sea_orm(string_value = "Sell")]
+    Sell,
+}
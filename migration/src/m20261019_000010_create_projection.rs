@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // How far into the journal the tables are; nothing yet means they predate it.
+        manager
+            .create_table(
+                Table::create()
+                    .table(Projection::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Projection::Name)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Projection::Seq).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Projection::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Projection {
+    Table,
+    Name,
+    Seq,
+}
@@ -0,0 +1,29 @@
+use sea_orm_migration::prelude::*;
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The phase of the market as of the projected seq; null until a phase is projected.
+        portable::add_columns(
+            manager,
+            Projection::Table,
+            [ColumnDef::new(Projection::Phase).string().to_owned()],
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        portable::drop_columns(manager, Projection::Table, [Projection::Phase]).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Projection {
+    Table,
+    Phase,
+}
@@ -0,0 +1,51 @@
+use sea_orm_migration::prelude::*;
+
+use crate::portable;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // When the account acknowledged the message; null until it does.
+        portable::add_columns(
+            manager,
+            Msg::Table,
+            [ColumnDef::new(Msg::AckedAt)
+                .timestamp_with_time_zone()
+                .to_owned()],
+        )
+        .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-msg-id-ack")
+                    .table(Msg::Table)
+                    .col(Msg::Id)
+                    .col(Msg::Ack)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-msg-id-ack")
+                    .table(Msg::Table)
+                    .to_owned(),
+            )
+            .await?;
+        portable::drop_columns(manager, Msg::Table, [Msg::AckedAt]).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Msg {
+    Table,
+    Id,
+    Ack,
+    AckedAt,
+}
@@ -0,0 +1,107 @@
+//! Schema changes that read differently depending on the backend migrated.
+
+use sea_orm_migration::prelude::extension::postgres::Type;
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ActiveEnum, DbBackend, Schema};
+
+/// Creates the type of enum `E` where enums are types of their own. Elsewhere an enum column
+/// is text.
+pub async fn create_enum<E: ActiveEnum>(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    match manager.get_database_backend() {
+        DbBackend::Postgres => {
+            manager
+                .create_type(Schema::new(DbBackend::Postgres).create_enum_from_active_enum::<E>())
+                .await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Drops what `create_enum` created.
+pub async fn drop_enum<E: ActiveEnum>(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    match manager.get_database_backend() {
+        DbBackend::Postgres => {
+            manager
+                .drop_type(Type::drop().name(E::name()).to_owned())
+                .await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Adds columns to `table` one statement at a time, as SQLite alters only one thing at once.
+pub async fn add_columns(
+    manager: &SchemaManager<'_>,
+    table: impl IntoIden,
+    columns: impl IntoIterator<Item = ColumnDef>,
+) -> Result<(), DbErr> {
+    let table = table.into_iden();
+    for column in columns {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(table.clone())
+                    .add_column(column)
+                    .to_owned(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Drops columns of `table` one statement at a time.
+pub async fn drop_columns(
+    manager: &SchemaManager<'_>,
+    table: impl IntoIden,
+    columns: impl IntoIterator<Item = impl IntoIden>,
+) -> Result<(), DbErr> {
+    let table = table.into_iden();
+    for column in columns {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(table.clone())
+                    .drop_column(column)
+                    .to_owned(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Adds a foreign key to an existing table. SQLite only takes foreign keys when a table is
+/// created, so there the column goes unchecked.
+pub async fn add_foreign_key(
+    manager: &SchemaManager<'_>,
+    foreign_key: ForeignKeyCreateStatement,
+) -> Result<(), DbErr> {
+    match manager.get_database_backend() {
+        DbBackend::Sqlite => Ok(()),
+        _ => manager.create_foreign_key(foreign_key).await,
+    }
+}
+
+/// The default of a timestamp added to a table with rows in it. SQLite adds columns only with
+/// a constant default, so there rows already present read as the epoch.
+pub fn now(manager: &SchemaManager<'_>) -> SimpleExpr {
+    match manager.get_database_backend() {
+        DbBackend::Sqlite => Expr::val("1970-01-01T00:00:00+00:00").into(),
+        _ => Expr::current_timestamp().into(),
+    }
+}
+
+/// A decimal column. SQLite keeps decimals as reals and takes at most 16 digits of precision.
+pub fn decimal(
+    manager: &SchemaManager<'_>,
+    name: impl IntoIden,
+    precision: u32,
+    scale: u32,
+) -> ColumnDef {
+    let precision = match manager.get_database_backend() {
+        DbBackend::Sqlite => precision.min(16),
+        _ => precision,
+    };
+    ColumnDef::new(name)
+        .decimal_len(precision, scale)
+        .to_owned()
+}
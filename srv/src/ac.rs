@@ -0,0 +1,177 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::{routing, Router};
+use chrono::Utc;
+use entity::ac;
+use entity::sea_orm_active_enums::AcStatus;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct Register {
+    pub name: String,
+    pub pwd: String,
+}
+
+#[derive(Deserialize)]
+pub struct Update {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePwd {
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Deserialize)]
+pub struct Filter {
+    pub status: Option<AcStatus>,
+}
+
+fn hash(pwd: &str) -> Result<String, Error> {
+    Ok(Argon2::default()
+        .hash_password(pwd.as_bytes(), &SaltString::generate(&mut OsRng))?
+        .to_string())
+}
+
+/// Whether `pwd` is the password of account `model`. Accounts inserted directly into Postgres
+/// before this API existed keep a plain `pwd`, hashed the first time it is given right.
+pub async fn verify(state: &AppState, model: &ac::Model, pwd: &str) -> bool {
+    match PasswordHash::new(&model.pwd) {
+        Ok(hash) => Argon2::default()
+            .verify_password(pwd.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) if model.pwd != pwd => false,
+        Err(_) => {
+            match rehash(state, model, pwd).await {
+                Ok(()) => tracing::info!("account {}: plain password hashed", model.id),
+                Err(err) => tracing::warn!(
+                    "account {}: plain password not hashed: {}",
+                    model.id,
+                    err.detail()
+                ),
+            }
+            true
+        }
+    }
+}
+
+async fn rehash(state: &AppState, model: &ac::Model, pwd: &str) -> Result<(), Error> {
+    let mut model = model.clone().into_active_model();
+    model.pwd = ActiveValue::Set(hash(pwd)?);
+    model.update(&state.db).await?;
+    Ok(())
+}
+
+async fn register(
+    State(state): State<AppState>,
+    Json(form): Json<Register>,
+) -> Result<(StatusCode, Json<ac::Model>), Error> {
+    let now = Utc::now().fixed_offset();
+    let model = ac::ActiveModel {
+        pwd: ActiveValue::Set(hash(&form.pwd)?),
+        name: ActiveValue::Set(form.name),
+        status: ActiveValue::Set(AcStatus::Active),
+        created_at: ActiveValue::Set(now),
+        updated_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+async fn list(
+    State(state): State<AppState>,
+    Query(filter): Query<Filter>,
+) -> Result<Json<Vec<ac::Model>>, Error> {
+    let mut query = ac::Entity::find().order_by_asc(ac::Column::Id);
+    if let Some(status) = filter.status {
+        query = query.filter(ac::Column::Status.eq(status));
+    }
+    Ok(Json(query.all(&state.db).await?))
+}
+
+/// The account `id`, unless it was closed.
+async fn open(state: &AppState, id: i64) -> Result<ac::Model, Error> {
+    match state.account(id).await? {
+        model if model.status == AcStatus::Closed => Err(Error::Forbidden("account is closed")),
+        model => Ok(model),
+    }
+}
+
+async fn get(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Json<ac::Model>, Error> {
+    Ok(Json(state.account(id).await?))
+}
+
+async fn update(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(form): Json<Update>,
+) -> Result<Json<ac::Model>, Error> {
+    let mut model = open(&state, id).await?.into_active_model();
+    model.name = ActiveValue::Set(form.name);
+    model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+    Ok(Json(model.update(&state.db).await?))
+}
+
+async fn change_pwd(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(form): Json<ChangePwd>,
+) -> Result<StatusCode, Error> {
+    let model = open(&state, id).await?;
+    if !verify(&state, &model, &form.old).await {
+        return Err(Error::Unauthorized);
+    }
+    let mut model = model.into_active_model();
+    model.pwd = ActiveValue::Set(hash(&form.new)?);
+    model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+    model.update(&state.db).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_status(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(status): Json<AcStatus>,
+) -> Result<StatusCode, Error> {
+    let mut model = open(&state, id).await?.into_active_model();
+    model.status = ActiveValue::Set(status);
+    model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+    model.update(&state.db).await?;
+    // Kept first, so no order comes in meanwhile. Every order is tried, whichever fails.
+    let mut failed = None;
+    if status != AcStatus::Active {
+        for (_, order) in state.orders(|progress| progress.id == id) {
+            let seq = order.seq;
+            if let Err(err) = state.cancel(id, order).await {
+                tracing::error!("account {id}: order {seq} not canceled: {}", err.detail());
+                failed.get_or_insert(err);
+            }
+        }
+    }
+    failed.map_or(Ok(StatusCode::NO_CONTENT), Err)
+}
+
+async fn close(State(state): State<AppState>, Path(id): Path<i64>) -> Result<StatusCode, Error> {
+    set_status(State(state), Path(id), Json(AcStatus::Closed)).await
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/ac", routing::get(list).post(register))
+        .route("/ac/:id", routing::get(get).put(update).delete(close))
+        .route("/ac/:id/pwd", routing::put(change_pwd))
+        .route("/ac/:id/status", routing::put(set_status))
+}
@@ -0,0 +1,215 @@
+//! `srv bench`: drives the matching engine alone, without a journal, store or network, and
+//! prints how fast it matched and how long commands and book views took.
+//!
+//! Each security is sent a stream of one-lot limit orders around 10.00, buying and selling at
+//! random, and every third command cancels an order sent a little earlier. Commands go in through
+//! the queue as the server sends them, waiting whenever the queue is full, while `readers` tasks
+//! per security take views of the book as market data subscribers do.
+
+use entity::order;
+use entity::sea_orm_active_enums::{Dir, OrdStatus};
+use rust_decimal::Decimal;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::task;
+
+use crate::config::{BenchArgs, Engine};
+use crate::period::Period;
+use crate::security::{Event, Op, Security};
+
+/// When each command was sent and done with, in nanoseconds from the start.
+struct Times {
+    sent: Vec<AtomicU64>,
+    done: Vec<AtomicU64>,
+}
+
+/// What a security measured.
+#[derive(Default)]
+struct Run {
+    latencies: Vec<Duration>,
+    views: Vec<Duration>,
+    deals: usize,
+}
+
+/// A generator of the same orders on every run.
+struct Flow(u64);
+
+impl Flow {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn order(&mut self, seq: i64, code: &str) -> order::Model {
+        let roll = self.next();
+        order::Model {
+            seq,
+            code: code.to_owned(),
+            dir: if roll.is_multiple_of(2) {
+                Dir::Buy
+            } else {
+                Dir::Sell
+            },
+            price: Decimal::new(1000 + (roll / 2 % 21) as i64 - 10, 2),
+            quantity: 100 * (1 + (roll / 64 % 5) as i64),
+        }
+    }
+}
+
+async fn security(n: usize, args: Arc<BenchArgs>, tuning: Arc<Engine>, start: Instant) -> Run {
+    let code: Arc<str> = Arc::from(format!("B{n:04}"));
+    let times = Arc::new(Times {
+        sent: (0..args.orders).map(|_| AtomicU64::new(0)).collect(),
+        done: (0..args.orders).map(|_| AtomicU64::new(0)).collect(),
+    });
+    let deals = Arc::new(AtomicUsize::new(0));
+    let events = {
+        let (times, deals) = (times.clone(), deals.clone());
+        Arc::new(move |event| match event {
+            Event::Done(seq) => times.done[seq as usize - 1]
+                .store(start.elapsed().as_nanos() as u64, Ordering::Relaxed),
+            Event::Traded(_, traded) => {
+                deals.fetch_add(traded.len(), Ordering::Relaxed);
+            }
+            _ => {}
+        })
+    };
+    let (alerts, _) = broadcast::channel(1);
+    let security = Arc::new(Security::new(
+        code.clone(),
+        Decimal::new(1, 2),
+        Period::Continuous,
+        alerts,
+        tuning,
+        events,
+    ));
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..args.readers)
+        .map(|_| {
+            let (security, finished) = (security.clone(), finished.clone());
+            task::spawn(async move {
+                let mut views = Vec::new();
+                while !finished.load(Ordering::Relaxed) {
+                    let at = Instant::now();
+                    let picture = security.view();
+                    views.push(at.elapsed());
+                    std::hint::black_box(picture);
+                    task::yield_now().await;
+                }
+                views
+            })
+        })
+        .collect();
+
+    let mut flow = Flow(n as u64 + 1);
+    let mut sent = Vec::with_capacity(args.orders);
+    for i in 0..args.orders {
+        let seq = i as i64 + 1;
+        let op = match sent.len() {
+            len if i % 3 == 2 && len > 8 => Op::Cancel {
+                seq,
+                id: 0,
+                order: sent[len - 8],
+                status: OrdStatus::Canceled,
+                reply: None,
+            },
+            _ => {
+                let order = flow.order(seq, &code);
+                sent.push(order.seq);
+                Op::Place(Arc::new(order))
+            }
+        };
+        let room = loop {
+            match security.reserve().await {
+                Ok(room) => break room,
+                Err(_) => task::yield_now().await,
+            }
+        };
+        times.sent[i].store(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        security.send(op).await;
+        drop(room);
+    }
+    while times.done[args.orders - 1].load(Ordering::Relaxed) == 0 {
+        task::yield_now().await;
+    }
+    finished.store(true, Ordering::Relaxed);
+
+    let mut run = Run {
+        deals: deals.load(Ordering::Relaxed),
+        ..Default::default()
+    };
+    for reader in readers {
+        run.views.extend(reader.await.unwrap_or_default());
+    }
+    run.latencies = times
+        .sent
+        .iter()
+        .zip(&times.done)
+        .map(|(sent, done)| {
+            let (sent, done) = (sent.load(Ordering::Relaxed), done.load(Ordering::Relaxed));
+            Duration::from_nanos(done.saturating_sub(sent))
+        })
+        .collect();
+    run
+}
+
+/// The latency below which `q` of `latencies`, sorted, fall.
+fn quantile(latencies: &[Duration], q: f64) -> Duration {
+    match latencies.len() {
+        0 => Duration::ZERO,
+        n => latencies[((n - 1) as f64 * q) as usize],
+    }
+}
+
+fn line(what: &str, latencies: &[Duration]) -> String {
+    format!(
+        "{what:<9} p50 {:>9.1?}  p99 {:>9.1?}  p99.9 {:>9.1?}  max {:>9.1?}",
+        quantile(latencies, 0.5),
+        quantile(latencies, 0.99),
+        quantile(latencies, 0.999),
+        quantile(latencies, 1.0)
+    )
+}
+
+/// Runs the benchmark `args` describes with the engine settings `tuning`.
+pub async fn run(args: BenchArgs, tuning: Engine) {
+    let args = Arc::new(args);
+    let tuning = Arc::new(tuning);
+    let start = Instant::now();
+    let runs: Vec<_> = (0..args.securities)
+        .map(|n| task::spawn(security(n, args.clone(), tuning.clone(), start)))
+        .collect();
+    let mut total = Run::default();
+    for run in runs {
+        let run = run.await.expect("benchmark task failed");
+        total.latencies.extend(run.latencies);
+        total.views.extend(run.views);
+        total.deals += run.deals;
+    }
+    let elapsed = start.elapsed();
+    total.latencies.sort();
+    total.views.sort();
+
+    let commands = args.securities * args.orders;
+    println!(
+        "{commands} commands on {} securities in {:.2}s: {:.0} commands/s, {} deals",
+        args.securities,
+        elapsed.as_secs_f64(),
+        commands as f64 / elapsed.as_secs_f64(),
+        total.deals
+    );
+    println!("{}", line("command", &total.latencies));
+    println!(
+        "{} views by {} readers a security, {:.0} views/s",
+        total.views.len(),
+        args.readers,
+        total.views.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!("{}", line("view", &total.views));
+}
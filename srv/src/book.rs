@@ -1,78 +1,438 @@
+//! The order book of a security. Prices are kept as whole numbers of ticks, converted from and to
+//! `Decimal` only where orders come in and changes, deals and pictures go out. Resting orders
+//! live in an arena, each linked into the first in, first out queue of its level, and are found
+//! by seq through an index, so taking one off the book is a matter of unlinking it.
+
 use crate::deal::{Deal, DealCall, DealValue};
 use entity::{order, sea_orm_active_enums::Dir};
+use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use serde::Serialize;
 use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::HashMap;
+
+/// A price as a whole number of the ticks of its book.
+pub type Ticks = i64;
+
+/// The most ticks a price may take, leaving room to scale the book to finer ticks and to add
+/// up quantities at a price.
+pub const MAX_TICKS: Ticks = 1_000_000_000_000_000;
+
+/// Where a resting order is in the arena.
+type Slot = u32;
 
+/// A resting order, linked to its neighbours in the queue of its level.
+#[derive(Copy, Clone, Debug)]
+struct Resting {
+    seq: i64,
+    dir: Dir,
+    price: Ticks,
+    quantity: i64,
+    prev: Option<Slot>,
+    next: Option<Slot>,
+}
+
+/// The orders at one price, by time priority, with the total of their quantities.
 #[derive(Default, Clone, Debug)]
-pub struct Vol {
-    pub prices: BTreeMap<i64, i64>,
+pub struct Level {
+    head: Option<Slot>,
+    tail: Option<Slot>,
+    pub len: usize,
     pub sum: i64,
 }
 
-#[derive(Default, Clone, Debug)]
+/// One resting order touched by the book, with the quantity it gained (positive) or lost (negative).
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct Change {
+    pub seq: i64,
+    pub dir: Dir,
+    pub price: Decimal,
+    pub quantity: i64,
+}
+
+/// Where a call auction would uncross now, and what would be left over on which side.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct Imbalance {
+    pub price: Decimal,
+    pub paired: i64,
+    pub surplus: i64,
+    pub dir: Option<Dir>,
+}
+
+#[derive(Clone, Debug)]
 pub struct Book {
-    pub bids: BTreeMap<Decimal, Vol>,
-    pub offers: BTreeMap<Decimal, Vol>,
+    /// The price of one tick. It becomes finer should a price between two come in, as after
+    /// the tick size of the security was changed.
+    tick: Decimal,
+    bids: BTreeMap<Ticks, Level>,
+    offers: BTreeMap<Ticks, Level>,
+    /// Every resting order; the slots of those gone are taken again.
+    arena: Vec<Resting>,
+    free: Vec<Slot>,
+    /// The slot of each resting order, by seq.
+    slots: HashMap<i64, Slot>,
     pub price_call: Option<Decimal>,
+    /// The price of the last trade, continuous or auction.
+    pub last_price: Option<Decimal>,
+    pub seq: u64,
+    /// Order level changes since the last tick was published.
+    pub changes: Vec<Change>,
+}
+
+impl Default for Book {
+    /// A book in ticks of 1, made finer by the first orders.
+    fn default() -> Self {
+        Self::new(Decimal::ONE)
+    }
 }
 
 impl Book {
-    pub fn insert(&mut self, order: &order::Model) {
-        let vol = match order.dir {
-            Dir::Buy => self.bids.entry(order.price).or_default(),
-            Dir::Sell => self.offers.entry(order.price).or_default(),
+    /// An empty book in ticks of `tick`, which must be positive.
+    pub fn new(tick: Decimal) -> Self {
+        Self {
+            tick,
+            bids: BTreeMap::new(),
+            offers: BTreeMap::new(),
+            arena: Vec::new(),
+            free: Vec::new(),
+            slots: HashMap::new(),
+            price_call: None,
+            last_price: None,
+            seq: 0,
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn tick(&self) -> Decimal {
+        self.tick
+    }
+
+    /// The price of `ticks`, in the scale of the tick.
+    pub fn price(&self, ticks: Ticks) -> Decimal {
+        Decimal::from_i128_with_scale(self.tick.mantissa() * ticks as i128, self.tick.scale())
+    }
+
+    /// `price` in ticks, making them finer first if it falls between two. None if it would take
+    /// more than [`MAX_TICKS`] of them, the book being left as it was.
+    pub fn ticks(&mut self, price: Decimal) -> Option<Ticks> {
+        let (mut price_units, mut tick_units, scale) = units(price, self.tick)?;
+        if price_units.checked_rem(tick_units)? != 0 {
+            self.refine(price_units, tick_units, scale)?;
+            (price_units, tick_units, _) = units(price, self.tick)?;
+        }
+        let ticks = price_units / tick_units;
+        (ticks.abs() <= MAX_TICKS as i128).then_some(ticks as Ticks)
+    }
+
+    /// Makes ticks fine enough for a price of `price_units` to be a whole number of them, the
+    /// tick being `tick_units` of the same unit of `scale`, rescaling every price on the book.
+    /// None if a price on the book would then take more than [`MAX_TICKS`].
+    fn refine(&mut self, price_units: i128, tick_units: i128, scale: u32) -> Option<()> {
+        let (mut finer, mut rest) = (tick_units, price_units.abs());
+        while rest != 0 {
+            (finer, rest) = (rest, finer % rest);
+        }
+        let factor = Ticks::try_from(tick_units / finer).ok()?;
+        let highest = [&self.bids, &self.offers]
+            .into_iter()
+            .filter_map(|levels| levels.last_key_value())
+            .map(|(price, _)| *price)
+            .max()
+            .unwrap_or(1);
+        if highest.checked_mul(factor)? > MAX_TICKS {
+            return None;
+        }
+        self.tick = Decimal::from_i128_with_scale(finer, scale);
+        for levels in [&mut self.bids, &mut self.offers] {
+            *levels = std::mem::take(levels)
+                .into_iter()
+                .map(|(price, level)| (price * factor, level))
+                .collect();
+        }
+        for resting in &mut self.arena {
+            resting.price *= factor;
+        }
+        Some(())
+    }
+
+    /// Puts an order priced in ticks on the book without recording a change, behind the orders
+    /// at its price with a lower seq.
+    pub fn rest(&mut self, seq: i64, dir: Dir, price: Ticks, quantity: i64) {
+        let resting = Resting {
+            seq,
+            dir,
+            price,
+            quantity,
+            prev: None,
+            next: None,
         };
-        vol.sum += order.quantity;
-        vol.prices.insert(order.seq, order.quantity);
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.arena[slot as usize] = resting;
+                slot
+            }
+            None => {
+                self.arena.push(resting);
+                (self.arena.len() - 1) as Slot
+            }
+        };
+        self.slots.insert(seq, slot);
+        let level = match dir {
+            Dir::Buy => self.bids.entry(price).or_default(),
+            Dir::Sell => self.offers.entry(price).or_default(),
+        };
+        // Orders come in by seq, so this stops at the tail but for those put back on the book.
+        let mut prev = level.tail;
+        while let Some(at) = prev.filter(|at| self.arena[*at as usize].seq > seq) {
+            prev = self.arena[at as usize].prev;
+        }
+        let next = match prev {
+            Some(at) => self.arena[at as usize].next,
+            None => level.head,
+        };
+        match prev {
+            Some(at) => self.arena[at as usize].next = Some(slot),
+            None => level.head = Some(slot),
+        }
+        match next {
+            Some(at) => self.arena[at as usize].prev = Some(slot),
+            None => level.tail = Some(slot),
+        }
+        self.arena[slot as usize].prev = prev;
+        self.arena[slot as usize].next = next;
+        level.len += 1;
+        level.sum += quantity;
     }
 
-    pub fn matches(&mut self, get_price: impl Fn(Decimal, Decimal) -> Decimal) -> Option<Deal> {
-        Option::zip(self.bids.last_entry(), self.offers.first_entry())
-            .and_then(|(mut bid_vol, mut offer_vol)| {
-                (bid_vol.key() >= offer_vol.key()).then(|| {
-                    let price = get_price(*bid_vol.key(), *offer_vol.key());
-                    Option::zip(
-                        bid_vol.get_mut().prices.first_entry(),
-                        offer_vol.get_mut().prices.first_entry(),
-                    )
-                    .map(|(mut bid, mut offer)| {
-                        let quantity = *std::cmp::min(bid.get(), offer.get());
-                        *bid.get_mut() -= quantity;
-                        *offer.get_mut() -= quantity;
-                        let seq_bid = *bid.key();
-                        let seq_offer = *offer.key();
-                        if *bid.get_mut() == 0 {
-                            bid.remove();
-                        }
-                        if *offer.get_mut() == 0 {
-                            offer.remove();
-                        }
-                        DealValue {
-                            seq_bid,
-                            seq_offer,
-                            quantity,
-                        }
-                    })
-                    .map(|value| {
-                        bid_vol.get_mut().sum -= value.quantity;
-                        offer_vol.get_mut().sum -= value.quantity;
-                        let remain_bid = bid_vol.get().sum;
-                        if remain_bid == 0 {
-                            bid_vol.remove();
-                        }
-                        let remain_offer = offer_vol.get().sum;
-                        if remain_offer == 0 {
-                            offer_vol.remove();
-                        }
-                        Deal {
-                            price,
-                            value,
-                        }
+    /// Takes the order in `slot` off its level and frees the slot.
+    fn unlink(&mut self, slot: Slot) -> Resting {
+        let resting = self.arena[slot as usize];
+        let levels = match resting.dir {
+            Dir::Buy => &mut self.bids,
+            Dir::Sell => &mut self.offers,
+        };
+        if let Entry::Occupied(mut level) = levels.entry(resting.price) {
+            match resting.prev {
+                Some(at) => self.arena[at as usize].next = resting.next,
+                None => level.get_mut().head = resting.next,
+            }
+            match resting.next {
+                Some(at) => self.arena[at as usize].prev = resting.prev,
+                None => level.get_mut().tail = resting.prev,
+            }
+            level.get_mut().len -= 1;
+            level.get_mut().sum -= resting.quantity;
+            if level.get().len == 0 {
+                level.remove();
+            }
+        }
+        self.slots.remove(&resting.seq);
+        self.free.push(slot);
+        resting
+    }
+
+    /// Takes `quantity` off the order in `slot`, and the order off the book once none is left.
+    fn fill(&mut self, slot: Slot, quantity: i64) {
+        let resting = &mut self.arena[slot as usize];
+        resting.quantity -= quantity;
+        let (dir, price, left) = (resting.dir, resting.price, resting.quantity);
+        let levels = match dir {
+            Dir::Buy => &mut self.bids,
+            Dir::Sell => &mut self.offers,
+        };
+        if let Some(level) = levels.get_mut(&price) {
+            level.sum -= quantity;
+        }
+        if left == 0 {
+            self.unlink(slot);
+        }
+    }
+
+    /// Puts `order` on the book, unless its price is beyond the ticks of the book.
+    #[must_use]
+    pub fn insert(&mut self, order: &order::Model) -> bool {
+        let Some(price) = self.ticks(order.price) else {
+            return false;
+        };
+        self.rest(order.seq, order.dir, price, order.quantity);
+        self.changes.push(Change {
+            seq: order.seq,
+            dir: order.dir,
+            price: self.price(price),
+            quantity: order.quantity,
+        });
+        true
+    }
+
+    /// Takes the order `seq` off the book, returning it with what was left of it.
+    pub fn remove(&mut self, seq: i64) -> Option<Change> {
+        let slot = *self.slots.get(&seq)?;
+        let resting = self.unlink(slot);
+        let order = Change {
+            seq,
+            dir: resting.dir,
+            price: self.price(resting.price),
+            quantity: resting.quantity,
+        };
+        self.changes.push(Change {
+            quantity: -order.quantity,
+            ..order
+        });
+        Some(order)
+    }
+
+    /// The levels on side `dir`, lowest price first.
+    pub fn levels(
+        &self,
+        dir: Dir,
+    ) -> impl DoubleEndedIterator<Item = (Ticks, &Level)> + ExactSizeIterator {
+        match dir {
+            Dir::Buy => &self.bids,
+            Dir::Sell => &self.offers,
+        }
+        .iter()
+        .map(|(price, level)| (*price, level))
+    }
+
+    /// The orders at `level` by time priority, as `(seq, quantity)`.
+    pub fn queue(&self, level: &Level) -> impl Iterator<Item = (i64, i64)> + '_ {
+        std::iter::successors(level.head, |slot| self.arena[*slot as usize].next).map(|slot| {
+            let resting = &self.arena[slot as usize];
+            (resting.seq, resting.quantity)
+        })
+    }
+
+    /// Every resting order, by side, price and time priority, as if just added.
+    pub fn orders(&self) -> Vec<Change> {
+        [Dir::Buy, Dir::Sell]
+            .into_iter()
+            .flat_map(|dir| {
+                self.levels(dir).flat_map(move |(price, level)| {
+                    let price = self.price(price);
+                    self.queue(level).map(move |(seq, quantity)| Change {
+                        seq,
+                        dir,
+                        price,
+                        quantity,
                     })
                 })
             })
-            .flatten()
+            .collect()
+    }
+
+    /// Rewrites every resting order through `f`, keeping time priority by seq.
+    /// Orders mapped to a zero quantity, or to a price beyond the ticks of the book, are dropped
+    /// from the book and returned with a zero quantity.
+    /// Returns `(seq, price, quantity)` of every order after the adjustment,
+    /// and records the orders left on the book as the changes.
+    pub fn adjust(
+        &mut self,
+        f: impl Fn(Decimal, i64) -> (Decimal, i64),
+    ) -> Vec<(i64, Decimal, i64)> {
+        let orders = self.orders();
+        *self = Book {
+            price_call: self.price_call.map(|price| f(price, 0).0),
+            last_price: self.last_price.map(|price| f(price, 0).0),
+            seq: self.seq,
+            ..Book::new(self.tick)
+        };
+        let mut adjusted = Vec::new();
+        let mut kept = Vec::new();
+        for order in orders {
+            let (price, quantity) = f(order.price, order.quantity);
+            if quantity > 0 {
+                kept.push((order.seq, order.dir, price, quantity));
+            }
+            adjusted.push((order.seq, price, quantity));
+        }
+        kept.sort_by_key(|(seq, ..)| *seq);
+        for (seq, dir, price, quantity) in kept {
+            match self.ticks(price) {
+                Some(price) => self.rest(seq, dir, price, quantity),
+                // Off the book, as if nothing were left of it.
+                None => {
+                    if let Some(order) = adjusted.iter_mut().find(|order| order.0 == seq) {
+                        order.2 = 0;
+                    }
+                }
+            }
+        }
+        self.changes = self.orders();
+        adjusted
+    }
+
+    /// Whether every level holds orders, linked both ways, and the total of their quantities,
+    /// and every resting order is found by its seq.
+    pub fn is_consistent(&self) -> bool {
+        let mut count = 0;
+        let levels = [(Dir::Buy, &self.bids), (Dir::Sell, &self.offers)]
+            .into_iter()
+            .all(|(dir, levels)| {
+                levels.iter().all(|(price, level)| {
+                    let (mut prev, mut at) = (None, level.head);
+                    let (mut len, mut sum) = (0, 0);
+                    while let Some(slot) = at {
+                        let Some(resting) = self.arena.get(slot as usize) else {
+                            return false;
+                        };
+                        if resting.prev != prev
+                            || resting.dir != dir
+                            || resting.price != *price
+                            || resting.quantity <= 0
+                            || self.slots.get(&resting.seq) != Some(&slot)
+                            || len > self.arena.len()
+                        {
+                            return false;
+                        }
+                        len += 1;
+                        sum += resting.quantity;
+                        (prev, at) = (at, resting.next);
+                    }
+                    count += len;
+                    len > 0 && level.tail == prev && level.len == len && level.sum == sum
+                })
+            });
+        levels && count == self.slots.len()
+    }
+
+    pub fn matches(&mut self, get_price: impl Fn(Decimal, Decimal) -> Decimal) -> Option<Deal> {
+        let (&price_bid, bid) = self.bids.last_key_value()?;
+        let (&price_offer, offer) = self.offers.first_key_value()?;
+        if price_bid < price_offer {
+            return None;
+        }
+        let (bid, offer) = (bid.head?, offer.head?);
+        let (seq_bid, seq_offer) = (self.arena[bid as usize].seq, self.arena[offer as usize].seq);
+        let quantity = std::cmp::min(
+            self.arena[bid as usize].quantity,
+            self.arena[offer as usize].quantity,
+        );
+        let (price_bid, price_offer) = (self.price(price_bid), self.price(price_offer));
+        let price = get_price(price_bid, price_offer);
+        self.fill(bid, quantity);
+        self.fill(offer, quantity);
+        self.changes.push(Change {
+            seq: seq_bid,
+            dir: Dir::Buy,
+            price: price_bid,
+            quantity: -quantity,
+        });
+        self.changes.push(Change {
+            seq: seq_offer,
+            dir: Dir::Sell,
+            price: price_offer,
+            quantity: -quantity,
+        });
+        self.last_price = Some(price);
+        Some(Deal {
+            price,
+            value: DealValue {
+                seq_bid,
+                seq_offer,
+                quantity,
+            },
+            aggressor: None,
+        })
     }
 
     pub fn calc(&mut self) -> Option<DealCall> {
@@ -87,37 +447,237 @@ impl Book {
         self.price_call.map(|price| DealCall { price, values })
     }
 
-    pub fn remove(&mut self, order: &order::Model) -> Option<i64> {
-        match match order.dir {
-            Dir::Buy => self.bids.entry(order.price),
-            Dir::Sell => self.offers.entry(order.price),
-        } {
-            Entry::Vacant(_) => None,
-            Entry::Occupied(mut vol) => {
-                vol.get_mut().sum -= order.quantity;
-                let quantity = vol.get_mut().prices.remove(&order.seq);
-                if vol.get_mut().sum == 0 {
-                    vol.remove();
-                }
-                quantity
-            }
-        }
+    /// Uncrosses a copy of the book to find the indicative auction price and the imbalance left over.
+    pub fn indicative(&self) -> Option<Imbalance> {
+        let mut book = Book {
+            price_call: None,
+            changes: Vec::new(),
+            ..self.clone()
+        };
+        let DealCall { price, values } = book.calc()?;
+        // The price may fall halfway between two ticks.
+        let at = price / book.tick;
+        let bid: i64 = book
+            .bids
+            .range(at.ceil().to_i64()?..)
+            .map(|(_, level)| level.sum)
+            .sum();
+        let offer: i64 = book
+            .offers
+            .range(..=at.floor().to_i64()?)
+            .map(|(_, level)| level.sum)
+            .sum();
+        Some(Imbalance {
+            price,
+            paired: values.iter().map(|value| value.quantity).sum(),
+            surplus: bid + offer,
+            dir: match (bid, offer) {
+                (0, 0) => None,
+                (_, 0) => Some(Dir::Buy),
+                _ => Some(Dir::Sell),
+            },
+        })
     }
 }
 
+/// `a` and `b` as whole numbers of the same unit, with the scale of that unit.
+fn units(a: Decimal, b: Decimal) -> Option<(i128, i128, u32)> {
+    let scale = a.scale().max(b.scale());
+    let at = |value: Decimal| {
+        value
+            .mantissa()
+            .checked_mul(10i128.pow(scale - value.scale()))
+    };
+    Some((at(a)?, at(b)?, scale))
+}
+
 #[derive(Serialize, Default, Clone)]
 pub struct Picture {
     bids: BTreeMap<Decimal, i64>,
     offers: BTreeMap<Decimal, i64>,
     price_call: Option<Decimal>,
+    last_price: Option<Decimal>,
+    pub seq: u64,
 }
 
 impl Book {
     pub fn view(&self) -> Picture {
         Picture {
-            bids: self.bids.iter().map(|(k, q)| (*k, q.sum)).collect(),
-            offers: self.offers.iter().map(|(k, q)| (*k, q.sum)).collect(),
+            bids: self
+                .bids
+                .iter()
+                .map(|(price, level)| (self.price(*price), level.sum))
+                .collect(),
+            offers: self
+                .offers
+                .iter()
+                .map(|(price, level)| (self.price(*price), level.sum))
+                .collect(),
             price_call: self.price_call,
+            last_price: self.last_price,
+            seq: self.seq,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(seq: i64, dir: Dir, price: &str, quantity: i64) -> order::Model {
+        order::Model {
+            seq,
+            code: "TEST".to_owned(),
+            dir,
+            price: price.parse().unwrap(),
+            quantity,
+        }
+    }
+
+    /// A book in ticks of 0.01 holding `orders`.
+    fn book(orders: &[order::Model]) -> Book {
+        let mut book = Book::new(Decimal::new(1, 2));
+        for order in orders {
+            assert!(book.insert(order));
+        }
+        assert!(book.is_consistent());
+        book
+    }
+
+    /// The orders at `price` on side `dir` by time priority, as `(seq, quantity)`.
+    fn queue(book: &Book, dir: Dir, price: &str) -> Vec<(i64, i64)> {
+        let price: Decimal = price.parse().unwrap();
+        book.levels(dir)
+            .find(|(ticks, _)| book.price(*ticks) == price)
+            .map_or(Vec::new(), |(_, level)| book.queue(level).collect())
+    }
+
+    #[test]
+    fn keeps_time_priority_within_a_level() {
+        let mut book = book(&[
+            order(1, Dir::Buy, "10.00", 100),
+            order(3, Dir::Buy, "10.00", 300),
+            order(4, Dir::Buy, "9.99", 400),
+        ]);
+        // Put back on the book, as after a restart, behind the orders before it only.
+        book.rest(2, Dir::Buy, 1000, 200);
+        assert!(book.is_consistent());
+        assert_eq!(
+            queue(&book, Dir::Buy, "10.00"),
+            [(1, 100), (2, 200), (3, 300)]
+        );
+        let (_, level) = book.levels(Dir::Buy).next_back().unwrap();
+        assert_eq!((level.len, level.sum), (3, 600));
+
+        assert!(book.insert(&order(5, Dir::Sell, "9.99", 250)));
+        let deals: Vec<_> = std::iter::from_fn(|| book.matches(|bid, _| bid))
+            .map(|deal| (deal.value.seq_bid, deal.value.quantity))
+            .collect();
+        assert_eq!(deals, [(1, 100), (2, 150)]);
+        assert!(book.is_consistent());
+        assert_eq!(queue(&book, Dir::Buy, "10.00"), [(2, 50), (3, 300)]);
+    }
+
+    #[test]
+    fn cancels_from_anywhere_in_a_level() {
+        let mut book = book(
+            &(1..=5)
+                .map(|seq| order(seq, Dir::Sell, "10.00", seq * 100))
+                .collect::<Vec<_>>(),
+        );
+        for (seq, left) in [(1, [2, 3, 4, 5].as_slice()), (3, &[2, 4, 5]), (5, &[2, 4])] {
+            let removed = book.remove(seq).unwrap();
+            assert_eq!((removed.seq, removed.quantity), (seq, seq * 100));
+            assert_eq!(book.changes.last().unwrap().quantity, -seq * 100);
+            assert!(book.is_consistent());
+            let queue = queue(&book, Dir::Sell, "10.00");
+            assert_eq!(queue.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), left);
         }
+        assert!(book.remove(3).is_none());
+        let (_, level) = book.levels(Dir::Sell).next().unwrap();
+        assert_eq!((level.len, level.sum), (2, 600));
+
+        book.remove(2).unwrap();
+        book.remove(4).unwrap();
+        assert!(book.is_consistent());
+        assert_eq!(book.levels(Dir::Sell).len(), 0);
+    }
+
+    #[test]
+    fn fills_orders_in_part() {
+        let mut book = book(&[
+            order(1, Dir::Sell, "10.00", 300),
+            order(2, Dir::Buy, "10.00", 100),
+        ]);
+        let deal = book.matches(|_, offer| offer).unwrap();
+        assert_eq!(
+            (
+                deal.price,
+                deal.value.seq_bid,
+                deal.value.seq_offer,
+                deal.value.quantity
+            ),
+            ("10.00".parse().unwrap(), 2, 1, 100)
+        );
+        assert!(book.is_consistent());
+        assert!(book.matches(|_, offer| offer).is_none());
+        assert_eq!(queue(&book, Dir::Sell, "10.00"), [(1, 200)]);
+        assert_eq!(book.levels(Dir::Buy).len(), 0);
+
+        assert!(book.insert(&order(3, Dir::Buy, "10.01", 150)));
+        assert_eq!(book.matches(|_, offer| offer).unwrap().value.quantity, 150);
+        assert!(book.is_consistent());
+        assert_eq!(queue(&book, Dir::Sell, "10.00"), [(1, 50)]);
+        let (_, level) = book.levels(Dir::Sell).next().unwrap();
+        assert_eq!((level.len, level.sum), (1, 50));
+    }
+
+    #[test]
+    fn refines_ticks_under_resting_orders() {
+        let mut book = Book::new(Decimal::new(5, 2));
+        for order in [
+            order(1, Dir::Buy, "10.00", 100),
+            order(2, Dir::Buy, "10.00", 200),
+            order(3, Dir::Sell, "10.05", 300),
+        ] {
+            assert!(book.insert(&order));
+        }
+        assert!(book.insert(&order(4, Dir::Sell, "10.01", 400)));
+        assert_eq!(book.tick(), Decimal::new(1, 2));
+        assert!(book.is_consistent());
+        let orders: Vec<_> = book
+            .orders()
+            .into_iter()
+            .map(|change| (change.seq, change.price.to_string()))
+            .collect();
+        assert_eq!(
+            orders,
+            [(1, "10.00"), (2, "10.00"), (4, "10.01"), (3, "10.05")]
+                .map(|(seq, price)| (seq, price.to_owned()))
+        );
+        assert_eq!(queue(&book, Dir::Buy, "10.00"), [(1, 100), (2, 200)]);
+
+        // Ticks too fine for the prices on the book are refused, the book left as it was.
+        let mut book = Book::new(Decimal::ONE);
+        assert!(book.insert(&order(1, Dir::Sell, &MAX_TICKS.to_string(), 100)));
+        assert!(!book.insert(&order(2, Dir::Buy, "0.5", 100)));
+        assert_eq!(book.tick(), Decimal::ONE);
+        assert!(book.is_consistent());
+        assert_eq!(book.orders().len(), 1);
+    }
+
+    #[test]
+    fn takes_a_freed_slot_again() {
+        let mut book = book(&[
+            order(1, Dir::Buy, "10.00", 100),
+            order(2, Dir::Buy, "10.00", 200),
+        ]);
+        let slot = book.slots[&1];
+        book.remove(1).unwrap();
+        assert!(book.is_consistent());
+        assert!(book.insert(&order(3, Dir::Buy, "10.00", 300)));
+        assert!(book.is_consistent());
+        assert_eq!((book.slots[&3], book.arena.len()), (slot, 2));
+        assert_eq!(queue(&book, Dir::Buy, "10.00"), [(2, 200), (3, 300)]);
     }
 }
@@ -0,0 +1,207 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::response::sse::{Event, KeepAlive};
+use axum::response::{IntoResponse, Sse};
+use axum::{routing, Router};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use entity::{candle, rec};
+use rust_decimal::Decimal;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{ConnectionTrait, DbErr, EntityTrait, IntoActiveModel};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::error::Error;
+use crate::state::AppState;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Span {
+    #[serde(rename = "1s")]
+    S1,
+    #[serde(rename = "1m")]
+    M1,
+    #[serde(rename = "5m")]
+    M5,
+    #[serde(rename = "1h")]
+    H1,
+    #[serde(rename = "1d")]
+    D1,
+}
+
+impl Span {
+    pub const ALL: [Span; 5] = [Span::S1, Span::M1, Span::M5, Span::H1, Span::D1];
+
+    pub fn secs(self) -> i64 {
+        match self {
+            Span::S1 => 1,
+            Span::M1 => 60,
+            Span::M5 => 300,
+            Span::H1 => 3600,
+            Span::D1 => 86400,
+        }
+    }
+
+    /// The start of the bar containing `at`, aligned to UTC.
+    pub fn start(self, at: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        let secs = at.timestamp();
+        Utc.timestamp_opt(secs - secs.rem_euclid(self.secs()), 0)
+            .unwrap()
+            .fixed_offset()
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Candle {
+    #[serde(flatten)]
+    pub model: candle::Model,
+    pub vwap: Decimal,
+}
+
+impl From<candle::Model> for Candle {
+    fn from(model: candle::Model) -> Self {
+        let vwap = (model.turnover / Decimal::from(model.volume)).round_dp(4);
+        Self { model, vwap }
+    }
+}
+
+/// The bar of `span` a trade opens.
+pub fn bar(span: Span, rec: &rec::Model) -> candle::Model {
+    candle::Model {
+        code: rec.code.clone(),
+        span: span.secs(),
+        start: span.start(rec.created_at),
+        open: rec.price,
+        high: rec.price,
+        low: rec.price,
+        close: rec.price,
+        volume: rec.quantity,
+        turnover: rec.price * Decimal::from(rec.quantity),
+    }
+}
+
+/// Folds a trade into the bar it falls in.
+pub fn fold(bar: &mut candle::Model, rec: &rec::Model) {
+    bar.high = bar.high.max(rec.price);
+    bar.low = bar.low.min(rec.price);
+    bar.close = rec.price;
+    bar.volume += rec.quantity;
+    bar.turnover += rec.price * Decimal::from(rec.quantity);
+}
+
+/// Folds a trade into the bar of every span it falls in, as part of the projection that
+/// booked it.
+pub async fn update(
+    conn: &impl ConnectionTrait,
+    rec: &rec::Model,
+) -> Result<Vec<candle::Model>, DbErr> {
+    let mut candles = Vec::with_capacity(Span::ALL.len());
+    let price = rec.price;
+    let turnover = rec.price * Decimal::from(rec.quantity);
+    for span in Span::ALL {
+        candles.push(
+            candle::Entity::insert(bar(span, rec).into_active_model())
+                .on_conflict(
+                    OnConflict::columns([
+                        candle::Column::Code,
+                        candle::Column::Span,
+                        candle::Column::Start,
+                    ])
+                    .value(
+                        candle::Column::High,
+                        Expr::case(
+                            Expr::col((candle::Entity, candle::Column::High)).lt(price),
+                            price,
+                        )
+                        .finally(Expr::col((candle::Entity, candle::Column::High))),
+                    )
+                    .value(
+                        candle::Column::Low,
+                        Expr::case(
+                            Expr::col((candle::Entity, candle::Column::Low)).gt(price),
+                            price,
+                        )
+                        .finally(Expr::col((candle::Entity, candle::Column::Low))),
+                    )
+                    .update_column(candle::Column::Close)
+                    .value(
+                        candle::Column::Volume,
+                        Expr::col((candle::Entity, candle::Column::Volume)).add(rec.quantity),
+                    )
+                    .value(
+                        candle::Column::Turnover,
+                        Expr::col((candle::Entity, candle::Column::Turnover)).add(turnover),
+                    )
+                    .to_owned(),
+                )
+                .exec_with_returning(conn)
+                .await?,
+        );
+    }
+    Ok(candles)
+}
+
+#[derive(Deserialize)]
+pub struct History {
+    pub span: Span,
+    pub from: Option<DateTime<FixedOffset>>,
+    pub to: Option<DateTime<FixedOffset>>,
+    pub limit: Option<u64>,
+}
+
+impl History {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(500).min(5000)
+    }
+}
+
+async fn history(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<History>,
+) -> Result<Json<Vec<Candle>>, Error> {
+    let mut candles: Vec<Candle> = state
+        .store
+        .candles(&code, &query)
+        .await?
+        .into_iter()
+        .map(Candle::from)
+        .collect();
+    candles.reverse();
+    Ok(Json(candles))
+}
+
+#[derive(Deserialize)]
+pub struct Live {
+    pub span: Span,
+}
+
+async fn live(
+    State(state): State<AppState>,
+    Path(code): Path<Arc<str>>,
+    Query(Live { span }): Query<Live>,
+) -> Result<impl IntoResponse, Error> {
+    let security = state
+        .engine
+        .get(&code)
+        .map(|security| security.value().clone())
+        .ok_or(Error::NotFound("security"))?;
+    Ok(Sse::new(async_stream::stream! {
+        let mut rx = security.bc_candle.subscribe();
+        loop {
+            match rx.recv().await {
+                Ok(candle) if candle.span == span.secs() => {
+                    yield Event::default().event("candle").json_data(Candle::from(candle));
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+    .keep_alive(KeepAlive::default()))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/candle/:code", routing::get(history))
+        .route("/candle/:code/live", routing::get(live))
+}
@@ -0,0 +1,200 @@
+//! The little endian encoding shared by the journal and book snapshots.
+
+use entity::sea_orm_active_enums::{Dir, OrdStatus};
+use rust_decimal::Decimal;
+
+use crate::period::Period;
+
+/// Bytes that do not decode.
+#[derive(Debug)]
+pub struct Corrupt;
+
+#[derive(Default)]
+pub struct Writer(pub Vec<u8>);
+
+impl Writer {
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub fn decimal(&mut self, value: Decimal) {
+        self.0.extend(value.serialize());
+    }
+
+    pub fn opt_decimal(&mut self, value: Option<Decimal>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.decimal(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    pub fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend(value.as_bytes());
+    }
+
+    pub fn opt_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.str(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    /// Counts the items of a list to follow.
+    pub fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    pub fn dir(&mut self, dir: Dir) {
+        self.u8(match dir {
+            Dir::Buy => 0,
+            Dir::Sell => 1,
+        });
+    }
+
+    pub fn status(&mut self, status: OrdStatus) {
+        self.u8(match status {
+            OrdStatus::New => 0,
+            OrdStatus::PartiallyFilled => 1,
+            OrdStatus::Filled => 2,
+            OrdStatus::Canceled => 3,
+            OrdStatus::Expired => 4,
+            OrdStatus::Rejected => 5,
+            OrdStatus::Replaced => 6,
+        });
+    }
+
+    pub fn period(&mut self, period: Period) {
+        self.u8(match period {
+            Period::Prepare => 0,
+            Period::Call => 1,
+            Period::Continuous => 2,
+            Period::Suspense => 3,
+        });
+    }
+}
+
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Corrupt> {
+        let (head, rest) = self.0.split_first_chunk::<N>().ok_or(Corrupt)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Corrupt> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Corrupt> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Corrupt> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, Corrupt> {
+        Ok(i64::from_le_bytes(self.take()?))
+    }
+
+    pub fn decimal(&mut self) -> Result<Decimal, Corrupt> {
+        Ok(Decimal::deserialize(self.take()?))
+    }
+
+    pub fn opt_decimal(&mut self) -> Result<Option<Decimal>, Corrupt> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => self.decimal().map(Some),
+            _ => Err(Corrupt),
+        }
+    }
+
+    pub fn str(&mut self) -> Result<String, Corrupt> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return Err(Corrupt);
+        }
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(value.to_vec()).map_err(|_| Corrupt)
+    }
+
+    pub fn opt_str(&mut self) -> Result<Option<String>, Corrupt> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => self.str().map(Some),
+            _ => Err(Corrupt),
+        }
+    }
+
+    /// The number of items of a list to follow, each at least `size` bytes long.
+    pub fn len(&mut self, size: usize) -> Result<usize, Corrupt> {
+        let len = self.u32()? as usize;
+        match len
+            .checked_mul(size)
+            .is_some_and(|bytes| bytes <= self.0.len())
+        {
+            true => Ok(len),
+            false => Err(Corrupt),
+        }
+    }
+
+    pub fn dir(&mut self) -> Result<Dir, Corrupt> {
+        match self.u8()? {
+            0 => Ok(Dir::Buy),
+            1 => Ok(Dir::Sell),
+            _ => Err(Corrupt),
+        }
+    }
+
+    pub fn status(&mut self) -> Result<OrdStatus, Corrupt> {
+        match self.u8()? {
+            0 => Ok(OrdStatus::New),
+            1 => Ok(OrdStatus::PartiallyFilled),
+            2 => Ok(OrdStatus::Filled),
+            3 => Ok(OrdStatus::Canceled),
+            4 => Ok(OrdStatus::Expired),
+            5 => Ok(OrdStatus::Rejected),
+            6 => Ok(OrdStatus::Replaced),
+            _ => Err(Corrupt),
+        }
+    }
+
+    pub fn period(&mut self) -> Result<Period, Corrupt> {
+        match self.u8()? {
+            0 => Ok(Period::Prepare),
+            1 => Ok(Period::Call),
+            2 => Ok(Period::Continuous),
+            3 => Ok(Period::Suspense),
+            _ => Err(Corrupt),
+        }
+    }
+
+    /// Checks that nothing is left over.
+    pub fn end(self) -> Result<(), Corrupt> {
+        match self.0.is_empty() {
+            true => Ok(()),
+            false => Err(Corrupt),
+        }
+    }
+}
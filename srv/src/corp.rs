@@ -0,0 +1,245 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::{routing, Router};
+use chrono::Utc;
+use entity::sea_orm_active_enums::SecurityStatus;
+use entity::{corp_action, entitlement, security};
+use implicit_clone::sync::IString;
+use rust_decimal::Decimal;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::journal::Command;
+use crate::msg::MsgBody;
+use crate::period::Period;
+use crate::state::AppState;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Action {
+    /// `from` old shares become `to` new shares; a reverse split has `from > to`.
+    Split { from: i64, to: i64 },
+    /// Cash paid per share held.
+    Dividend { amount: Decimal },
+    /// The security continues trading under a new code.
+    Rename { code: String },
+}
+
+impl Action {
+    fn is_valid(&self, code: &str) -> bool {
+        match self {
+            Action::Split { from, to } => *from > 0 && *to > 0 && from != to,
+            Action::Dividend { amount } => *amount > Decimal::ZERO,
+            Action::Rename { code: new } => !new.is_empty() && new != code,
+        }
+    }
+}
+
+async fn apply(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Json(action): Json<Action>,
+) -> Result<(StatusCode, Json<corp_action::Model>), Error> {
+    if *state.period.borrow() != Period::Suspense {
+        return Err(Error::Forbidden(
+            "corporate actions apply only while the market is closed",
+        ));
+    }
+    if !action.is_valid(&code) {
+        return Err(Error::Invalid("invalid corporate action"));
+    }
+    let listed = match security::Entity::find_by_id(code.clone())
+        .one(&state.db)
+        .await?
+    {
+        Some(listed) if listed.status != SecurityStatus::Delisted => listed,
+        _ => return Err(Error::NotFound("security")),
+    };
+
+    // Positions are read from the store, which must have caught up on the trades.
+    state.projector.flush().await?;
+    let holders = state.store.holders(&code).await?;
+    let owners = state.orders(|progress| progress.code == code);
+    let affected: BTreeSet<i64> = holders
+        .iter()
+        .map(|position| position.id)
+        .chain(owners.iter().map(|(id, _)| *id))
+        .collect();
+
+    let txn = state.db.begin().await?;
+    let journal = corp_action::ActiveModel {
+        code: ActiveValue::Set(code.clone()),
+        action: ActiveValue::Set(serde_json::json!(&action)),
+        created_at: ActiveValue::Set(Utc::now().fixed_offset()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    let mut entitlements = Vec::new();
+    let mut command = None;
+    match &action {
+        &Action::Split { from, to } => {
+            let tick = listed.tick_size;
+            let ratio = Decimal::from(from) / Decimal::from(to);
+            command = Some(Command::Split {
+                code: code.clone(),
+                from,
+                to,
+                tick,
+                lot: listed.lot_size,
+            });
+            let ref_price = listed
+                .ref_price
+                .map(|price| std::cmp::max(tick, (price * ratio / tick).round() * tick));
+            // Positions keep whole shares; the fraction left over is paid in cash at the new
+            // reference price.
+            for holder in holders.iter() {
+                let fraction = holder.quantity * to % from;
+                if fraction == 0 {
+                    continue;
+                }
+                let price = ref_price.ok_or(Error::Invalid(
+                    "a split leaving fractions of shares needs a reference price",
+                ))?;
+                entitlements.push(
+                    entitlement::ActiveModel {
+                        seq: ActiveValue::Set(journal.seq),
+                        id: ActiveValue::Set(holder.id),
+                        code: ActiveValue::Set(code.clone()),
+                        quantity: ActiveValue::Set(holder.quantity),
+                        amount: ActiveValue::Set(
+                            (price * Decimal::from(fraction) / Decimal::from(from)).round_dp(2),
+                        ),
+                        ..Default::default()
+                    }
+                    .insert(&txn)
+                    .await?,
+                );
+            }
+            let mut model = listed.into_active_model();
+            model.ref_price = ActiveValue::Set(ref_price);
+            model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+            model.update(&txn).await?;
+        }
+        &Action::Dividend { amount } => {
+            for holder in holders.iter().filter(|position| position.quantity > 0) {
+                entitlements.push(
+                    entitlement::ActiveModel {
+                        seq: ActiveValue::Set(journal.seq),
+                        id: ActiveValue::Set(holder.id),
+                        code: ActiveValue::Set(code.clone()),
+                        quantity: ActiveValue::Set(holder.quantity),
+                        amount: ActiveValue::Set(amount * Decimal::from(holder.quantity)),
+                        ..Default::default()
+                    }
+                    .insert(&txn)
+                    .await?,
+                );
+            }
+            let tick = listed.tick_size;
+            let ref_price = listed
+                .ref_price
+                .map(|price| std::cmp::max(tick, price - amount));
+            let mut model = listed.into_active_model();
+            model.ref_price = ActiveValue::Set(ref_price);
+            model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+            model.update(&txn).await?;
+        }
+        Action::Rename { code: new } => {
+            // A code already taken is a unique violation, answered as a conflict.
+            let now = Utc::now().fixed_offset();
+            security::Model {
+                code: new.clone(),
+                status: SecurityStatus::Listed,
+                updated_at: now,
+                ..listed.clone()
+            }
+            .into_active_model()
+            .reset_all()
+            .insert(&txn)
+            .await?;
+            let mut model = listed.into_active_model();
+            model.status = ActiveValue::Set(SecurityStatus::Delisted);
+            model.updated_at = ActiveValue::Set(now);
+            model.update(&txn).await?;
+            command = Some(Command::Rename {
+                code: code.clone(),
+                new: new.clone(),
+            });
+        }
+    }
+    // Nothing is kept unless the journal takes the command, and the books follow only once the
+    // securities they refer to are in place.
+    match command {
+        Some(command) => {
+            state
+                .submit_after(command, async { Ok(txn.commit().await?) })
+                .await?;
+        }
+        None => txn.commit().await?,
+    }
+
+    let happened_at = Utc::now().fixed_offset();
+    let data = Arc::new(journal.clone());
+    for id in affected {
+        state.send(
+            id,
+            MsgBody {
+                ack: 0,
+                name: IString::Static("CorporateAction"),
+                data: data.clone(),
+                happened_at,
+            },
+        );
+    }
+    for entitlement in entitlements {
+        state.send(
+            entitlement.id,
+            MsgBody {
+                ack: 0,
+                name: IString::Static("Entitlement"),
+                data: Arc::new(entitlement),
+                happened_at,
+            },
+        );
+    }
+
+    Ok((StatusCode::CREATED, Json(journal)))
+}
+
+async fn journal(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<Json<Vec<corp_action::Model>>, Error> {
+    Ok(Json(
+        corp_action::Entity::find()
+            .filter(corp_action::Column::Code.eq(code))
+            .order_by_asc(corp_action::Column::Seq)
+            .all(&state.db)
+            .await?,
+    ))
+}
+
+async fn view_entitlement(
+    State(state): State<AppState>,
+    Query(id): Query<i64>,
+) -> Result<Json<Vec<entitlement::Model>>, Error> {
+    Ok(Json(
+        entitlement::Entity::find()
+            .filter(entitlement::Column::Id.eq(id))
+            .order_by_desc(entitlement::Column::Ack)
+            .all(&state.db)
+            .await?,
+    ))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/security/:code/action", routing::get(journal).post(apply))
+        .route("/view_entitlement", routing::get(view_entitlement))
+}
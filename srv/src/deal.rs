@@ -1,3 +1,4 @@
+use entity::sea_orm_active_enums::Dir;
 use rust_decimal::Decimal;
 use serde::Serialize;
 
@@ -18,4 +19,6 @@ pub struct DealCall {
 pub struct Deal {
     pub price: Decimal,
     pub value: DealValue,
+    /// The side of the incoming order that took liquidity; `None` for a trade of an auction.
+    pub aggressor: Option<Dir>,
 }
@@ -0,0 +1,159 @@
+use argon2::password_hash;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, FixedOffset};
+use sea_orm::{DbErr, SqlErr};
+use serde::Serialize;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+/// Why a request failed. Answered as `{ "code": .., "message": .. }` with the matching status.
+#[derive(Debug)]
+pub enum Error {
+    /// Names what was not found.
+    NotFound(&'static str),
+    Unauthorized,
+    Forbidden(&'static str),
+    Invalid(&'static str),
+    /// The order was filled or canceled in the meantime.
+    Gone,
+    /// Matching for the security stopped after repeated failures.
+    Halted(Arc<str>),
+    /// The security has more waiting to be matched than it takes.
+    Busy(Arc<str>),
+    /// The store did not catch up with the journal in time.
+    Behind,
+    Db(DbErr),
+    /// The journal could not be written, so the command was not taken.
+    Journal(std::io::Error),
+    /// A password could not be hashed.
+    Hash(password_hash::Error),
+    /// The books rebuilt at startup disagree with the store, on each of these.
+    Inconsistent(Vec<String>),
+}
+
+impl Error {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Gone => StatusCode::GONE,
+            Error::Halted(_) | Error::Busy(_) | Error::Behind => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Journal(_) | Error::Hash(_) | Error::Inconsistent(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            Error::Db(err) => match err.sql_err() {
+                Some(SqlErr::ForeignKeyConstraintViolation(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+                Some(SqlErr::UniqueConstraintViolation(_)) => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+        }
+    }
+
+    /// A stable, machine readable name for the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound(_) => "not_found",
+            Error::Unauthorized => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::Invalid(_) => "invalid",
+            Error::Gone => "gone",
+            Error::Halted(_) => "halted",
+            Error::Busy(_) => "busy",
+            Error::Behind => "behind",
+            Error::Journal(_) | Error::Hash(_) | Error::Inconsistent(_) => "internal",
+            Error::Db(err) => match err.sql_err() {
+                Some(SqlErr::ForeignKeyConstraintViolation(_)) => "unknown_reference",
+                Some(SqlErr::UniqueConstraintViolation(_)) => "conflict",
+                _ => "internal",
+            },
+        }
+    }
+
+    /// Like the message, but with the database's own words, for the log and alerts.
+    pub fn detail(&self) -> String {
+        match self {
+            Error::Db(err) => err.to_string(),
+            Error::Journal(err) => format!("journal: {err}"),
+            Error::Hash(err) => format!("password hash: {err}"),
+            Error::Inconsistent(problems) => format!("{self}:\n  {}", problems.join("\n  ")),
+            err => err.to_string(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound(what) => write!(f, "{what} not found"),
+            Error::Unauthorized => write!(f, "wrong account or password"),
+            Error::Forbidden(reason) | Error::Invalid(reason) => write!(f, "{reason}"),
+            Error::Gone => write!(f, "order no longer on the book"),
+            Error::Halted(code) => write!(f, "{code} is halted"),
+            Error::Busy(code) => write!(f, "{code} is busy, try again"),
+            Error::Behind => write!(f, "the store is behind, try again"),
+            // The database's own message stays in the server log.
+            Error::Db(err) => match err.sql_err() {
+                Some(SqlErr::ForeignKeyConstraintViolation(_)) => {
+                    write!(f, "refers to something that does not exist")
+                }
+                Some(SqlErr::UniqueConstraintViolation(_)) => write!(f, "already exists"),
+                _ => write!(f, "internal error"),
+            },
+            Error::Journal(_) | Error::Hash(_) => write!(f, "internal error"),
+            Error::Inconsistent(_) => write!(f, "the books disagree with the store"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<DbErr> for Error {
+    fn from(err: DbErr) -> Self {
+        Error::Db(err)
+    }
+}
+
+impl From<password_hash::Error> for Error {
+    fn from(err: password_hash::Error) -> Self {
+        Error::Hash(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Journal(err)
+    }
+}
+
+#[derive(Serialize)]
+struct Body {
+    code: &'static str,
+    message: String,
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        if let Error::Db(_) | Error::Journal(_) | Error::Hash(_) = &self {
+            tracing::error!("{}", self.detail());
+        }
+        let body = Body {
+            code: self.code(),
+            message: self.to_string(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+/// Raised when matching fails on the book of a security.
+#[derive(Serialize, Clone, Debug)]
+pub struct Alert {
+    pub code: Arc<str>,
+    /// Whether the security was halted rather than matching going on.
+    pub halted: bool,
+    pub reason: String,
+    pub happened_at: DateTime<FixedOffset>,
+}
@@ -0,0 +1,918 @@
+//! FIX 4.4 order entry. A session logs on with its account id as `Username` and the account password
+//! as `Password`; orders then go through the same checks and `req` log as the REST and WebSocket routes.
+
+use ::fix::{msg_type, tag, timestamp, Message};
+use dashmap::DashMap;
+use entity::ac;
+use entity::order;
+use entity::sea_orm_active_enums::{AcStatus, Dir, OrdStatus};
+use rust_decimal::Decimal;
+use sea_orm::EntityTrait;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+
+use crate::msg::{MsgBody, Receiver, Takes};
+use crate::report::ExecutionReport;
+use crate::state::{AppState, Placed};
+
+pub const COMP_ID: &str = "SECURITY_MATCHING";
+/// Messages read ahead of the one being handled; past it, the counterparty is left to wait.
+const INBOUND_CAPACITY: usize = 64;
+/// Application messages kept per session for resending; older ones are gap filled.
+const RESEND_CAPACITY: usize = 4096;
+
+/// A cancel or replace in flight, so the resulting cancel is reported accordingly.
+enum Pending {
+    Cancel(String),
+    Replace,
+}
+
+/// An order entered on a FIX session, with what has been reported for it so far.
+struct Tracked {
+    cl_ord_id: String,
+    code: String,
+    dir: Dir,
+    price: Decimal,
+    quantity: i64,
+    cum: i64,
+    turnover: Decimal,
+    pending: Option<Pending>,
+    canceled: bool,
+}
+
+impl Tracked {
+    fn avg(&self) -> Decimal {
+        if self.cum == 0 {
+            Decimal::ZERO
+        } else {
+            (self.turnover / Decimal::from(self.cum)).round_dp(4)
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self.cum {
+            0 => "0",
+            cum if cum < self.quantity => "1",
+            _ => "2",
+        }
+    }
+}
+
+/// Session state kept per `SenderCompID` across reconnects, so sequence numbers can be recovered.
+struct Store {
+    id: i64,
+    online: bool,
+    next_in: u64,
+    next_out: u64,
+    exec_id: u64,
+    /// Application messages as sent, for resending; admin messages are gap filled instead.
+    sent: BTreeMap<u64, Message>,
+    orders: HashMap<i64, Tracked>,
+    cl_ord: HashMap<String, i64>,
+    /// `ClOrdID`s of orders being entered, reported on before they are tracked.
+    entering: HashSet<String>,
+}
+
+impl Store {
+    fn exec_id(&mut self) -> u64 {
+        self.exec_id += 1;
+        self.exec_id
+    }
+
+    fn new(id: i64) -> Self {
+        Self {
+            id,
+            online: false,
+            next_in: 1,
+            next_out: 1,
+            exec_id: 0,
+            sent: BTreeMap::new(),
+            orders: HashMap::new(),
+            cl_ord: HashMap::new(),
+            entering: HashSet::new(),
+        }
+    }
+}
+
+type Stores = Arc<DashMap<String, Arc<Mutex<Store>>>>;
+
+/// The execution report in `body`, if it is one.
+fn execution(body: &MsgBody) -> serde_json::Result<Option<ExecutionReport>> {
+    if body.name != ExecutionReport::NAME {
+        return Ok(None);
+    }
+    serde_json::from_value(serde_json::to_value(&body.data)?).map(Some)
+}
+
+/// Picks the messages a session forwards: reports on the orders entered on it, new orders being
+/// answered as they are entered. The rest are left to the account's other sessions.
+fn takes(store: Arc<Mutex<Store>>) -> Takes {
+    Arc::new(move |body| {
+        let Ok(Some(execution)) = execution(body) else {
+            return false;
+        };
+        let store = store.lock().unwrap();
+        let tracked = execution
+            .seq
+            .is_some_and(|seq| store.orders.contains_key(&seq));
+        let entering = execution
+            .cl_ord_id
+            .is_some_and(|cl_ord_id| store.entering.contains(&cl_ord_id));
+        tracked || entering
+    })
+}
+
+fn side(dir: Dir) -> &'static str {
+    match dir {
+        Dir::Buy => "1",
+        Dir::Sell => "2",
+    }
+}
+
+fn dir(side: &str) -> Option<Dir> {
+    match side {
+        "1" => Some(Dir::Buy),
+        "2" => Some(Dir::Sell),
+        _ => None,
+    }
+}
+
+fn report(exec_id: u64, seq: i64, tracked: &Tracked, exec_type: &str, ord_status: &str) -> Message {
+    let leaves = match ord_status {
+        "0" | "1" => tracked.quantity - tracked.cum,
+        _ => 0,
+    };
+    Message::new(msg_type::EXECUTION_REPORT)
+        .with(tag::ORDER_ID, seq)
+        .with(tag::CL_ORD_ID, &tracked.cl_ord_id)
+        .with(tag::EXEC_ID, exec_id)
+        .with(tag::EXEC_TYPE, exec_type)
+        .with(tag::ORD_STATUS, ord_status)
+        .with(tag::SYMBOL, &tracked.code)
+        .with(tag::SIDE, side(tracked.dir))
+        .with(tag::ORD_TYPE, "2")
+        .with(tag::PRICE, tracked.price)
+        .with(tag::ORDER_QTY, tracked.quantity)
+        .with(tag::LEAVES_QTY, leaves)
+        .with(tag::CUM_QTY, tracked.cum)
+        .with(tag::AVG_PX, tracked.avg())
+        .with(tag::TRANSACT_TIME, timestamp())
+}
+
+struct Session {
+    state: AppState,
+    write: OwnedWriteHalf,
+    store: Arc<Mutex<Store>>,
+    comp_id: String,
+    id: i64,
+    heart_bt_int: Duration,
+    last_sent: Instant,
+    last_received: Instant,
+    test_req: Option<Instant>,
+    resending: bool,
+}
+
+impl Session {
+    async fn send(&mut self, mut message: Message) -> io::Result<()> {
+        let frame = {
+            let mut store = self.store.lock().unwrap();
+            let seq = store.next_out;
+            store.next_out += 1;
+            message.stamp(COMP_ID, &self.comp_id, seq, &timestamp());
+            let frame = message.encode();
+            if !msg_type::is_admin(message.msg_type()) {
+                store.sent.insert(seq, message);
+                if store.sent.len() > RESEND_CAPACITY {
+                    store.sent.pop_first();
+                }
+            }
+            frame
+        };
+        self.last_sent = Instant::now();
+        self.write.write_all(&frame).await
+    }
+
+    async fn logout(&mut self, text: &str) -> io::Result<bool> {
+        self.send(Message::new(msg_type::LOGOUT).with(tag::TEXT, text))
+            .await?;
+        Ok(false)
+    }
+
+    fn exec_id(&self) -> u64 {
+        self.store.lock().unwrap().exec_id()
+    }
+
+    /// Tracks order `seq`, entered until now. Both at once, so a report on it coming meanwhile
+    /// finds it one or the other.
+    fn track(&self, seq: i64, tracked: Tracked) {
+        let cl_ord_id = tracked.cl_ord_id.clone();
+        let mut store = self.store.lock().unwrap();
+        store.cl_ord.insert(cl_ord_id.clone(), seq);
+        store.orders.insert(seq, tracked);
+        store.entering.remove(&cl_ord_id);
+    }
+
+    fn cancel_reject(
+        &self,
+        seq: Option<i64>,
+        cl_ord_id: &str,
+        orig: &str,
+        response_to: &str,
+        reason: &str,
+        text: &str,
+    ) -> Message {
+        let ord_status = seq
+            .and_then(|seq| {
+                self.store
+                    .lock()
+                    .unwrap()
+                    .orders
+                    .get(&seq)
+                    .map(Tracked::status)
+            })
+            .unwrap_or("8");
+        Message::new(msg_type::ORDER_CANCEL_REJECT)
+            .with(
+                tag::ORDER_ID,
+                seq.map_or("NONE".to_string(), |seq| seq.to_string()),
+            )
+            .with(tag::CL_ORD_ID, cl_ord_id)
+            .with(tag::ORIG_CL_ORD_ID, orig)
+            .with(tag::ORD_STATUS, ord_status)
+            .with(tag::CXL_REJ_RESPONSE_TO, response_to)
+            .with(tag::CXL_REJ_REASON, reason)
+            .with(tag::TEXT, text)
+    }
+
+    /// Resends what the counterparty asked for, gap filling admin messages and anything no longer kept.
+    async fn resend(&mut self, begin: u64, end: u64) -> io::Result<()> {
+        let frames = {
+            let store = self.store.lock().unwrap();
+            let last = store.next_out - 1;
+            let end = if end == 0 || end > last { last } else { end };
+            let mut frames = Vec::new();
+            let mut gap = None;
+            let gap_fill = |from: u64, to: u64| {
+                let mut message = Message::new(msg_type::SEQUENCE_RESET)
+                    .with(tag::GAP_FILL_FLAG, "Y")
+                    .with(tag::NEW_SEQ_NO, to);
+                message.stamp(COMP_ID, &self.comp_id, from, &timestamp());
+                message
+                    .fields
+                    .insert(5, (tag::POSS_DUP_FLAG, "Y".to_string()));
+                message.encode()
+            };
+            for seq in begin.max(1)..=end {
+                match store.sent.get(&seq) {
+                    Some(sent) => {
+                        if let Some(from) = gap.take() {
+                            frames.push(gap_fill(from, seq));
+                        }
+                        let mut message = sent.clone();
+                        let orig = message
+                            .get(tag::SENDING_TIME)
+                            .unwrap_or_default()
+                            .to_string();
+                        message.stamp(COMP_ID, &self.comp_id, seq, &timestamp());
+                        message
+                            .fields
+                            .insert(5, (tag::POSS_DUP_FLAG, "Y".to_string()));
+                        message.fields.insert(6, (tag::ORIG_SENDING_TIME, orig));
+                        frames.push(message.encode());
+                    }
+                    None => {
+                        gap.get_or_insert(seq);
+                    }
+                }
+            }
+            if let Some(from) = gap {
+                frames.push(gap_fill(from, end + 1));
+            }
+            frames
+        };
+        for frame in frames {
+            self.write.write_all(&frame).await?;
+        }
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Applies sequencing to an inbound message, then dispatches it. Returns whether to stay connected.
+    async fn receive(&mut self, message: Message) -> io::Result<bool> {
+        self.last_received = Instant::now();
+        self.test_req = None;
+        if message.get(tag::SENDER_COMP_ID) != Some(&self.comp_id)
+            || message.get(tag::TARGET_COMP_ID) != Some(COMP_ID)
+        {
+            return self.logout("CompID problem").await;
+        }
+        let Some(seq) = message.parse::<u64>(tag::MSG_SEQ_NUM) else {
+            return self.logout("MsgSeqNum missing").await;
+        };
+        let msg_type = message.msg_type().to_string();
+        if msg_type == msg_type::SEQUENCE_RESET && !message.flag(tag::GAP_FILL_FLAG) {
+            if let Some(new) = message.parse::<u64>(tag::NEW_SEQ_NO) {
+                self.store.lock().unwrap().next_in = new;
+                self.resending = false;
+            }
+            return Ok(true);
+        }
+        if msg_type == msg_type::RESEND_REQUEST {
+            let begin = message.parse(tag::BEGIN_SEQ_NO).unwrap_or(1);
+            let end = message.parse(tag::END_SEQ_NO).unwrap_or(0);
+            self.resend(begin, end).await?;
+        }
+        let expected = self.store.lock().unwrap().next_in;
+        if seq > expected {
+            if msg_type == msg_type::LOGOUT {
+                return Ok(false);
+            }
+            if !self.resending {
+                self.resending = true;
+                self.send(
+                    Message::new(msg_type::RESEND_REQUEST)
+                        .with(tag::BEGIN_SEQ_NO, expected)
+                        .with(tag::END_SEQ_NO, 0),
+                )
+                .await?;
+            }
+            return Ok(true);
+        }
+        if seq < expected {
+            if message.flag(tag::POSS_DUP_FLAG) {
+                return Ok(true);
+            }
+            return self
+                .logout(&format!(
+                    "MsgSeqNum too low, expecting {expected} but received {seq}"
+                ))
+                .await;
+        }
+        {
+            let mut store = self.store.lock().unwrap();
+            store.next_in = match message.parse::<u64>(tag::NEW_SEQ_NO) {
+                Some(new) if msg_type == msg_type::SEQUENCE_RESET && new > seq => new,
+                _ => seq + 1,
+            };
+        }
+        self.resending = false;
+        match msg_type.as_str() {
+            msg_type::HEARTBEAT
+            | msg_type::RESEND_REQUEST
+            | msg_type::REJECT
+            | msg_type::SEQUENCE_RESET => {}
+            msg_type::TEST_REQUEST => {
+                let mut heartbeat = Message::new(msg_type::HEARTBEAT);
+                if let Some(id) = message.get(tag::TEST_REQ_ID) {
+                    heartbeat = heartbeat.with(tag::TEST_REQ_ID, id);
+                }
+                self.send(heartbeat).await?;
+            }
+            msg_type::LOGOUT => {
+                self.send(Message::new(msg_type::LOGOUT)).await?;
+                return Ok(false);
+            }
+            msg_type::NEW_ORDER_SINGLE => self.new_order(&message).await?,
+            msg_type::ORDER_CANCEL_REQUEST => self.cancel(&message).await?,
+            msg_type::ORDER_CANCEL_REPLACE_REQUEST => self.replace(&message).await?,
+            _ => {
+                self.send(
+                    Message::new(msg_type::REJECT)
+                        .with(tag::REF_SEQ_NUM, seq)
+                        .with(tag::REF_MSG_TYPE, &msg_type)
+                        .with(tag::SESSION_REJECT_REASON, 11)
+                        .with(tag::TEXT, "Unsupported MsgType"),
+                )
+                .await?
+            }
+        }
+        Ok(true)
+    }
+
+    async fn new_order(&mut self, message: &Message) -> io::Result<()> {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let parsed = (
+            message.get(tag::SYMBOL),
+            message.get(tag::SIDE).and_then(dir),
+            message.parse::<Decimal>(tag::PRICE),
+            message.parse::<i64>(tag::ORDER_QTY),
+        );
+        let (code, dir, price, quantity) = match parsed {
+            _ if cl_ord_id.is_empty() => return self.reject(message, "ClOrdID missing").await,
+            _ if message.get(tag::ORD_TYPE) != Some("2") => {
+                return self
+                    .reject(message, "Only limit orders are supported")
+                    .await
+            }
+            (Some(code), Some(dir), Some(price), Some(quantity)) => {
+                (code.to_string(), dir, price, quantity)
+            }
+            _ => {
+                return self
+                    .reject(message, "Symbol, Side, Price or OrderQty missing")
+                    .await
+            }
+        };
+        let fresh = {
+            let mut store = self.store.lock().unwrap();
+            !store.cl_ord.contains_key(&cl_ord_id) && store.entering.insert(cl_ord_id.clone())
+        };
+        if !fresh {
+            return self.reject(message, "Duplicate ClOrdID").await;
+        }
+        let placed = self
+            .state
+            .place(
+                self.id,
+                order::Model {
+                    seq: 0,
+                    code: code.clone(),
+                    dir,
+                    price,
+                    quantity,
+                },
+                Some(cl_ord_id.clone()),
+            )
+            .await;
+        match placed {
+            Ok(Placed::Existing(_)) => {
+                self.store.lock().unwrap().entering.remove(&cl_ord_id);
+                self.reject(message, "Duplicate ClOrdID").await
+            }
+            Ok(Placed::New(seq)) => {
+                let tracked = Tracked {
+                    cl_ord_id: cl_ord_id.clone(),
+                    code,
+                    dir,
+                    price,
+                    quantity,
+                    cum: 0,
+                    turnover: Decimal::ZERO,
+                    pending: None,
+                    canceled: false,
+                };
+                let report = report(self.exec_id(), seq, &tracked, "0", "0");
+                self.track(seq, tracked);
+                self.send(report).await
+            }
+            Err(err) => {
+                self.store.lock().unwrap().entering.remove(&cl_ord_id);
+                self.reject(message, &err.to_string()).await
+            }
+        }
+    }
+
+    /// Rejects a new order with an execution report echoing what was sent.
+    async fn reject(&mut self, message: &Message, text: &str) -> io::Result<()> {
+        let mut report = Message::new(msg_type::EXECUTION_REPORT)
+            .with(tag::ORDER_ID, "NONE")
+            .with(tag::EXEC_ID, self.exec_id())
+            .with(tag::EXEC_TYPE, "8")
+            .with(tag::ORD_STATUS, "8");
+        for tag in [
+            tag::CL_ORD_ID,
+            tag::SYMBOL,
+            tag::SIDE,
+            tag::ORD_TYPE,
+            tag::PRICE,
+            tag::ORDER_QTY,
+        ] {
+            if let Some(value) = message.get(tag) {
+                report = report.with(tag, value);
+            }
+        }
+        self.send(
+            report
+                .with(tag::ORD_REJ_REASON, 99)
+                .with(tag::LEAVES_QTY, 0)
+                .with(tag::CUM_QTY, 0)
+                .with(tag::AVG_PX, 0)
+                .with(tag::TEXT, text)
+                .with(tag::TRANSACT_TIME, timestamp()),
+        )
+        .await
+    }
+
+    /// Finds the order a cancel or replace refers to, by `OrderID` or else by `OrigClOrdID`.
+    fn target(&self, message: &Message) -> Option<i64> {
+        message.parse(tag::ORDER_ID).or_else(|| {
+            let store = self.store.lock().unwrap();
+            store.cl_ord.get(message.get(tag::ORIG_CL_ORD_ID)?).copied()
+        })
+    }
+
+    fn set_pending(&self, seq: i64, pending: Option<Pending>) {
+        if let Some(tracked) = self.store.lock().unwrap().orders.get_mut(&seq) {
+            tracked.pending = pending;
+        }
+    }
+
+    async fn cancel(&mut self, message: &Message) -> io::Result<()> {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let orig = message
+            .get(tag::ORIG_CL_ORD_ID)
+            .unwrap_or_default()
+            .to_string();
+        let Some(seq) = self.target(message) else {
+            let reject = self.cancel_reject(None, &cl_ord_id, &orig, "1", "1", "Unknown order");
+            return self.send(reject).await;
+        };
+        self.set_pending(seq, Some(Pending::Cancel(cl_ord_id.clone())));
+        let text = match self.state.cancel_order(self.id, seq).await {
+            Ok(Some(_)) => return Ok(()),
+            Ok(None) => "Too late to cancel".to_string(),
+            Err(err) => err.to_string(),
+        };
+        self.set_pending(seq, None);
+        let reject = self.cancel_reject(Some(seq), &cl_ord_id, &orig, "1", "0", &text);
+        self.send(reject).await
+    }
+
+    async fn replace(&mut self, message: &Message) -> io::Result<()> {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let orig = message
+            .get(tag::ORIG_CL_ORD_ID)
+            .unwrap_or_default()
+            .to_string();
+        let Some(seq) = self.target(message) else {
+            let reject = self.cancel_reject(None, &cl_ord_id, &orig, "2", "1", "Unknown order");
+            return self.send(reject).await;
+        };
+        let (Some(price), Some(quantity)) = (
+            message.parse::<Decimal>(tag::PRICE),
+            message.parse::<i64>(tag::ORDER_QTY),
+        ) else {
+            let reject = self.cancel_reject(
+                Some(seq),
+                &cl_ord_id,
+                &orig,
+                "2",
+                "99",
+                "Price or OrderQty missing",
+            );
+            return self.send(reject).await;
+        };
+        self.set_pending(seq, Some(Pending::Replace));
+        let entering = self
+            .store
+            .lock()
+            .unwrap()
+            .entering
+            .insert(cl_ord_id.clone());
+        let replaced = self
+            .state
+            .amend(self.id, seq, price, quantity, Some(cl_ord_id.clone()))
+            .await;
+        let old = {
+            let mut store = self.store.lock().unwrap();
+            let old = store.orders.get_mut(&seq).map(|tracked| {
+                // A replaced order keeps its pending flag, as its cancel may still be on the way.
+                if !matches!(replaced, Ok(Placed::New(_))) {
+                    tracked.pending = None;
+                }
+                (
+                    tracked.cl_ord_id.clone(),
+                    tracked.code.clone(),
+                    tracked.dir,
+                    tracked.canceled,
+                )
+            });
+            // A replacement stays entering until it is tracked.
+            if entering && !(matches!(replaced, Ok(Placed::New(_))) && old.is_some()) {
+                store.entering.remove(&cl_ord_id);
+            }
+            old
+        };
+        match (replaced, old) {
+            (Ok(Placed::Existing(_)), _) => {
+                let reject = self.cancel_reject(
+                    Some(seq),
+                    &cl_ord_id,
+                    &orig,
+                    "2",
+                    "99",
+                    "Duplicate ClOrdID",
+                );
+                self.send(reject).await
+            }
+            (Ok(Placed::New(new)), Some((orig, code, dir, _))) => {
+                let tracked = Tracked {
+                    cl_ord_id: cl_ord_id.clone(),
+                    code,
+                    dir,
+                    price,
+                    quantity,
+                    cum: 0,
+                    turnover: Decimal::ZERO,
+                    pending: None,
+                    canceled: false,
+                };
+                let report =
+                    report(self.exec_id(), new, &tracked, "5", "0").with(tag::ORIG_CL_ORD_ID, orig);
+                self.track(new, tracked);
+                self.send(report).await
+            }
+            (Ok(Placed::New(new)), None) => {
+                let reject = self.cancel_reject(
+                    Some(new),
+                    &cl_ord_id,
+                    &orig,
+                    "2",
+                    "99",
+                    "Replaced an order not entered on this session",
+                );
+                self.send(reject).await
+            }
+            (Err(err), Some((_, _, _, true))) => {
+                // The original was taken off the book before the replacement failed.
+                let report = {
+                    let mut store = self.store.lock().unwrap();
+                    let exec_id = store.exec_id();
+                    report(exec_id, seq, &store.orders[&seq], "4", "4")
+                        .with(tag::TEXT, err.to_string())
+                };
+                self.send(report).await
+            }
+            (Err(err), _) => {
+                let reject =
+                    self.cancel_reject(Some(seq), &cl_ord_id, &orig, "2", "99", &err.to_string());
+                self.send(reject).await
+            }
+        }
+    }
+
+    /// Turns execution reports on orders entered on this session into FIX execution reports.
+    async fn deliver(&mut self, body: MsgBody) -> io::Result<()> {
+        let Some(execution) = execution(&body)? else {
+            return Ok(());
+        };
+        // New orders and rejections before the book are answered as they are entered.
+        let (Some(seq), false) = (execution.seq, execution.status == OrdStatus::New) else {
+            return Ok(());
+        };
+        let report = {
+            let mut store = self.store.lock().unwrap();
+            let Some(tracked) = store.orders.get_mut(&seq) else {
+                return Ok(());
+            };
+            tracked.cum = execution.cum_quantity;
+            tracked.turnover = execution.avg_price * Decimal::from(execution.cum_quantity);
+            if let OrdStatus::Canceled
+            | OrdStatus::Expired
+            | OrdStatus::Rejected
+            | OrdStatus::Replaced = execution.status
+            {
+                tracked.canceled = true;
+            }
+            if let (OrdStatus::Replaced, Some(Pending::Replace)) =
+                (execution.status, &tracked.pending)
+            {
+                return Ok(());
+            }
+            let exec_id = store.exec_id();
+            let tracked = &store.orders[&seq];
+            match execution.status {
+                OrdStatus::PartiallyFilled | OrdStatus::Filled => {
+                    report(exec_id, seq, tracked, "F", tracked.status())
+                        .with(tag::LAST_PX, execution.last_price.unwrap_or_default())
+                        .with(tag::LAST_QTY, execution.last_quantity.unwrap_or_default())
+                }
+                OrdStatus::Expired => report(exec_id, seq, tracked, "C", "C"),
+                OrdStatus::Rejected => report(exec_id, seq, tracked, "8", "8")
+                    .with(tag::TEXT, execution.text.unwrap_or_default()),
+                _ => match &tracked.pending {
+                    Some(Pending::Cancel(cl_ord_id)) => {
+                        let mut report = report(exec_id, seq, tracked, "4", "4");
+                        report.fields.retain(|(t, _)| *t != tag::CL_ORD_ID);
+                        report
+                            .with(tag::CL_ORD_ID, cl_ord_id)
+                            .with(tag::ORIG_CL_ORD_ID, &tracked.cl_ord_id)
+                    }
+                    _ => report(exec_id, seq, tracked, "4", "4"),
+                },
+            }
+        };
+        self.send(report).await
+    }
+
+    /// Sends heartbeats and test requests as `HeartBtInt` requires. Returns whether to stay connected.
+    async fn heartbeat(&mut self) -> io::Result<bool> {
+        if let Some(sent) = self.test_req {
+            if sent.elapsed() >= self.heart_bt_int {
+                return self.logout("TestRequest not answered").await;
+            }
+        } else if self.last_received.elapsed() >= self.heart_bt_int * 6 / 5 {
+            self.send(Message::new(msg_type::TEST_REQUEST).with(tag::TEST_REQ_ID, timestamp()))
+                .await?;
+            self.test_req = Some(Instant::now());
+            return Ok(true);
+        }
+        if self.last_sent.elapsed() >= self.heart_bt_int {
+            self.send(Message::new(msg_type::HEARTBEAT)).await?;
+        }
+        Ok(true)
+    }
+
+    async fn run(
+        &mut self,
+        inbound: &mut mpsc::Receiver<Result<Message, ::fix::Error>>,
+        mut inbox: Receiver,
+        unsent: Vec<MsgBody>,
+    ) -> io::Result<()> {
+        for body in unsent {
+            self.deliver(body).await?;
+        }
+        let mut ticker = time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                message = inbound.recv() => match message {
+                    Some(Ok(message)) => {
+                        if !self.receive(message).await? {
+                            return Ok(());
+                        }
+                    }
+                    // Garbled messages are dropped without consuming a sequence number.
+                    Some(Err(_)) => {}
+                    None => return Ok(()),
+                },
+                body = inbox.recv() => match body {
+                    Some(body) => self.deliver(body).await?,
+                    None => {
+                        self.logout("Too far behind on execution reports").await?;
+                        return Ok(());
+                    }
+                },
+                _ = ticker.tick() => {
+                    if !self.heartbeat().await? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.store.lock().unwrap().online = false;
+    }
+}
+
+/// Refuses a logon with a `Logout` outside of any session's sequence.
+async fn refuse(mut write: OwnedWriteHalf, target: &str, text: &str) {
+    let mut message = Message::new(msg_type::LOGOUT).with(tag::TEXT, text);
+    message.stamp(COMP_ID, target, 1, &timestamp());
+    write.write_all(&message.encode()).await.unwrap_or_default();
+}
+
+async fn session(state: AppState, stores: Stores, stream: TcpStream) {
+    let (read, write) = stream.into_split();
+    let (tx, mut inbound) = mpsc::channel(INBOUND_CAPACITY);
+    let reader = tokio::spawn(async move {
+        let mut read = BufReader::new(read);
+        while let Ok(Some(frame)) = ::fix::read_frame(&mut read).await {
+            if tx.send(Message::decode(&frame)).await.is_err() {
+                break;
+            }
+        }
+    });
+    let logon = match time::timeout(Duration::from_secs(10), inbound.recv()).await {
+        Ok(Some(Ok(logon))) if logon.msg_type() == msg_type::LOGON => logon,
+        _ => {
+            reader.abort();
+            return;
+        }
+    };
+    let comp_id = logon
+        .get(tag::SENDER_COMP_ID)
+        .unwrap_or_default()
+        .to_string();
+    if comp_id.is_empty() || logon.get(tag::TARGET_COMP_ID) != Some(COMP_ID) {
+        reader.abort();
+        return refuse(write, &comp_id, "CompID problem").await;
+    }
+    let account = match logon.parse::<i64>(tag::USERNAME) {
+        Some(id) => match ac::Entity::find_by_id(id).one(&state.db).await {
+            Ok(account) => account,
+            Err(err) => {
+                tracing::warn!("{comp_id}: logon failed: {err}");
+                reader.abort();
+                return refuse(write, &comp_id, "Logon failed, try again later").await;
+            }
+        },
+        None => None,
+    };
+    let id = match account {
+        Some(model)
+            if model.status != AcStatus::Closed
+                && crate::ac::verify(
+                    &state,
+                    &model,
+                    logon.get(tag::PASSWORD).unwrap_or_default(),
+                )
+                .await =>
+        {
+            model.id
+        }
+        _ => {
+            reader.abort();
+            return refuse(write, &comp_id, "Invalid Username or Password").await;
+        }
+    };
+    let reset = logon.flag(tag::RESET_SEQ_NUM_FLAG);
+    let store = stores
+        .entry(comp_id.clone())
+        .or_insert_with(|| Arc::new(Mutex::new(Store::new(id))))
+        .clone();
+    let taken = {
+        let mut store = store.lock().unwrap();
+        if store.online {
+            true
+        } else {
+            if store.id != id {
+                *store = Store::new(id);
+            } else if reset {
+                store.next_in = 1;
+                store.next_out = 1;
+                store.sent.clear();
+            }
+            store.online = true;
+            false
+        }
+    };
+    if taken {
+        reader.abort();
+        return refuse(write, &comp_id, "Session already logged on").await;
+    }
+    let heart_bt_int = logon
+        .parse::<u64>(tag::HEART_BT_INT)
+        .filter(|secs| *secs > 0)
+        .unwrap_or(30);
+    let mut session = Session {
+        state: state.clone(),
+        write,
+        store,
+        comp_id,
+        id,
+        heart_bt_int: Duration::from_secs(heart_bt_int),
+        last_sent: Instant::now(),
+        last_received: Instant::now(),
+        test_req: None,
+        resending: false,
+    };
+    let mut reply = Message::new(msg_type::LOGON)
+        .with(tag::ENCRYPT_METHOD, 0)
+        .with(tag::HEART_BT_INT, heart_bt_int);
+    if reset {
+        reply = reply.with(tag::RESET_SEQ_NUM_FLAG, "Y");
+    }
+    let (inbox, unsent) = state
+        .online_taking(id, "fix", takes(session.store.clone()))
+        .await;
+    let started = async {
+        session.send(reply).await?;
+        let expected = session.store.lock().unwrap().next_in;
+        match logon.parse::<u64>(tag::MSG_SEQ_NUM) {
+            Some(seq) if seq == expected => session.store.lock().unwrap().next_in += 1,
+            Some(seq) if seq > expected => {
+                session.resending = true;
+                session
+                    .send(
+                        Message::new(msg_type::RESEND_REQUEST)
+                            .with(tag::BEGIN_SEQ_NO, expected)
+                            .with(tag::END_SEQ_NO, 0),
+                    )
+                    .await?;
+            }
+            _ => {
+                session
+                    .logout(&format!("MsgSeqNum too low, expecting {expected}"))
+                    .await?;
+                return Ok(false);
+            }
+        }
+        io::Result::Ok(true)
+    };
+    if let Ok(true) = started.await {
+        session
+            .run(&mut inbound, inbox, unsent)
+            .await
+            .unwrap_or_default();
+    }
+    reader.abort();
+}
+
+/// Accepts FIX sessions until the listener fails.
+pub async fn serve(state: AppState, listener: TcpListener) {
+    let stores = Stores::default();
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(session(state.clone(), stores.clone(), stream));
+    }
+}
@@ -0,0 +1,91 @@
+use axum::extract::{Json, Query, State};
+use axum::{routing, Router};
+use chrono::{DateTime, FixedOffset};
+use entity::order_history;
+use entity::sea_orm_active_enums::OrdStatus;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::state::AppState;
+use crate::tape::{Fill, Own, Page};
+
+/// Filters an account's orders. Pages run from newest to oldest;
+/// pass the returned `next` as `cursor` for the following page.
+#[derive(Deserialize)]
+pub struct Filter {
+    pub status: Option<OrdStatus>,
+    pub code: Option<String>,
+    pub from: Option<DateTime<FixedOffset>>,
+    pub to: Option<DateTime<FixedOffset>>,
+    pub cursor: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+/// Names an order by `seq` or, failing that, by `cl_ord_id`.
+#[derive(Deserialize)]
+pub struct One {
+    pub id: i64,
+    pub seq: Option<i64>,
+    pub cl_ord_id: Option<String>,
+}
+
+impl Filter {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(100).min(1000)
+    }
+}
+
+async fn find(state: &AppState, one: One) -> Result<order_history::Model, Error> {
+    state
+        .store
+        .order(one)
+        .await?
+        .ok_or(Error::NotFound("order"))
+}
+
+/// One order of an account, by seq or client order id.
+async fn view_order(
+    State(state): State<AppState>,
+    Query(one): Query<One>,
+) -> Result<Json<order_history::Model>, Error> {
+    Ok(Json(find(&state, one).await?))
+}
+
+/// The trades that filled one order of an account, oldest first.
+async fn view_fills(
+    State(state): State<AppState>,
+    Query(one): Query<One>,
+) -> Result<Json<Vec<Fill>>, Error> {
+    let order = find(&state, one).await?;
+    let fills = state.store.fills(&order).await?;
+    Ok(Json(
+        fills
+            .into_iter()
+            .map(|rec| Fill {
+                rec,
+                dir: order.dir,
+            })
+            .collect(),
+    ))
+}
+
+/// An account's orders, working or done, by when they were placed.
+async fn view_orders(
+    State(state): State<AppState>,
+    Query(Own { id }): Query<Own>,
+    Query(filter): Query<Filter>,
+) -> Result<Json<Page<order_history::Model>>, Error> {
+    let limit = filter.limit();
+    let items = state.store.order_history(id, &filter).await?;
+    let next = (items.len() as u64 == limit)
+        .then(|| items.last().map(|order| order.seq))
+        .flatten();
+    Ok(Json(Page { items, next }))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/view_order", routing::get(view_order))
+        .route("/view_orders", routing::get(view_orders))
+        .route("/view_fills", routing::get(view_fills))
+}
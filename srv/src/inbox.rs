@@ -0,0 +1,136 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive};
+use axum::response::{IntoResponse, Sse};
+use axum::{routing, Router};
+use chrono::Utc;
+use entity::msg;
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tokio::time::{self, Duration, MissedTickBehavior};
+
+use crate::error::Error;
+use crate::msg::{MsgBody, SessionInfo};
+use crate::state::AppState;
+use crate::storage::Effect;
+use crate::tape::{Own, Page};
+
+/// Filters an account's messages. Pages run from newest to oldest;
+/// pass the returned `next` as `cursor` for the following page.
+#[derive(Deserialize)]
+pub struct Filter {
+    /// Only messages not acknowledged yet, or only those acknowledged.
+    pub unacked: Option<bool>,
+    pub cursor: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+impl Filter {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(100).min(1000)
+    }
+}
+
+/// Streams the messages of an account, each under its ack as the event id: first those it has
+/// not been sent, then the rest as they come. A client reconnecting with `Last-Event-ID` is
+/// first sent every message after that one. The stream ends should the client fall too far
+/// behind; every other session of the account goes on.
+async fn msg(
+    State(state): State<AppState>,
+    Query(Own { id }): Query<Own>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
+    let last = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok()?.trim().parse::<i64>().ok());
+    if last.is_some() {
+        // Messages sent since the client left may not be kept yet.
+        state.projector.flush().await?;
+    }
+    let (rx, unsent) = state.online(id, "sse").await;
+    let mut backlog: BTreeMap<i64, MsgBody> =
+        unsent.into_iter().map(|body| (body.ack, body)).collect();
+    if let Some(last) = last {
+        for msg in state.store.msgs_after(id, last).await? {
+            backlog.entry(msg.ack).or_insert_with(|| MsgBody::from(msg));
+        }
+    }
+    // Those sent since going online may be in the backlog already.
+    let seen = backlog.last_key_value().map_or(0, |(ack, _)| *ack);
+    let live = stream::unfold(rx, |mut rx| async {
+        let body = rx.recv().await?;
+        Some((body, rx))
+    })
+    .filter(move |body| {
+        let fresh = body.ack > seen;
+        async move { fresh }
+    });
+    Ok(Sse::new(
+        stream::iter(backlog.into_values())
+            .chain(live)
+            .map(|body: MsgBody| {
+                Event::default()
+                    .event(body.name)
+                    .id(body.ack.to_string())
+                    .json_data(body.data)
+            }),
+    )
+    .keep_alive(KeepAlive::default()))
+}
+
+/// Acknowledges every message of an account up to the ack given.
+async fn ack(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(ack): Json<i64>,
+) -> Result<StatusCode, Error> {
+    state.ack(id, ack).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// An account's messages kept, delivered or not, by ack.
+async fn inbox(
+    State(state): State<AppState>,
+    Query(Own { id }): Query<Own>,
+    Query(filter): Query<Filter>,
+) -> Result<Json<Page<msg::Model>>, Error> {
+    let limit = filter.limit();
+    let items = state.store.inbox(id, &filter).await?;
+    let next = (items.len() as u64 == limit)
+        .then(|| items.last().map(|msg| msg.ack))
+        .flatten();
+    Ok(Json(Page { items, next }))
+}
+
+/// The live sessions of an account.
+async fn sessions(
+    State(state): State<AppState>,
+    Query(Own { id }): Query<Own>,
+) -> Result<Json<Vec<SessionInfo>>, Error> {
+    state.account(id).await?;
+    Ok(Json(state.msg_box.sessions(id)))
+}
+
+/// Every `inbox.purge_interval_secs`, deletes the messages acknowledged longer than
+/// `inbox.retention_secs` ago.
+pub async fn purge(state: AppState) {
+    let config = state.config.inbox.clone();
+    let retention = chrono::Duration::seconds(config.retention_secs as i64);
+    let mut interval = time::interval(Duration::from_secs(config.purge_interval_secs));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let before = Utc::now().fixed_offset() - retention;
+        tracing::debug!("purging messages acknowledged before {before}");
+        state.projector.after(Effect::Purge(before));
+    }
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/msg", routing::get(msg))
+        .route("/ack/:id", routing::post(ack))
+        .route("/inbox", routing::get(inbox))
+        .route("/sessions", routing::get(sessions))
+}
@@ -0,0 +1,317 @@
+//! The binary market data feed. Every security's ticks are translated into `itch` messages and
+//! sequenced into one feed, published over TCP and multicast UDP. The latest messages of the session
+//! stay in memory so the retransmission service can fill a gap in them.
+
+use ::itch::{Body, Message, Packet, Request, Side, State, MAX_PAYLOAD, PRICE_SCALE};
+use chrono::Utc;
+use entity::sea_orm_active_enums::Dir;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
+use std::net::SocketAddrV4;
+use std::sync::{Arc, RwLock};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{self, Duration, Instant};
+
+use crate::book::Change;
+use crate::l3::anonymous;
+use crate::period::Period;
+use crate::security::{Security, Tick, Update};
+
+pub struct Itch {
+    session: [u8; 10],
+    tx: mpsc::Sender<Vec<Vec<u8>>>,
+    log: RwLock<Log>,
+    /// Messages kept in the log.
+    retention: usize,
+    packets: broadcast::Sender<Arc<Vec<u8>>>,
+}
+
+/// The latest messages of the session.
+struct Log {
+    /// The sequence number of the first message kept.
+    first: u64,
+    messages: VecDeque<Vec<u8>>,
+}
+
+fn side(dir: Dir) -> Side {
+    match dir {
+        Dir::Buy => Side::Buy,
+        Dir::Sell => Side::Sell,
+    }
+}
+
+fn price(price: Decimal) -> i64 {
+    (price * Decimal::from(PRICE_SCALE))
+        .trunc()
+        .to_i64()
+        .unwrap_or_default()
+}
+
+fn add(change: &Change) -> Body {
+    Body::AddOrder {
+        order: anonymous(change.seq),
+        side: side(change.dir),
+        shares: change.quantity as u64,
+        price: price(change.price),
+    }
+}
+
+fn translate(code: &str, seq: u64, update: &Update, changes: &[Change]) -> Vec<Vec<u8>> {
+    let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+    let message = |body| {
+        Message {
+            timestamp,
+            code: code.to_string(),
+            book_seq: seq,
+            body,
+        }
+        .encode()
+    };
+    let executions = |at: Decimal| {
+        changes.iter().map(move |change| Body::OrderExecuted {
+            order: anonymous(change.seq),
+            shares: -change.quantity as u64,
+            price: price(at),
+            match_number: seq,
+        })
+    };
+    match *update {
+        Update::Order(..) => changes
+            .iter()
+            .map(|change| match change.quantity {
+                quantity if quantity > 0 => add(change),
+                quantity => Body::OrderCancel {
+                    order: anonymous(change.seq),
+                    shares: -quantity as u64,
+                },
+            })
+            .map(message)
+            .collect(),
+        Update::Trade(aggressor, at, quantity) => executions(at)
+            .chain([match aggressor {
+                Some(dir) => Body::Trade {
+                    side: side(dir),
+                    shares: quantity as u64,
+                    price: price(at),
+                    match_number: seq,
+                },
+                None => Body::CrossTrade {
+                    shares: quantity as u64,
+                    price: price(at),
+                    match_number: seq,
+                },
+            }])
+            .map(message)
+            .collect(),
+        Update::Reset => std::iter::once(Body::BookReset)
+            .chain(changes.iter().map(add))
+            .map(message)
+            .collect(),
+        Update::Imbalance(imbalance) => vec![message(Body::Imbalance {
+            paired: imbalance.paired as u64,
+            imbalance: imbalance.surplus as u64,
+            direction: imbalance.dir.map(side),
+            price: price(imbalance.price),
+        })],
+        Update::Phase(period) => vec![message(Body::TradingState {
+            state: match period {
+                Period::Prepare => State::Prepare,
+                Period::Call => State::Call,
+                Period::Continuous => State::Continuous,
+                Period::Suspense => State::Halted,
+            },
+        })],
+    }
+}
+
+impl Itch {
+    /// Keeps up to `capacity` packets for subscribers falling behind, and the last `retention`
+    /// messages for retransmission. Up to `capacity` batches of messages wait to be sequenced.
+    pub fn new(capacity: usize, retention: usize) -> Arc<Self> {
+        let (tx, mut rx) = mpsc::channel::<Vec<Vec<u8>>>(capacity);
+        let mut session = [b' '; 10];
+        session.copy_from_slice(Utc::now().format("%Y%m%d%H").to_string().as_bytes());
+        let itch = Arc::new(Self {
+            session,
+            tx,
+            log: RwLock::new(Log {
+                first: 1,
+                messages: VecDeque::new(),
+            }),
+            retention,
+            packets: broadcast::Sender::new(capacity),
+        });
+        tokio::spawn({
+            let itch = itch.clone();
+            async move {
+                let mut heartbeat = time::interval(Duration::from_secs(1));
+                let mut last = Instant::now();
+                loop {
+                    tokio::select! {
+                        batch = rx.recv() => {
+                            let Some(mut messages) = batch else {
+                                break;
+                            };
+                            while let Ok(batch) = rx.try_recv() {
+                                messages.extend(batch);
+                            }
+                            itch.sequence(messages);
+                            last = Instant::now();
+                        }
+                        _ = heartbeat.tick() => {
+                            if last.elapsed() >= Duration::from_secs(1) {
+                                itch.sequence(Vec::new());
+                                last = Instant::now();
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        itch
+    }
+
+    /// Numbers `messages`, logs them and broadcasts them in packets; no messages sends a heartbeat.
+    fn sequence(&self, messages: Vec<Vec<u8>>) {
+        let mut log = self.log.write().unwrap();
+        let mut packet = Packet {
+            session: self.session,
+            seq: log.first + log.messages.len() as u64,
+            messages: Vec::new(),
+        };
+        for message in messages {
+            if !packet.is_empty() && packet.len() + 2 + message.len() > MAX_PAYLOAD {
+                self.packets
+                    .send(Arc::new(packet.encode()))
+                    .unwrap_or_default();
+                packet.seq += packet.messages.len() as u64;
+                packet.messages.clear();
+            }
+            log.messages.push_back(message.clone());
+            if log.messages.len() > self.retention {
+                log.messages.pop_front();
+                log.first += 1;
+            }
+            packet.messages.push(message);
+        }
+        self.packets
+            .send(Arc::new(packet.encode()))
+            .unwrap_or_default();
+    }
+
+    /// Translates the ticks of `security` into the feed, waiting while the feed is behind. A
+    /// lagging translator starts the security over with a book reset, as does a rewritten book.
+    pub fn attach(&self, code: Arc<str>, security: &Arc<Security>) {
+        let mut rx = security.bc.subscribe();
+        let security = Arc::downgrade(security);
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let mut seen = 0;
+            loop {
+                let messages = match rx.recv().await {
+                    Ok(Tick { seq, .. }) if seq <= seen => continue,
+                    Ok(Tick {
+                        seq,
+                        update,
+                        changes,
+                    }) => translate(&code, seq, &update, &changes),
+                    Err(RecvError::Lagged(_)) => {
+                        let Some(security) = security.upgrade() else {
+                            break;
+                        };
+                        let (seq, orders) = security.orders().await;
+                        seen = seq;
+                        translate(&code, seq, &Update::Reset, &orders)
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if tx.send(messages).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Answers a retransmission request with as many of the asked messages still kept as fit in
+    /// one packet, which starts at the first of them kept.
+    fn retransmit(&self, request: Request) -> Packet {
+        let log = self.log.read().unwrap();
+        let from = request.seq.max(log.first);
+        let end = request.seq.saturating_add(request.count as u64);
+        let mut packet = Packet {
+            session: self.session,
+            seq: from,
+            messages: Vec::new(),
+        };
+        let kept = log.messages.iter().skip((from - log.first) as usize);
+        for message in kept.take(end.saturating_sub(from) as usize) {
+            if packet.len() + 2 + message.len() > MAX_PAYLOAD {
+                break;
+            }
+            packet.messages.push(message.clone());
+        }
+        packet
+    }
+}
+
+/// Streams the feed to TCP clients from the moment they connect.
+async fn stream(itch: Arc<Itch>, listener: TcpListener) {
+    while let Ok((mut stream, _)) = listener.accept().await {
+        let mut rx = itch.packets.subscribe();
+        tokio::spawn(async move {
+            // A client too slow to keep up is dropped; it can reconnect and fill the gap.
+            while let Ok(packet) = rx.recv().await {
+                if stream.write_all(&packet).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+async fn multicast(itch: Arc<Itch>, socket: UdpSocket, group: SocketAddrV4) {
+    let mut rx = itch.packets.subscribe();
+    loop {
+        match rx.recv().await {
+            Ok(packet) => {
+                socket.send_to(&packet, group).await.unwrap_or_default();
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn retransmission(itch: Arc<Itch>, socket: UdpSocket) {
+    let mut buf = [0; ::itch::HEADER_LEN];
+    while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+        match Request::decode(&buf[..len]) {
+            Ok(request) if request.session == itch.session => {
+                let packet = itch.retransmit(request);
+                socket
+                    .send_to(&packet.encode(), from)
+                    .await
+                    .unwrap_or_default();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub async fn serve(
+    itch: Arc<Itch>,
+    listener: TcpListener,
+    socket: UdpSocket,
+    group: SocketAddrV4,
+    retransmitter: UdpSocket,
+) {
+    tokio::join!(
+        stream(itch.clone(), listener),
+        multicast(itch.clone(), socket, group),
+        retransmission(itch, retransmitter),
+    );
+}
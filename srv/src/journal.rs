@@ -0,0 +1,456 @@
+//! The journal: every command that changes the books, numbered and appended to a file before it
+//! reaches matching. Replaying it rebuilds the books; the database only follows it.
+//!
+//! The file starts with `SMJ\0` and a little endian `u16` version. Each record after that is the
+//! `u32` length and CRC-32 of its body, then the body: the seq, the time in microseconds since
+//! the epoch, a tag naming the command and the command's fields.
+
+use chrono::{DateTime, Utc};
+use entity::order;
+use entity::sea_orm_active_enums::OrdStatus;
+use rust_decimal::Decimal;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::codec::{Corrupt, Reader, Writer};
+use crate::period::Period;
+
+const MAGIC: &[u8; 4] = b"SMJ\0";
+const VERSION: u16 = 1;
+const HEADER: u64 = 6;
+/// Longer records are taken for garbage.
+const MAX_RECORD: u32 = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    /// Account `id` places `order`, which takes the seq of the entry.
+    Place {
+        id: i64,
+        order: order::Model,
+        cl_ord_id: Option<String>,
+    },
+    /// Order `seq` of account `id` leaves the book, reported with `status`.
+    Cancel {
+        id: i64,
+        seq: i64,
+        status: OrdStatus,
+    },
+    /// Order `seq` of account `id` is replaced by one at `price` for `quantity`, which takes the
+    /// seq of the entry.
+    Amend {
+        id: i64,
+        seq: i64,
+        price: Decimal,
+        quantity: i64,
+        cl_ord_id: Option<String>,
+    },
+    Phase(Period),
+    /// `from` shares of `code` become `to`; prices are rounded to `tick` and orders down to whole
+    /// lots of `lot`.
+    Split {
+        code: String,
+        from: i64,
+        to: i64,
+        tick: Decimal,
+        lot: i64,
+    },
+    Rename {
+        code: String,
+        new: String,
+    },
+    /// `code` leaves the market, its book with it.
+    Delist {
+        code: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub seq: i64,
+    pub at: DateTime<Utc>,
+    pub command: Command,
+}
+
+pub struct Journal {
+    file: File,
+    last: i64,
+    fsync: bool,
+    /// Where the last whole record ends.
+    end: u64,
+    /// A failed write could not be taken back, so nothing more is appended.
+    poisoned: bool,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if need be, with the entries it holds. A last
+    /// record cut short or garbled, as by a crash while it was written, is cut off; a bad record
+    /// with more after it is corruption, and the journal is not opened.
+    pub fn open(path: &Path, fsync: bool) -> io::Result<(Self, Vec<Entry>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        if file.metadata()?.len() == 0 {
+            let mut header = MAGIC.to_vec();
+            header.extend(VERSION.to_le_bytes());
+            file.write_all(&header)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&file);
+        let mut header = [0; HEADER as usize];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a journal"));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("journal version {version}, expected {VERSION}"),
+            ));
+        }
+
+        let size = file.metadata()?.len();
+        let mut entries = Vec::new();
+        let mut end = HEADER;
+        let mut last = 0;
+        loop {
+            let body = match read_record(&mut reader)? {
+                Record::Body(body) => body,
+                Record::End => break,
+                Record::Bad { len } if end + len >= size => break,
+                Record::Bad { .. } => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("journal record at byte {end} is corrupt, with records after it"),
+                    ))
+                }
+            };
+            let entry = decode(&body).map_err(|Corrupt| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("journal record at byte {end} does not decode"),
+                )
+            })?;
+            if entry.seq <= last {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("journal seq {} follows {last}", entry.seq),
+                ));
+            }
+            last = entry.seq;
+            end += 8 + body.len() as u64;
+            entries.push(entry);
+        }
+        drop(reader);
+        if size > end {
+            tracing::warn!("{}: cutting off a torn record at {end}", path.display());
+            file.set_len(end)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok((
+            Self {
+                file,
+                last,
+                fsync,
+                end,
+                poisoned: false,
+            },
+            entries,
+        ))
+    }
+
+    /// Numbers the next entry after `seq` if the journal is behind it, as when the database
+    /// predates the journal.
+    pub fn skip_to(&mut self, seq: i64) {
+        self.last = self.last.max(seq);
+    }
+
+    /// The seq of the last entry.
+    pub fn last(&self) -> i64 {
+        self.last
+    }
+
+    /// Writes `command` down under the next seq, durably if so configured. A record that fails
+    /// to be written is cut off again, so the entries after it are not lost behind it.
+    pub fn append(&mut self, at: DateTime<Utc>, mut command: Command) -> io::Result<Entry> {
+        if self.poisoned {
+            return Err(io::Error::other(
+                "the journal takes nothing more after a write that could not be taken back",
+            ));
+        }
+        let seq = self.last + 1;
+        if let Command::Place { order, .. } = &mut command {
+            order.seq = seq;
+        }
+        let entry = Entry { seq, at, command };
+        let body = encode(&entry);
+        let mut record = Vec::with_capacity(8 + body.len());
+        record.extend((body.len() as u32).to_le_bytes());
+        record.extend(crc32fast::hash(&body).to_le_bytes());
+        record.extend(body);
+        if let Err(err) = self.write(&record) {
+            if let Err(cut) = self.file.set_len(self.end) {
+                tracing::error!("journal: cannot cut off a failed write: {cut}");
+                self.poisoned = true;
+            }
+            return Err(err);
+        }
+        self.end += record.len() as u64;
+        self.last = entry.seq;
+        Ok(entry)
+    }
+
+    fn write(&mut self, record: &[u8]) -> io::Result<()> {
+        self.file.write_all(record)?;
+        if self.fsync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+enum Record {
+    Body(Vec<u8>),
+    /// The journal ends here, maybe in the middle of a record's head.
+    End,
+    /// A record `len` bytes long with its head, which is cut short or garbled.
+    Bad {
+        len: u64,
+    },
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<Record> {
+    let mut head = [0; 8];
+    match reader.read_exact(&mut head) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(Record::End),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_le_bytes([head[0], head[1], head[2], head[3]]);
+    let crc = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+    let bad = Record::Bad {
+        len: 8 + u64::from(len),
+    };
+    if len > MAX_RECORD {
+        return Ok(bad);
+    }
+    let mut body = vec![0; len as usize];
+    match reader.read_exact(&mut body) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(bad),
+        Err(err) => return Err(err),
+    }
+    Ok(match crc32fast::hash(&body) == crc {
+        true => Record::Body(body),
+        false => bad,
+    })
+}
+
+fn encode(entry: &Entry) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.i64(entry.seq);
+    w.i64(entry.at.timestamp_micros());
+    match &entry.command {
+        Command::Place {
+            id,
+            order,
+            cl_ord_id,
+        } => {
+            w.u8(1);
+            w.i64(*id);
+            w.str(&order.code);
+            w.dir(order.dir);
+            w.decimal(order.price);
+            w.i64(order.quantity);
+            w.opt_str(cl_ord_id.as_deref());
+        }
+        Command::Cancel { id, seq, status } => {
+            w.u8(2);
+            w.i64(*id);
+            w.i64(*seq);
+            w.status(*status);
+        }
+        Command::Amend {
+            id,
+            seq,
+            price,
+            quantity,
+            cl_ord_id,
+        } => {
+            w.u8(3);
+            w.i64(*id);
+            w.i64(*seq);
+            w.decimal(*price);
+            w.i64(*quantity);
+            w.opt_str(cl_ord_id.as_deref());
+        }
+        Command::Phase(period) => {
+            w.u8(4);
+            w.period(*period);
+        }
+        Command::Split {
+            code,
+            from,
+            to,
+            tick,
+            lot,
+        } => {
+            w.u8(7);
+            w.str(code);
+            w.i64(*from);
+            w.i64(*to);
+            w.decimal(*tick);
+            w.i64(*lot);
+        }
+        Command::Rename { code, new } => {
+            w.u8(6);
+            w.str(code);
+            w.str(new);
+        }
+        Command::Delist { code } => {
+            w.u8(8);
+            w.str(code);
+        }
+    }
+    w.0
+}
+
+fn decode(body: &[u8]) -> Result<Entry, Corrupt> {
+    let mut r = Reader(body);
+    let seq = r.i64()?;
+    let at = DateTime::from_timestamp_micros(r.i64()?).ok_or(Corrupt)?;
+    let command = match r.u8()? {
+        1 => {
+            let id = r.i64()?;
+            let code = r.str()?;
+            let dir = r.dir()?;
+            let price = r.decimal()?;
+            let quantity = r.i64()?;
+            Command::Place {
+                id,
+                order: order::Model {
+                    seq,
+                    code,
+                    dir,
+                    price,
+                    quantity,
+                },
+                cl_ord_id: r.opt_str()?,
+            }
+        }
+        2 => Command::Cancel {
+            id: r.i64()?,
+            seq: r.i64()?,
+            status: r.status()?,
+        },
+        3 => Command::Amend {
+            id: r.i64()?,
+            seq: r.i64()?,
+            price: r.decimal()?,
+            quantity: r.i64()?,
+            cl_ord_id: r.opt_str()?,
+        },
+        4 => Command::Phase(r.period()?),
+        // Splits journaled before lot sizes were kept rounded orders to single shares.
+        5 => Command::Split {
+            code: r.str()?,
+            from: r.i64()?,
+            to: r.i64()?,
+            tick: r.decimal()?,
+            lot: 1,
+        },
+        6 => Command::Rename {
+            code: r.str()?,
+            new: r.str()?,
+        },
+        7 => Command::Split {
+            code: r.str()?,
+            from: r.i64()?,
+            to: r.i64()?,
+            tick: r.decimal()?,
+            lot: r.i64()?,
+        },
+        8 => Command::Delist { code: r.str()? },
+        _ => return Err(Corrupt),
+    };
+    r.end()?;
+    Ok(Entry { seq, at, command })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, phases: &[Period]) -> Vec<u64> {
+        let (mut journal, _) = Journal::open(path, false).unwrap();
+        phases
+            .iter()
+            .map(|phase| {
+                journal.append(Utc::now(), Command::Phase(*phase)).unwrap();
+                journal.end
+            })
+            .collect()
+    }
+
+    fn seqs(entries: &[Entry]) -> Vec<i64> {
+        entries.iter().map(|entry| entry.seq).collect()
+    }
+
+    #[test]
+    fn cuts_off_a_torn_last_record() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("srv.journal");
+        let ends = write(&path, &[Period::Call, Period::Continuous, Period::Suspense]);
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(ends[2] - 3)
+            .unwrap();
+
+        let (mut journal, entries) = Journal::open(&path, false).unwrap();
+        assert_eq!(seqs(&entries), [1, 2]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), ends[1]);
+        let entry = journal
+            .append(Utc::now(), Command::Phase(Period::Suspense))
+            .unwrap();
+        assert_eq!(entry.seq, 3);
+        drop(journal);
+        assert_eq!(seqs(&Journal::open(&path, false).unwrap().1), [1, 2, 3]);
+    }
+
+    #[test]
+    fn cuts_off_a_garbled_last_record() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("srv.journal");
+        let ends = write(&path, &[Period::Call, Period::Continuous]);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[ends[1] as usize - 1] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+
+        let (_, entries) = Journal::open(&path, false).unwrap();
+        assert_eq!(seqs(&entries), [1]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), ends[0]);
+    }
+
+    #[test]
+    fn refuses_a_bad_record_before_others() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("srv.journal");
+        let ends = write(&path, &[Period::Call, Period::Continuous, Period::Suspense]);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[ends[1] as usize - 1] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+
+        let err = Journal::open(&path, false).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,198 @@
+use axum::extract::{Path, State};
+use axum::response::sse::{Event as SseEvent, KeepAlive};
+use axum::response::{IntoResponse, Sse};
+use axum::{routing, Router};
+use blake2::digest::consts::{U64, U8};
+use blake2::digest::generic_array::GenericArray;
+use blake2::digest::{Digest, KeyInit, Mac};
+use blake2::{Blake2b512, Blake2bMac};
+use entity::sea_orm_active_enums::Dir;
+use futures::{Stream, StreamExt};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::book::Change;
+use crate::error::Error;
+use crate::security::{Security, Tick, Update};
+use crate::state::AppState;
+
+static KEY: OnceLock<GenericArray<u8, U64>> = OnceLock::new();
+
+/// Keys the public ids of orders with `key`, once at startup before any id is taken.
+pub fn set_key(key: &str) {
+    if KEY.set(Blake2b512::digest(key)).is_err() {
+        tracing::warn!("the key to public order ids was set already");
+    }
+}
+
+/// The public id of an order: stable for as long as the key is, but not its seq, so the feed
+/// cannot be joined against anything an account sees about its own orders.
+pub fn anonymous(seq: i64) -> u64 {
+    let key = KEY.get_or_init(|| Blake2b512::digest(""));
+    let mut mac = <Blake2bMac<U8> as KeyInit>::new(key);
+    mac.update(&seq.to_le_bytes());
+    u64::from_le_bytes(mac.finalize().into_bytes().into())
+}
+
+#[derive(Serialize)]
+pub struct Order {
+    pub id: u64,
+    pub dir: Dir,
+    pub price: Decimal,
+    pub quantity: i64,
+}
+
+impl From<&Change> for Order {
+    fn from(change: &Change) -> Self {
+        Self {
+            id: anonymous(change.seq),
+            dir: change.dir,
+            price: change.price,
+            quantity: change.quantity,
+        }
+    }
+}
+
+/// An order-by-order update. Every event carries the book sequence of the tick behind it;
+/// a snapshot lists the resting orders in time priority as of `seq`.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum Event {
+    Snapshot {
+        seq: u64,
+        orders: Vec<Order>,
+    },
+    Add {
+        seq: u64,
+        #[serde(flatten)]
+        order: Order,
+    },
+    /// Part of the order was taken off the book; it keeps its place in the queue.
+    Reduce {
+        seq: u64,
+        id: u64,
+        quantity: i64,
+    },
+    Execute {
+        seq: u64,
+        id: u64,
+        price: Decimal,
+        quantity: i64,
+    },
+    /// What was left of the order was taken off the book.
+    Delete {
+        seq: u64,
+        id: u64,
+    },
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Snapshot { .. } => "snapshot",
+            Event::Add { .. } => "add",
+            Event::Reduce { .. } => "reduce",
+            Event::Execute { .. } => "execute",
+            Event::Delete { .. } => "delete",
+        }
+    }
+
+    fn seq(&self) -> u64 {
+        match *self {
+            Event::Snapshot { seq, .. }
+            | Event::Add { seq, .. }
+            | Event::Reduce { seq, .. }
+            | Event::Execute { seq, .. }
+            | Event::Delete { seq, .. } => seq,
+        }
+    }
+}
+
+/// Like [`Security::feed`], but order by order: a snapshot, then every change in book sequence,
+/// starting over with a snapshot after a lag or a rewritten book.
+pub fn stream(security: Arc<Security>) -> impl Stream<Item = Event> {
+    async_stream::stream! {
+        let mut rx = security.bc.subscribe();
+        let mut snapshot = Some(security.orders().await);
+        let mut seq = 0;
+        let mut resting = HashMap::new();
+        loop {
+            if let Some((at, orders)) = snapshot.take() {
+                seq = at;
+                resting = orders.iter().map(|change| (change.seq, change.quantity)).collect();
+                yield Event::Snapshot {
+                    seq,
+                    orders: orders.iter().map(Order::from).collect(),
+                };
+            }
+            match rx.recv().await {
+                Ok(tick) if tick.seq <= seq => {}
+                Ok(Tick {
+                    update: Update::Reset,
+                    ..
+                })
+                | Err(RecvError::Lagged(_)) => snapshot = Some(security.orders().await),
+                Ok(Tick {
+                    seq: next,
+                    update,
+                    changes,
+                }) => {
+                    seq = next;
+                    for change in changes {
+                        let left = resting.get(&change.seq).copied().unwrap_or_default() + change.quantity;
+                        if left > 0 {
+                            resting.insert(change.seq, left);
+                        } else {
+                            resting.remove(&change.seq);
+                        }
+                        let id = anonymous(change.seq);
+                        yield match update {
+                            _ if change.quantity > 0 => Event::Add {
+                                seq,
+                                order: Order::from(&change),
+                            },
+                            Update::Trade(_, price, _) => Event::Execute {
+                                seq,
+                                id,
+                                price,
+                                quantity: -change.quantity,
+                            },
+                            _ if left > 0 => Event::Reduce {
+                                seq,
+                                id,
+                                quantity: -change.quantity,
+                            },
+                            _ => Event::Delete { seq, id },
+                        };
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+async fn watch_orders(
+    State(state): State<AppState>,
+    Path(code): Path<Arc<str>>,
+) -> Result<impl IntoResponse, Error> {
+    let security = state
+        .engine
+        .get(&code)
+        .map(|security| security.value().clone())
+        .ok_or(Error::NotFound("security"))?;
+    Ok(Sse::new(stream(security).map(|event| {
+        SseEvent::default()
+            .event(event.name())
+            .id(event.seq().to_string())
+            .json_data(event)
+    }))
+    .keep_alive(KeepAlive::default()))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new().route("/watch/:code/orders", routing::get(watch_orders))
+}
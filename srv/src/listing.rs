@@ -0,0 +1,199 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::http::StatusCode;
+use axum::{routing, Router};
+use chrono::Utc;
+use entity::sea_orm_active_enums::SecurityStatus;
+use entity::{order, security};
+use rust_decimal::Decimal;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::book::MAX_TICKS;
+use crate::error::Error;
+use crate::journal::Command;
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct Attrs {
+    pub name: String,
+    pub currency: String,
+    pub tick_size: Decimal,
+    pub lot_size: i64,
+    pub ref_price: Option<Decimal>,
+    pub price_band: Option<Decimal>,
+    pub segment: String,
+}
+
+#[derive(Deserialize)]
+pub struct Listing {
+    pub code: String,
+    #[serde(flatten)]
+    pub attrs: Attrs,
+}
+
+#[derive(Deserialize)]
+pub struct Filter {
+    pub status: Option<SecurityStatus>,
+    pub segment: Option<String>,
+}
+
+impl Attrs {
+    fn is_valid(&self) -> bool {
+        // The store keeps the tick size to two decimal places.
+        self.tick_size > Decimal::ZERO
+            && self.tick_size.normalize().scale() <= 2
+            && self.lot_size > 0
+            && self.ref_price.is_none_or(|price| price > Decimal::ZERO)
+            && self.price_band.is_none_or(|band| band > Decimal::ZERO)
+    }
+
+    fn set(self, model: &mut security::ActiveModel) {
+        model.name = ActiveValue::Set(self.name);
+        model.currency = ActiveValue::Set(self.currency);
+        model.tick_size = ActiveValue::Set(self.tick_size);
+        model.lot_size = ActiveValue::Set(self.lot_size);
+        model.ref_price = ActiveValue::Set(self.ref_price);
+        model.price_band = ActiveValue::Set(self.price_band);
+        model.segment = ActiveValue::Set(self.segment);
+        model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+    }
+}
+
+/// Checks an incoming order against the trading rules of its security.
+pub fn check(security: &security::Model, order: &order::Model) -> Result<(), Error> {
+    match security.status {
+        SecurityStatus::Delisted => return Err(Error::NotFound("security")),
+        SecurityStatus::Suspended => return Err(Error::Forbidden("security is suspended")),
+        SecurityStatus::Listed => {}
+    }
+    if order.quantity <= 0 || order.quantity % security.lot_size != 0 {
+        return Err(Error::Invalid(
+            "quantity must be a positive multiple of the lot size",
+        ));
+    }
+    if order.price <= Decimal::ZERO || !(order.price % security.tick_size).is_zero() {
+        return Err(Error::Invalid(
+            "price must be a positive multiple of the tick size",
+        ));
+    }
+    let ticks = order.price.checked_div(security.tick_size);
+    if ticks.is_none_or(|ticks| ticks > Decimal::from(MAX_TICKS)) {
+        return Err(Error::Invalid("price beyond the range of the book"));
+    }
+    let band = Option::zip(security.ref_price, security.price_band)
+        .map(|(price, band)| (price * (Decimal::ONE - band), price * (Decimal::ONE + band)));
+    if band.is_some_and(|(low, high)| order.price < low || order.price > high) {
+        return Err(Error::Invalid("price outside the band"));
+    }
+    Ok(())
+}
+
+async fn list(
+    State(state): State<AppState>,
+    Query(filter): Query<Filter>,
+) -> Result<Json<Vec<security::Model>>, Error> {
+    let mut query = security::Entity::find().order_by_asc(security::Column::Code);
+    if let Some(status) = filter.status {
+        query = query.filter(security::Column::Status.eq(status));
+    }
+    if let Some(segment) = filter.segment {
+        query = query.filter(security::Column::Segment.eq(segment));
+    }
+    Ok(Json(query.all(&state.db).await?))
+}
+
+async fn get(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<Json<security::Model>, Error> {
+    security::Entity::find_by_id(code)
+        .one(&state.db)
+        .await?
+        .map(Json)
+        .ok_or(Error::NotFound("security"))
+}
+
+async fn list_security(
+    State(state): State<AppState>,
+    Json(Listing { code, mut attrs }): Json<Listing>,
+) -> Result<(StatusCode, Json<security::Model>), Error> {
+    attrs.price_band = attrs.price_band.or(state.config.market.price_band);
+    if !attrs.is_valid() {
+        return Err(Error::Invalid("invalid trading rules"));
+    }
+    let mut model = security::ActiveModel {
+        code: ActiveValue::Set(code.clone()),
+        status: ActiveValue::Set(SecurityStatus::Listed),
+        ..Default::default()
+    };
+    attrs.set(&mut model);
+    // A code already listed is a unique violation, answered as a conflict.
+    let model = model.insert(&state.db).await?;
+    state.entry_or_default(Arc::from(code), model.tick_size);
+    Ok((StatusCode::CREATED, Json(model)))
+}
+
+async fn edit(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Json(mut attrs): Json<Attrs>,
+) -> Result<Json<security::Model>, Error> {
+    attrs.price_band = attrs.price_band.or(state.config.market.price_band);
+    if !attrs.is_valid() {
+        return Err(Error::Invalid("invalid trading rules"));
+    }
+    let mut model = security::Entity::find_by_id(code)
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound("security"))?
+        .into_active_model();
+    attrs.set(&mut model);
+    Ok(Json(model.update(&state.db).await?))
+}
+
+async fn set_status(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Json(status): Json<SecurityStatus>,
+) -> Result<StatusCode, Error> {
+    let mut model = security::Entity::find_by_id(code.clone())
+        .one(&state.db)
+        .await?
+        .ok_or(Error::NotFound("security"))?
+        .into_active_model();
+    model.status = ActiveValue::Set(status);
+    model.updated_at = ActiveValue::Set(Utc::now().fixed_offset());
+    match status {
+        SecurityStatus::Listed | SecurityStatus::Suspended => {
+            let model = model.update(&state.db).await?;
+            state.entry_or_default(Arc::from(code), model.tick_size);
+        }
+        // The book goes, and its orders expire, as the journal takes the delisting.
+        SecurityStatus::Delisted => {
+            let update = async { Ok(model.update(&state.db).await.map(drop)?) };
+            state.submit_after(Command::Delist { code }, update).await?;
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delist(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Result<StatusCode, Error> {
+    set_status(State(state), Path(code), Json(SecurityStatus::Delisted)).await
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/security", routing::get(list).post(list_security))
+        .route(
+            "/security/:code",
+            routing::get(get).put(edit).delete(delist),
+        )
+        .route("/security/:code/status", routing::put(set_status))
+}
@@ -1,39 +1,223 @@
-use chrono::{DateTime, FixedOffset};
+use chrono::{DateTime, FixedOffset, Utc};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use entity::msg;
 use implicit_clone::sync::IString;
 use serde::Serialize;
-use std::sync::Arc;
-use tokio::sync::mpsc::error::SendError;
-use tokio::sync::mpsc::UnboundedSender;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, Sender};
 
 #[derive(Serialize, Clone)]
 pub struct MsgBody {
+    /// Numbers the message among all messages, in the order each account is sent them; 0 until
+    /// it is sent.
+    pub ack: i64,
     pub name: IString,
     pub data: Arc<dyn erased_serde::Serialize + Send + Sync>,
     pub happened_at: DateTime<FixedOffset>,
 }
 
-#[derive(Default)]
+impl From<msg::Model> for MsgBody {
+    fn from(msg: msg::Model) -> Self {
+        Self {
+            ack: msg.ack,
+            name: IString::from(msg.event_type),
+            data: Arc::new(msg.data),
+            happened_at: msg.happened_at,
+        }
+    }
+}
+
+/// What a session of an account has taken so far.
+pub struct Session {
+    pub id: u64,
+    /// What the session connected by: `sse`, `ws` or `fix`.
+    pub via: &'static str,
+    pub since: DateTime<FixedOffset>,
+    delivered: AtomicU64,
+    last_ack: AtomicI64,
+}
+
+/// A session as listed, with how many messages wait in its queue.
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub session: u64,
+    pub via: &'static str,
+    pub since: DateTime<FixedOffset>,
+    pub queued: usize,
+    pub delivered: u64,
+    pub last_ack: Option<i64>,
+}
+
+/// Which messages a session takes; those it passes over are left to the other sessions, or kept
+/// for when one comes online.
+pub type Takes = Arc<dyn Fn(&MsgBody) -> bool + Send + Sync>;
+
+struct Addr {
+    tx: Sender<MsgBody>,
+    session: Arc<Session>,
+    takes: Takes,
+}
+
+/// The messages of one session, in the order they were sent. Ends when the session falls too
+/// far behind; dropping it takes the session offline.
+pub struct Receiver {
+    rx: mpsc::Receiver<MsgBody>,
+    id: i64,
+    session: Arc<Session>,
+    msg_box: Weak<MsgBox>,
+}
+
+impl Receiver {
+    pub async fn recv(&mut self) -> Option<MsgBody> {
+        let body = self.rx.recv().await?;
+        self.session.delivered.fetch_add(1, Ordering::Relaxed);
+        self.session.last_ack.store(body.ack, Ordering::Relaxed);
+        Some(body)
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        if let Some(msg_box) = self.msg_box.upgrade() {
+            msg_box.leave(self.id, self.session.id);
+        }
+    }
+}
+
 pub struct MsgBox {
-    addrs: DashMap<i64, UnboundedSender<MsgBody>>,
+    /// Every live session of each account.
+    addrs: DashMap<i64, Vec<Addr>>,
     pub unsent: DashMap<i64, Vec<MsgBody>>,
+    /// The ack of the last message sent.
+    last: AtomicI64,
+    sessions: AtomicU64,
+    /// How many messages a session may fall behind before it is disconnected.
+    capacity: usize,
 }
 
 impl MsgBox {
-    pub fn send(&self, id: i64, body: MsgBody) -> Option<MsgBody> {
-        if let Some(tx) = self.addrs.get_mut(&id) {
-            tx.send(body).err().map(|SendError(msg)| msg)
-        } else {
-            Some(body)
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            addrs: Default::default(),
+            unsent: Default::default(),
+            last: Default::default(),
+            sessions: Default::default(),
+            capacity,
+        }
+    }
+
+    /// Numbers messages on from `ack`, the last one kept.
+    pub fn resume(&self, ack: i64) {
+        self.last.fetch_max(ack, Ordering::Relaxed);
+    }
+
+    pub fn last(&self) -> i64 {
+        self.last.load(Ordering::Relaxed)
+    }
+
+    /// Numbers `body` and queues it to every session of account `id` taking it, keeping it for
+    /// when one comes online if none does. Messages to one account are numbered in the order
+    /// they are sent. A session with a full queue is disconnected.
+    pub fn send(&self, id: i64, mut body: MsgBody) -> (MsgBody, bool) {
+        let mut unsent = self.unsent.entry(id).or_default();
+        body.ack = self.last.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut delivered = false;
+        if let Some(mut addrs) = self.addrs.get_mut(&id) {
+            addrs.retain(|addr| match (addr.takes)(&body) {
+                false => true,
+                true => match addr.tx.try_send(body.clone()) {
+                    Ok(()) => {
+                        delivered = true;
+                        true
+                    }
+                    Err(TrySendError::Full(_)) => {
+                        tracing::warn!(
+                            "account {id}: {} session {} fell {} messages behind, disconnected",
+                            addr.session.via,
+                            addr.session.id,
+                            self.capacity
+                        );
+                        false
+                    }
+                    Err(TrySendError::Closed(_)) => false,
+                },
+            });
+        }
+        if !delivered {
+            unsent.push(body.clone());
+        }
+        (body, delivered)
+    }
+
+    /// Opens a session of account `id` taking the messages `takes` picks, handing it those the
+    /// account has not been sent yet.
+    pub fn online(
+        self: &Arc<Self>,
+        id: i64,
+        via: &'static str,
+        takes: Takes,
+    ) -> (Receiver, Vec<MsgBody>) {
+        let (tx, rx) = mpsc::channel(self.capacity);
+        let session = Arc::new(Session {
+            id: self.sessions.fetch_add(1, Ordering::Relaxed) + 1,
+            via,
+            since: Utc::now().fixed_offset(),
+            delivered: Default::default(),
+            last_ack: Default::default(),
+        });
+        // Held so no message is sent in between.
+        let mut unsent = self.unsent.entry(id).or_default();
+        let (taken, left) = std::mem::take(&mut *unsent)
+            .into_iter()
+            .partition(|body| takes(body));
+        *unsent = left;
+        let addr = Addr {
+            tx,
+            session: session.clone(),
+            takes,
+        };
+        self.addrs.entry(id).or_default().push(addr);
+        drop(unsent);
+        self.unsent.remove_if(&id, |_, left| left.is_empty());
+        let receiver = Receiver {
+            rx,
+            id,
+            session,
+            msg_box: Arc::downgrade(self),
+        };
+        (receiver, taken)
+    }
+
+    fn leave(&self, id: i64, session: u64) {
+        if let Entry::Occupied(mut addrs) = self.addrs.entry(id) {
+            addrs.get_mut().retain(|addr| addr.session.id != session);
+            if addrs.get().is_empty() {
+                addrs.remove();
+            }
         }
-        .map(|body| {
-            self.unsent.entry(id).or_default().push(body.clone());
-            body
-        })
     }
 
-    pub fn online(&self, id: i64, tx: UnboundedSender<MsgBody>) -> Option<(i64, Vec<MsgBody>)> {
-        self.addrs.insert(id, tx);
-        self.unsent.remove(&id)
+    /// The live sessions of account `id`.
+    pub fn sessions(&self, id: i64) -> Vec<SessionInfo> {
+        let Some(addrs) = self.addrs.get(&id) else {
+            return Vec::new();
+        };
+        addrs
+            .iter()
+            .map(|Addr { tx, session, .. }| SessionInfo {
+                session: session.id,
+                via: session.via,
+                since: session.since,
+                queued: tx.max_capacity() - tx.capacity(),
+                delivered: session.delivered.load(Ordering::Relaxed),
+                last_ack: match session.last_ack.load(Ordering::Relaxed) {
+                    0 => None,
+                    ack => Some(ack),
+                },
+            })
+            .collect()
     }
 }
@@ -1,4 +1,4 @@
-#[derive(serde::Deserialize, Eq, PartialEq, Copy, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Period {
     Prepare,
     Call,
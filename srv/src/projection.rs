@@ -0,0 +1,268 @@
+//! Follows the journal into the store. Each journal entry's effects are gathered until every
+//! book it went to is done with it, then applied in one batch, strictly in seq order, off the
+//! path of matching. A batch that fails is retried a few times, then set aside with an error in
+//! the log: the journal already holds the command, so the store only falls behind or misses it.
+//! Effects wait in a queue of bounded length; once it is full, whoever adds to it waits too,
+//! but for tasks of a single threaded runtime, which must not block: what they add waits in a
+//! spill behind it, in order.
+
+use dashmap::DashMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::task;
+use tokio::time::{self, Duration};
+
+use crate::config;
+use crate::error::Error;
+use crate::security::Security;
+use crate::storage::{Batch, Effect, Storage};
+
+/// How long a failed batch waits before it is tried again.
+const RETRY: Duration = Duration::from_secs(1);
+
+enum Msg {
+    /// Entry `seq` is waiting on this many books.
+    Begin(i64, usize),
+    Effect(i64, Effect),
+    /// A book is done with entry `seq`.
+    Done(i64),
+    /// An effect of no entry in particular, kept behind those begun already.
+    After(Effect),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Default)]
+struct Pending {
+    parts: usize,
+    effects: Vec<Effect>,
+}
+
+#[derive(Default)]
+struct Spill {
+    msgs: VecDeque<Msg>,
+    draining: bool,
+}
+
+pub struct Projector {
+    tx: Sender<Msg>,
+    /// Messages that found the queue full on a single threaded runtime. While any wait, every
+    /// message goes behind them.
+    spill: Arc<Mutex<Spill>>,
+    floor: i64,
+    flush_timeout: Duration,
+}
+
+impl Projector {
+    /// Starts projecting the entries after `floor`, those the store holds already being
+    /// replayed from an earlier snapshot of the books.
+    pub fn spawn(
+        store: Arc<dyn Storage>,
+        engine: Arc<DashMap<Arc<str>, Arc<Security>>>,
+        floor: i64,
+        config: &config::Projection,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(config.capacity);
+        tokio::spawn(run(rx, store, engine, floor, config.attempts));
+        Self {
+            tx,
+            spill: Default::default(),
+            floor,
+            flush_timeout: Duration::from_millis(config.flush_timeout_ms),
+        }
+    }
+
+    /// Whether the store held entry `seq` before the start, so its effects are not projected.
+    pub fn is_projected(&self, seq: i64) -> bool {
+        seq <= self.floor
+    }
+
+    /// Queues `msg`, waiting for room should the store be that far behind. The task only ends
+    /// with the runtime.
+    fn send(&self, msg: Msg) {
+        let handle = Handle::try_current();
+        let msg = {
+            let mut spill = self.spill.lock().unwrap();
+            let msg = match spill.msgs.is_empty() {
+                true => match self.tx.try_send(msg) {
+                    Err(TrySendError::Full(msg)) => msg,
+                    _ => return,
+                },
+                false => msg,
+            };
+            let blocks = match &handle {
+                Ok(handle) => handle.runtime_flavor() != RuntimeFlavor::CurrentThread,
+                Err(_) => true,
+            };
+            if !blocks || !spill.msgs.is_empty() {
+                spill.msgs.push_back(msg);
+                if !std::mem::replace(&mut spill.draining, true) {
+                    tokio::spawn(drain(self.tx.clone(), self.spill.clone()));
+                }
+                return;
+            }
+            msg
+        };
+        let wait = || self.tx.blocking_send(msg).unwrap_or_default();
+        // Matching threads wait as they are; tasks hand their worker's other tasks on first.
+        match handle {
+            Ok(_) => task::block_in_place(wait),
+            Err(_) => wait(),
+        }
+    }
+
+    /// Starts the batch of entry `seq`, which is complete once `parts` books are done with it.
+    pub fn begin(&self, seq: i64, parts: usize) {
+        self.send(Msg::Begin(seq, parts));
+    }
+
+    pub fn effect(&self, seq: i64, effect: Effect) {
+        self.send(Msg::Effect(seq, effect));
+    }
+
+    pub fn done(&self, seq: i64) {
+        self.send(Msg::Done(seq));
+    }
+
+    pub fn after(&self, effect: Effect) {
+        self.send(Msg::After(effect));
+    }
+
+    /// Waits until every entry begun so far is in the store, or set aside. Fails should that
+    /// take longer than the flush timeout.
+    pub async fn flush(&self) -> Result<(), Error> {
+        time::timeout(self.flush_timeout, self.settle())
+            .await
+            .map_err(|_| Error::Behind)
+    }
+
+    /// Waits however long it takes until every entry begun so far is in the store, or set
+    /// aside, as after the journal was replayed at startup.
+    pub async fn settle(&self) {
+        let (tx, rx) = oneshot::channel();
+        self.send(Msg::Flush(tx));
+        rx.await.unwrap_or_default();
+    }
+}
+
+/// Moves the spill into the queue as room comes. A message leaves the spill only once it is in
+/// the queue, so none sent meanwhile gets ahead of it.
+async fn drain(tx: Sender<Msg>, spill: Arc<Mutex<Spill>>) {
+    loop {
+        let Ok(permit) = tx.reserve().await else {
+            return;
+        };
+        let mut spill = spill.lock().unwrap();
+        match spill.msgs.pop_front() {
+            Some(msg) => permit.send(msg),
+            None => {
+                spill.draining = false;
+                return;
+            }
+        }
+    }
+}
+
+async fn run(
+    mut rx: Receiver<Msg>,
+    store: Arc<dyn Storage>,
+    engine: Arc<DashMap<Arc<str>, Arc<Security>>>,
+    floor: i64,
+    attempts: u32,
+) {
+    let mut pending = BTreeMap::<i64, Pending>::new();
+    let mut last = 0;
+    let mut flushes: Vec<(i64, oneshot::Sender<()>)> = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        if let Msg::Begin(seq, _) | Msg::Effect(seq, _) | Msg::Done(seq) = &msg {
+            if *seq <= floor {
+                continue;
+            }
+        }
+        match msg {
+            Msg::Begin(seq, parts) => {
+                pending.insert(
+                    seq,
+                    Pending {
+                        parts,
+                        ..Default::default()
+                    },
+                );
+                last = seq;
+            }
+            Msg::Effect(seq, effect) => match pending.get_mut(&seq) {
+                Some(batch) => batch.effects.push(effect),
+                None => {
+                    tracing::warn!("journal entry {seq} got an effect after it was projected");
+                    project(&*store, &engine, attempts, None, vec![effect]).await;
+                }
+            },
+            Msg::Done(seq) => {
+                if let Some(batch) = pending.get_mut(&seq) {
+                    batch.parts = batch.parts.saturating_sub(1);
+                }
+            }
+            Msg::After(effect) => match pending.get_mut(&last) {
+                Some(batch) => batch.effects.push(effect),
+                None => project(&*store, &engine, attempts, None, vec![effect]).await,
+            },
+            Msg::Flush(tx) => flushes.push((last, tx)),
+        }
+        while let Some(batch) = pending.first_entry() {
+            if batch.get().parts > 0 {
+                break;
+            }
+            let (seq, batch) = batch.remove_entry();
+            project(&*store, &engine, attempts, Some(seq), batch.effects).await;
+        }
+        let projected = pending
+            .first_key_value()
+            .map_or(i64::MAX, |(seq, _)| seq - 1);
+        let ready;
+        (ready, flushes) = std::mem::take(&mut flushes)
+            .into_iter()
+            .partition(|(seq, _)| *seq <= projected);
+        for (_, tx) in ready {
+            tx.send(()).unwrap_or_default();
+        }
+    }
+}
+
+/// Applies one batch, trying up to `attempts` times before setting it aside, then publishes the
+/// candles of the trades it booked.
+async fn project(
+    store: &dyn Storage,
+    engine: &DashMap<Arc<str>, Arc<Security>>,
+    attempts: u32,
+    seq: Option<i64>,
+    effects: Vec<Effect>,
+) {
+    let batch = Batch { seq, effects };
+    let mut attempt = 1;
+    let candles = loop {
+        match store.project(&batch).await {
+            Ok(candles) => break candles,
+            Err(err) if attempt < attempts => {
+                tracing::error!("projecting journal entry {seq:?}: {}", err.detail());
+                attempt += 1;
+                time::sleep(RETRY).await;
+            }
+            Err(err) => {
+                tracing::error!(
+                    "journal entry {seq:?} set aside after {attempts} attempts, the store misses \
+                     its {} effects: {}",
+                    batch.effects.len(),
+                    err.detail()
+                );
+                return;
+            }
+        }
+    };
+    for candle in candles {
+        if let Some(security) = engine.get(candle.code.as_str()) {
+            security.bc_candle.send(candle).unwrap_or_default();
+        }
+    }
+}
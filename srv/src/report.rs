@@ -0,0 +1,171 @@
+use chrono::Utc;
+use entity::order;
+use entity::sea_orm_active_enums::{Dir, OrdStatus};
+use implicit_clone::sync::IString;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::msg::MsgBody;
+
+/// A report on an order, sent to its owner whenever its status or filled quantity changes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExecutionReport {
+    /// `None` for an order rejected before it was given a seq.
+    pub seq: Option<i64>,
+    /// Counts the reports of this order, from 1.
+    pub report_seq: i64,
+    pub cl_ord_id: Option<String>,
+    pub code: String,
+    pub dir: Dir,
+    pub price: Decimal,
+    pub quantity: i64,
+    pub status: OrdStatus,
+    pub cum_quantity: i64,
+    pub leaves_quantity: i64,
+    pub avg_price: Decimal,
+    pub last_price: Option<Decimal>,
+    pub last_quantity: Option<i64>,
+    pub text: Option<String>,
+}
+
+impl ExecutionReport {
+    pub const NAME: &'static str = "ExecutionReport";
+
+    /// Reports an order refused before it reached the book.
+    pub fn rejected(order: &order::Model, cl_ord_id: Option<String>, text: &str) -> Self {
+        Self {
+            seq: None,
+            report_seq: 1,
+            cl_ord_id,
+            code: order.code.clone(),
+            dir: order.dir,
+            price: order.price,
+            quantity: order.quantity,
+            status: OrdStatus::Rejected,
+            cum_quantity: 0,
+            leaves_quantity: 0,
+            avg_price: Decimal::ZERO,
+            last_price: None,
+            last_quantity: None,
+            text: Some(text.to_string()),
+        }
+    }
+
+    pub fn msg(self) -> MsgBody {
+        MsgBody {
+            ack: 0,
+            name: IString::Static(Self::NAME),
+            data: Arc::new(self),
+            happened_at: Utc::now().fixed_offset(),
+        }
+    }
+}
+
+/// What has been reported so far on an order still working.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub id: i64,
+    pub seq: i64,
+    pub cl_ord_id: Option<String>,
+    pub code: String,
+    pub dir: Dir,
+    pub price: Decimal,
+    pub quantity: i64,
+    pub cum: i64,
+    pub turnover: Decimal,
+    pub report_seq: i64,
+}
+
+impl Progress {
+    pub fn new(id: i64, order: &order::Model, cl_ord_id: Option<String>) -> Self {
+        Self {
+            id,
+            seq: order.seq,
+            cl_ord_id,
+            code: order.code.clone(),
+            dir: order.dir,
+            price: order.price,
+            quantity: order.quantity,
+            cum: 0,
+            turnover: Decimal::ZERO,
+            report_seq: 0,
+        }
+    }
+
+    /// Picks up where the last report of the order left off.
+    pub fn resume(id: i64, report: &ExecutionReport) -> Option<Self> {
+        Some(Self {
+            id,
+            seq: report.seq?,
+            cl_ord_id: report.cl_ord_id.clone(),
+            code: report.code.clone(),
+            dir: report.dir,
+            price: report.price,
+            quantity: report.quantity,
+            cum: report.cum_quantity,
+            turnover: report.avg_price * Decimal::from(report.cum_quantity),
+            report_seq: report.report_seq,
+        })
+    }
+
+    /// The order as it rests on the book, with what is left of it.
+    pub fn order(&self) -> order::Model {
+        order::Model {
+            seq: self.seq,
+            code: self.code.clone(),
+            dir: self.dir,
+            price: self.price,
+            quantity: self.quantity - self.cum,
+        }
+    }
+
+    fn avg(&self) -> Decimal {
+        if self.cum == 0 {
+            Decimal::ZERO
+        } else {
+            (self.turnover / Decimal::from(self.cum)).round_dp(4)
+        }
+    }
+
+    pub fn report(&mut self, status: OrdStatus) -> ExecutionReport {
+        self.report_seq += 1;
+        self.current(status)
+    }
+
+    /// The order as it stands, numbered as the last report on it.
+    pub fn current(&self, status: OrdStatus) -> ExecutionReport {
+        ExecutionReport {
+            seq: Some(self.seq),
+            report_seq: self.report_seq,
+            cl_ord_id: self.cl_ord_id.clone(),
+            code: self.code.clone(),
+            dir: self.dir,
+            price: self.price,
+            quantity: self.quantity,
+            status,
+            cum_quantity: self.cum,
+            leaves_quantity: match status.is_final() {
+                true => 0,
+                false => self.quantity - self.cum,
+            },
+            avg_price: self.avg(),
+            last_price: None,
+            last_quantity: None,
+            text: None,
+        }
+    }
+
+    pub fn fill(&mut self, price: Decimal, quantity: i64) -> ExecutionReport {
+        self.cum += quantity;
+        self.turnover += price * Decimal::from(quantity);
+        ExecutionReport {
+            last_price: Some(price),
+            last_quantity: Some(quantity),
+            ..self.report(match self.cum < self.quantity {
+                true => OrdStatus::PartiallyFilled,
+                false => OrdStatus::Filled,
+            })
+        }
+    }
+}
@@ -1,213 +1,179 @@
-use crate::deal::{Deal, DealCall};
 use axum::extract::{Json, Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::sse::{Event, KeepAlive};
 use axum::response::{IntoResponse, Sse};
 use axum::{routing, Router};
 use axum_streams::StreamBodyAs;
-use chrono::Utc;
-use entity::{order, req};
-use futures::{stream, StreamExt};
-use implicit_clone::sync::IString;
-use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, IntoActiveModel, ModelTrait,
-    QueryFilter, QueryOrder, TransactionTrait,
-};
+use entity::{order, position};
+use futures::StreamExt;
 use std::sync::Arc;
 
-use tokio::sync::mpsc::unbounded_channel;
-use tokio_stream::wrappers::{BroadcastStream, UnboundedReceiverStream};
+use tokio::sync::broadcast::error::RecvError;
 
-use crate::msg::MsgBody;
+use crate::candle;
+use crate::corp;
+use crate::error::Error;
+use crate::history;
+use crate::inbox;
+use crate::journal::Command;
+use crate::l3;
+use crate::listing;
 use crate::period::Period;
-use crate::state;
-use crate::state::AppState;
-
-async fn msg(State(state): State<AppState>, Query(id): Query<i64>) -> impl IntoResponse {
-    let (tx, rx) = unbounded_channel::<MsgBody>();
-    let unsent = state.msg_box.online(id, tx);
-    Sse::new(
-        stream::once(async { Event::default().json_data(unsent) }).chain(
-            UnboundedReceiverStream::new(rx)
-                .map(|event: MsgBody| Event::default().event(event.name).json_data(event.data)),
-        ),
-    )
-    .keep_alive(KeepAlive::default())
-}
+use crate::security::{Feed, Queue};
+use crate::state::{Amend, AppState, OrderRef, Place, Placed};
+use crate::storage::Orders;
+use crate::tape;
+use crate::ws;
 
 async fn place(
     State(state): State<AppState>,
     Path(id): Path<i64>,
-    Json(mut order): Json<order::Model>,
-) -> impl IntoResponse {
-    match *state.period.read().await {
-        Period::Suspense => (StatusCode::FORBIDDEN, Json(None)),
-        _ => {
-            let seq = req::ActiveModel {
-                id: ActiveValue::Set(id),
-                body: ActiveValue::Set(serde_json::json!(&order)),
-                ..Default::default()
-            }
-            .insert(&state.db)
-            .await
-            .unwrap()
-            .seq;
-            order.seq = seq;
-            let order = Arc::new(order);
-            state
-                .entry_or_default(Arc::from(order.code.clone()))
-                .place(order.clone(), {
-                    let state = state.clone();
-                    move |order| async move {
-                        order
-                            .as_ref()
-                            .to_owned()
-                            .into_active_model()
-                            .insert(&state.db)
-                            .await
-                            .unwrap();
-                    }
-                })
-                .await;
-
-            (StatusCode::CREATED, Json(Some(seq)))
-        }
-    }
+    Json(Place { order, cl_ord_id }): Json<Place>,
+) -> Result<(StatusCode, Json<i64>), Error> {
+    Ok(match state.place(id, order, cl_ord_id).await? {
+        Placed::New(seq) => (StatusCode::CREATED, Json(seq)),
+        Placed::Existing(seq) => (StatusCode::OK, Json(seq)),
+    })
 }
 
 async fn cancel(
     State(state): State<AppState>,
     Path(id): Path<i64>,
-    Json(seq): Json<i64>,
-) -> impl IntoResponse {
-    match *state.period.read().await {
-        Period::Call | Period::Suspense => StatusCode::FORBIDDEN,
-        _ => {
-            if let Some(order) = order::Entity::find_by_id(seq).one(&state.db).await.unwrap() {
-                let code = Arc::from(order.code.clone());
-                if let Some(book) = state.engine.get(&code) {
-                    let state = state.clone();
-                    book.value()
-                        .cancel(&order, {
-                            let order = order.clone();
-                            move |quantity| async move {
-                                let txn = state.db.begin().await.unwrap();
-                                let data = serde_json::json!({
-                                "cancel": {
-                                        "seq": seq,
-                                        "quantity": quantity
-                                    }
-                                });
-                                req::ActiveModel {
-                                    id: ActiveValue::Set(id),
-                                    body: ActiveValue::Set(data.clone()),
-                                    ..Default::default()
-                                }
-                                .insert(&txn)
-                                .await
-                                .unwrap();
-                                order.delete(&txn).await.unwrap();
-                                txn.commit().await.unwrap();
-                                state
-                                    .send(
-                                        id,
-                                        MsgBody {
-                                            name: IString::Static("Canceled"),
-                                            data: Arc::from(data),
-                                            happened_at: Utc::now().fixed_offset(),
-                                        },
-                                    )
-                                    .await
-                            }
-                        })
-                        .await
-                }
-            }
+    Json(order): Json<OrderRef>,
+) -> Result<StatusCode, Error> {
+    state.cancel_order(id, order).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-            StatusCode::NO_CONTENT
-        }
-    }
+async fn amend(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(Amend {
+        seq,
+        price,
+        quantity,
+        cl_ord_id,
+    }): Json<Amend>,
+) -> Result<(StatusCode, Json<i64>), Error> {
+    Ok(
+        match state.amend(id, seq, price, quantity, cl_ord_id).await? {
+            Placed::New(seq) => (StatusCode::CREATED, Json(seq)),
+            Placed::Existing(seq) => (StatusCode::OK, Json(seq)),
+        },
+    )
 }
 
-async fn ctrl(State(state): State<AppState>, Json(period): Json<Period>) {
-    let last_period = *state.period.read().await;
-    *state.period.write().await = period;
-    if let (Period::Call, Period::Suspense) = (last_period, period) {
-        for security in state.engine.iter() {
-            let code = security.key().clone();
-            let security = security.to_owned();
-            let state = state.clone();
-            tokio::spawn(async move {
-                if let Some(DealCall { price, values }) = security.calc().await {
-                    dbg!(&values);
-                    let mut msg = Vec::with_capacity(values.len() * 2);
-                    let txn = state.db.begin().await.unwrap();
-                    for value in values {
-                        let deal = Deal { price, value };
-                        let rec = state::trade(&txn, code.to_string(), deal).await;
-                        msg.extend(std::iter::zip(
-                            [rec.buyer_id, rec.seller_id],
-                            state::msg_deal(&rec, deal),
-                        ));
-                    }
-                    txn.commit().await.unwrap();
-                    for (id, body) in msg {
-                        let state = state.clone();
-                        tokio::spawn(async move { state.send(id, body).await });
-                    }
-                }
-            });
-        }
-    }
+/// Moves the market to `period`. Leaving the call auction for the close uncrosses it.
+async fn ctrl(State(state): State<AppState>, Json(period): Json<Period>) -> Result<(), Error> {
+    state.submit(Command::Phase(period)).await?;
+    Ok(())
 }
 
 async fn watch(State(state): State<AppState>, Path(code): Path<Arc<str>>) -> impl IntoResponse {
-    let security = state.entry_or_default(code.clone()).value().to_owned();
-    Sse::new(
-        stream::once(async move {
-            Event::default().json_data(state.entry_or_default(code).value().to_owned().view().await)
-        })
-        .chain(stream::select(
-            BroadcastStream::new(security.bc_deal.subscribe())
-                .map(|deal| Event::default().event("trade").json_data(deal.unwrap())),
-            BroadcastStream::new(security.bc_order.subscribe())
-                .map(|order| Event::default().event("order").json_data(order.unwrap())),
-        )),
-    )
+    let Some(security) = state
+        .engine
+        .get(&code)
+        .map(|security| security.value().clone())
+    else {
+        return Error::NotFound("security").into_response();
+    };
+    Sse::new(security.feed().map(|feed| {
+        match feed {
+            Feed::Snapshot(picture) => Event::default()
+                .event("snapshot")
+                .id(picture.seq.to_string())
+                .json_data(picture),
+            Feed::Order(seq, dir, price, quantity) => Event::default()
+                .event("order")
+                .id(seq.to_string())
+                .json_data((seq, (dir, price, quantity))),
+            Feed::Trade(seq, dir, price, quantity) => Event::default()
+                .event("trade")
+                .id(seq.to_string())
+                .json_data((seq, (dir, price, quantity))),
+        }
+    }))
     .keep_alive(KeepAlive::default())
+    .into_response()
 }
 
 async fn review_actions(State(state): State<AppState>, Query(id): Query<i64>) -> impl IntoResponse {
-    StreamBodyAs::json_nl(
+    // A failure mid-stream cuts the response short rather than killing the handler.
+    StreamBodyAs::json_nl_with_errors(
         state
-            .stream_query(
-                req::Entity::find()
-                    .filter(req::Column::Id.eq(id))
-                    .order_by_desc(req::Column::CreatedAt),
-            )
-            .await
-            .map(|model| model.unwrap()),
+            .store
+            .actions(id)
+            .map(|model| model.map_err(axum::Error::new)),
     )
 }
 
-async fn view_matching(State(state): State<AppState>, Query(id): Query<i64>) -> impl IntoResponse {
-    Json(
-        order::Entity::find()
-            .reverse_join(req::Entity)
-            .filter(req::Column::Id.eq(id))
-            .all(&state.db)
-            .await
-            .unwrap(),
-    )
+async fn view_matching(
+    State(state): State<AppState>,
+    Query(id): Query<i64>,
+) -> Result<Json<Vec<order::Model>>, Error> {
+    Ok(Json(
+        state
+            .store
+            .orders(Orders::Of(id))
+            .await?
+            .into_iter()
+            .map(|(order, _)| order)
+            .collect(),
+    ))
+}
+
+async fn view_position(
+    State(state): State<AppState>,
+    Query(id): Query<i64>,
+) -> Result<Json<Vec<position::Model>>, Error> {
+    Ok(Json(state.store.positions(id).await?))
+}
+
+/// Streams an alert whenever matching fails on the book of a security, and whether it was halted.
+async fn alerts(State(state): State<AppState>) -> impl IntoResponse {
+    let mut rx = state.alerts.subscribe();
+    Sse::new(async_stream::stream! {
+        loop {
+            match rx.recv().await {
+                Ok(alert) => yield Event::default().event("alert").json_data(alert),
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+    .keep_alive(KeepAlive::default())
+}
+
+/// How deep the queue of each security runs, and how often it turned commands away.
+async fn queues(State(state): State<AppState>) -> Json<Vec<Queue>> {
+    let mut queues: Vec<_> = state
+        .engine
+        .iter()
+        .map(|security| security.queue())
+        .collect();
+    queues.sort_by(|a, b| a.code.cmp(&b.code));
+    Json(queues)
 }
 
 pub fn create_router() -> Router<AppState> {
     Router::new()
-        .route("/msg", routing::get(msg))
         .route("/cancel/:id", routing::delete(cancel))
         .route("/place/:id", routing::post(place))
+        .route("/amend/:id", routing::put(amend))
         .route("/watch/:code", routing::get(watch))
         .route("/review_actions", routing::get(review_actions))
         .route("/view_matching", routing::get(view_matching))
+        .route("/view_position", routing::get(view_position))
         .route("/ctrl", routing::put(ctrl))
+        .route("/alerts", routing::get(alerts))
+        .route("/queues", routing::get(queues))
+        .merge(crate::ac::create_router())
+        .merge(listing::create_router())
+        .merge(corp::create_router())
+        .merge(candle::create_router())
+        .merge(tape::create_router())
+        .merge(l3::create_router())
+        .merge(history::create_router())
+        .merge(inbox::create_router())
+        .merge(ws::create_router())
 }
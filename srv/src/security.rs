@@ -1,147 +1,702 @@
 use rust_decimal::Decimal;
 use std::collections::VecDeque;
-use std::future::Future;
-use std::ops::DerefMut;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread::{self, Thread};
 
-use tokio::sync::{broadcast, oneshot, watch, RwLock};
-use tokio::task;
-use tokio::time::{self, Duration};
+use chrono::Utc;
+use crossbeam_queue::ArrayQueue;
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot, watch, Notify};
+use tokio::time::{self, Duration, Instant};
 
-use entity::order;
-use entity::sea_orm_active_enums::Dir;
+use entity::{candle, order};
+use entity::sea_orm_active_enums::{Dir, OrdStatus};
 
-use crate::book::{Book, Picture};
+use crate::book::{Book, Change, Imbalance, Picture};
+use crate::config::{Engine, Overflow};
 use crate::deal::{Deal, DealCall, DealValue};
+use crate::error::{Alert, Error};
 use crate::period::Period;
 
-#[derive(Clone)]
+/// Ops the queue holds beyond `queue_capacity`, for those sent without room reserved.
+const HEADROOM: usize = 256;
+/// Ops the matching thread takes before publishing a fresh picture while it has more waiting.
+const PUBLISH_EVERY: usize = 64;
+
+/// A change to the book as seen by market data subscribers.
+#[derive(Serialize, Clone, Debug)]
+pub enum Update {
+    Order(Dir, Decimal, i64),
+    Trade(Option<Dir>, Decimal, i64),
+    /// The whole book was rewritten, e.g. by a split; subscribers need a fresh snapshot.
+    Reset,
+    /// The indicative uncrossing of the call auction after an order came in.
+    Imbalance(Imbalance),
+    Phase(Period),
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Tick {
+    pub seq: u64,
+    pub update: Update,
+    /// The resting orders behind the update; after a reset, every order left on the book.
+    pub changes: Vec<Change>,
+}
+
+/// A journal entry for the book, taken in the order the journal has them, or a job done on the
+/// book in between.
+pub enum Op {
+    /// The order takes the seq of its entry.
+    Place(Arc<order::Model>),
+    /// Journal entry `seq` takes order `order` of account `id` off the book, answering with the
+    /// quantity it took off, `None` if the order was gone already.
+    Cancel {
+        seq: i64,
+        id: i64,
+        order: i64,
+        status: OrdStatus,
+        reply: Option<oneshot::Sender<Option<i64>>>,
+    },
+    /// Replaces order `order` of account `id` with `by`, unless it is gone already.
+    Amend {
+        id: i64,
+        order: i64,
+        by: Arc<order::Model>,
+        cl_ord_id: Option<String>,
+        reply: Option<oneshot::Sender<Option<i64>>>,
+    },
+    Phase(i64, Period),
+    /// Rewrites every resting order, answering with `(seq, price, quantity)` of each.
+    Adjust(
+        Box<dyn Fn(Decimal, i64) -> (Decimal, i64) + Send>,
+        oneshot::Sender<Vec<(i64, Decimal, i64)>>,
+    ),
+    /// Takes every order off the book, as when the security moves to another code.
+    Drain(oneshot::Sender<Vec<Change>>),
+    /// Puts a resting order back on the book as it was kept, without matching it.
+    Push(order::Model),
+    /// Takes over a book from a snapshot.
+    Load(Box<Book>),
+    /// Picks up the last trade price and the price of the last auction.
+    Resume {
+        last_price: Option<Decimal>,
+        price_call: Option<Decimal>,
+    },
+    /// Looks at the book as the ops queued before left it.
+    Read(Box<dyn FnOnce(&Book) + Send>),
+}
+
+impl Op {
+    /// The journal entry the op carries out, if it was journaled.
+    fn seq(&self) -> Option<i64> {
+        match self {
+            Op::Place(order) => Some(order.seq),
+            Op::Cancel { seq, .. } | Op::Phase(seq, _) => Some(*seq),
+            Op::Amend { by, .. } => Some(by.seq),
+            Op::Adjust(..) | Op::Drain(_) | Op::Push(_) | Op::Load(_) | Op::Resume { .. } | Op::Read(_) => None,
+        }
+    }
+}
+
+/// What matching did with a journal entry.
+pub enum Event {
+    Traded(i64, Vec<Deal>),
+    /// Journal entry `seq` took `quantity` of order `order` off the book.
+    Withdrawn {
+        seq: i64,
+        id: i64,
+        order: i64,
+        status: OrdStatus,
+        quantity: i64,
+    },
+    /// `order` goes on the book in place of the one just withdrawn.
+    Replacing {
+        id: i64,
+        order: Arc<order::Model>,
+        cl_ord_id: Option<String>,
+    },
+    /// Order `seq` could not go on the book, its price being beyond the ticks of the book.
+    Refused(i64),
+    /// The book is done with journal entry `seq`.
+    Done(i64),
+}
+
+pub type Events = Arc<dyn Fn(Event) + Send + Sync>;
+
+/// What a market data subscriber receives: a snapshot, then the ticks after it.
+pub enum Feed {
+    Snapshot(Arc<Picture>),
+    Order(u64, Dir, Decimal, i64),
+    Trade(u64, Option<Dir>, Decimal, i64),
+}
+
+/// How the queue of a security has fared.
+#[derive(Default)]
+struct Stats {
+    /// The most ops ever waiting at once.
+    peak: AtomicUsize,
+    /// Commands refused as busy.
+    refused: AtomicU64,
+    /// Ops taken off the queue.
+    taken: AtomicU64,
+}
+
+/// The queue of a security as listed.
+#[derive(Serialize)]
+pub struct Queue {
+    pub code: Arc<str>,
+    pub depth: usize,
+    /// Room held for commands on their way.
+    pub reserved: usize,
+    pub capacity: usize,
+    pub peak: usize,
+    pub refused: u64,
+    pub taken: u64,
+}
+
+/// What the matching thread of a security shares with those sending it ops and reading its book.
+struct Shared {
+    code: Arc<str>,
+    que: ArrayQueue<Op>,
+    /// Room held for commands on their way to the queue.
+    reserved: AtomicUsize,
+    /// Woken when room is made in a full queue.
+    room: Notify,
+    stats: Stats,
+    halted: AtomicBool,
+    /// Set once the security is dropped; the thread ends when it has taken the ops left.
+    closed: AtomicBool,
+    /// The book as the matching thread last published it, copied anew on every publication.
+    picture: watch::Sender<Arc<Picture>>,
+    alerts: broadcast::Sender<Alert>,
+    tuning: Arc<Engine>,
+    bc: broadcast::Sender<Tick>,
+}
+
+impl Shared {
+    /// Wakes those waiting for room if the queue was full with `was` ops waiting or reserved.
+    fn made_room(&self, was: usize) {
+        if was >= self.tuning.queue_capacity {
+            self.room.notify_waiters();
+        }
+    }
+
+    fn take_room(self: &Arc<Self>) -> Option<Reservation> {
+        let capacity = self.tuning.queue_capacity;
+        self.reserved
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |reserved| {
+                (self.que.len() + reserved < capacity).then_some(reserved + 1)
+            })
+            .ok()?;
+        Some(Reservation(self.clone()))
+    }
+
+    fn alert(&self, reason: String, halted: bool) {
+        tracing::error!(
+            "{}: {} after failure: {reason}",
+            self.code,
+            if halted { "halted" } else { "matching went on" }
+        );
+        self.alerts
+            .send(Alert {
+                code: self.code.clone(),
+                halted,
+                reason,
+                happened_at: Utc::now().fixed_offset(),
+            })
+            .unwrap_or_default();
+    }
+}
+
+/// Room held in the queue of a security, given back once the command is queued or refused.
+pub struct Reservation(Arc<Shared>);
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let reserved = self.0.reserved.fetch_sub(1, Ordering::AcqRel);
+        self.0.made_room(self.0.que.len() + reserved);
+    }
+}
+
+/// The book of a security. It is owned by a thread of its own, which takes ops off a bounded
+/// queue one at a time, matches them and publishes what changed; nothing else touches it.
+/// Readers get the picture the thread last published, or send it an op to look at the book.
 pub struct Security {
-    book: Arc<RwLock<Book>>,
-    que: Arc<RwLock<VecDeque<Arc<order::Model>>>>,
-    watcher: watch::Sender<bool>,
-    pub bc_deal: broadcast::Sender<(Option<Dir>, Decimal, i64)>,
-    pub bc_order: broadcast::Sender<(Dir, Decimal, i64)>,
+    pub code: Arc<str>,
+    /// The tick size the book was opened with; its ticks may have been made finer since.
+    pub tick: Decimal,
+    shared: Arc<Shared>,
+    /// The matching thread, woken when an op is queued.
+    thread: Thread,
+    pub bc: broadcast::Sender<Tick>,
+    pub bc_candle: broadcast::Sender<candle::Model>,
 }
 
-impl Security {
-    pub async fn push(&self, order: &order::Model) {
-        self.book.write().await.insert(order);
+/// The matching thread of a security.
+struct Matcher {
+    shared: Arc<Shared>,
+    book: Book,
+    /// The phase as of the last op taken.
+    phase: Period,
+    events: Events,
+    /// Ops taken since the picture was last published.
+    unpublished: usize,
+    /// When matching failed lately.
+    failures: VecDeque<Instant>,
+}
+
+impl Matcher {
+    /// Takes ops until the security is dropped, parking while there are none.
+    fn work(mut self) {
+        loop {
+            match self.shared.que.pop() {
+                Some(op) => {
+                    let was = self.shared.que.len() + 1 + self.shared.reserved.load(Ordering::Acquire);
+                    self.shared.made_room(was);
+                    self.shared.stats.taken.fetch_add(1, Ordering::Relaxed);
+                    self.take(op);
+                    self.unpublished += 1;
+                    if self.unpublished >= PUBLISH_EVERY {
+                        self.show();
+                    }
+                }
+                None if self.shared.closed.load(Ordering::Acquire) => return,
+                None => {
+                    if self.unpublished > 0 {
+                        self.show();
+                    }
+                    thread::park();
+                }
+            }
+        }
     }
 
-    pub fn new<FutT: Send + Future<Output = ()>>(
-        period: Arc<RwLock<Period>>,
-        mut trade: impl FnMut(Deal) -> FutT + Send + 'static,
-    ) -> Self {
-        let (tx, mut rx) = watch::channel(true);
-        let deal_maker = Self {
-            book: Default::default(),
-            que: Default::default(),
-            watcher: tx,
-            bc_deal: broadcast::Sender::new(1024),
-            bc_order: broadcast::Sender::new(1024),
+    /// Publishes the book as it is for readers.
+    fn show(&mut self) {
+        self.unpublished = 0;
+        self.shared.picture.send_replace(Arc::new(self.book.view()));
+    }
+
+    /// Carries out `op`; should it fail, the entry is done with as far as it got. Failing more
+    /// than `max_restarts` times within `restart_window_secs` halts the security: it stops
+    /// matching, but still takes orders off the book.
+    fn take(&mut self, op: Op) {
+        let seq = op.seq();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.run(op)));
+        if let Some(seq) = seq {
+            (self.events)(Event::Done(seq));
+        }
+        let Err(panic) = result else {
+            return;
         };
-        task::spawn({
-            let deal_maker = deal_maker.clone();
-            async move {
-                while !*rx.wait_for(|is_empty| !is_empty).await.unwrap() {
-                    while let Some(order) = {
-                        let mut que = deal_maker.que.write().await;
-                        let pop = que.pop_front();
-                        if pop.is_none() {
-                            deal_maker.watcher.send(true).unwrap();
-                        }
-                        pop
-                    } {
-                        let dir = order.dir;
-                        deal_maker.book.write().await.insert(&order);
-                        deal_maker
-                            .bc_order
-                            .send((order.dir, order.price, order.quantity))
-                            .unwrap_or_default();
-                        while let Some(deal) = match *period.read().await {
-                            Period::Continuous => {
-                                deal_maker.book.write().await.matches(match dir {
-                                    Dir::Buy => |_, price| price,
-                                    Dir::Sell => |price, _| price,
-                                })
-                            }
-                            _ => None,
-                        } {
-                            trade(deal).await;
-                            deal_maker
-                                .bc_deal
-                                .send((Some(dir), deal.price, deal.value.quantity))
-                                .unwrap_or_default();
-                        }
+        let reason = panic
+            .downcast_ref::<&str>()
+            .map(|reason| reason.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "matching panicked".to_string());
+        let window = Duration::from_secs(self.shared.tuning.restart_window_secs);
+        self.failures.push_back(Instant::now());
+        while self.failures.front().is_some_and(|at: &Instant| at.elapsed() > window) {
+            self.failures.pop_front();
+        }
+        if self.failures.len() > self.shared.tuning.max_restarts {
+            if !self.shared.halted.load(Ordering::Relaxed) {
+                self.halt(reason);
+            }
+        } else {
+            self.shared.alert(reason, false);
+        }
+    }
+
+    /// Stops matching for good, as the book may no longer agree with what was stored.
+    fn halt(&mut self, reason: String) {
+        self.shared.halted.store(true, Ordering::Relaxed);
+        self.publish(Update::Phase(Period::Suspense));
+        self.shared.alert(reason, true);
+    }
+
+    fn is_halted(&self) -> bool {
+        self.shared.halted.load(Ordering::Relaxed)
+    }
+
+    /// Stamps `update` with the next book sequence and broadcasts it, so ticks go out in
+    /// sequence order.
+    fn publish(&mut self, update: Update) {
+        self.book.seq += 1;
+        self.shared
+            .bc
+            .send(Tick {
+                seq: self.book.seq,
+                update,
+                changes: std::mem::take(&mut self.book.changes),
+            })
+            .unwrap_or_default();
+    }
+
+    fn run(&mut self, op: Op) {
+        let events = self.events.clone();
+        match op {
+            Op::Place(order) => self.enter(&order),
+            Op::Cancel { seq, id, order, status, reply } => {
+                let quantity = self.remove(order);
+                if let Some(quantity) = quantity {
+                    events(Event::Withdrawn { seq, id, order, status, quantity });
+                }
+                if let Some(reply) = reply {
+                    reply.send(quantity).unwrap_or_default();
+                }
+            }
+            Op::Amend { id, order, by, cl_ord_id, reply } => {
+                let quantity = self.remove(order);
+                if let Some(quantity) = quantity {
+                    events(Event::Withdrawn { seq: by.seq, id, order, status: OrdStatus::Replaced, quantity });
+                    events(Event::Replacing { id, order: by.clone(), cl_ord_id });
+                }
+                if let Some(reply) = reply {
+                    reply.send(quantity).unwrap_or_default();
+                }
+                if quantity.is_some() {
+                    self.enter(&by);
+                }
+            }
+            Op::Phase(seq, period) => {
+                let last = std::mem::replace(&mut self.phase, period);
+                self.publish(Update::Phase(if self.is_halted() { Period::Suspense } else { period }));
+                if let (Period::Call, Period::Suspense) = (last, period) {
+                    if let Some(DealCall { price, values }) = self.calc() {
+                        tracing::info!("{}: closing auction uncrossed {:?}", self.shared.code, values);
+                        let deals = values.into_iter().map(|value| Deal { price, value, aggressor: None }).collect();
+                        events(Event::Traded(seq, deals));
                     }
                 }
             }
+            Op::Adjust(f, reply) => {
+                let adjusted = self.book.adjust(f);
+                self.publish(Update::Reset);
+                reply.send(adjusted).unwrap_or_default();
+            }
+            Op::Drain(reply) => {
+                let orders = self.book.orders();
+                let seq = self.book.seq;
+                self.book = Book::new(self.book.tick());
+                self.book.seq = seq;
+                self.publish(Update::Reset);
+                reply.send(orders).unwrap_or_default();
+            }
+            Op::Push(order) => {
+                if !self.book.insert(&order) {
+                    tracing::error!("{}: order {} is beyond the ticks of the book", self.shared.code, order.seq);
+                    events(Event::Refused(order.seq));
+                    return;
+                }
+                self.publish(Update::Order(order.dir, order.price, order.quantity));
+            }
+            Op::Load(book) => {
+                let (last_price, price_call) = (book.last_price, book.price_call);
+                self.book = *book;
+                self.book.changes = self.book.orders();
+                self.publish(Update::Reset);
+                self.resume(last_price, price_call);
+            }
+            Op::Resume { last_price, price_call } => self.resume(last_price, price_call),
+            Op::Read(f) => f(&self.book),
+        }
+    }
+
+    /// Picks up the last trade price and the price of the last auction of a restored book, then
+    /// announces its phase and, in the call auction, where it would uncross.
+    fn resume(&mut self, last_price: Option<Decimal>, price_call: Option<Decimal>) {
+        self.book.last_price = last_price;
+        self.book.price_call = price_call;
+        self.publish(Update::Phase(if self.is_halted() { Period::Suspense } else { self.phase }));
+        if self.phase == Period::Call {
+            if let Some(imbalance) = self.book.indicative() {
+                self.publish(Update::Imbalance(imbalance));
+            }
+        }
+    }
+
+    /// Puts `order` on the book and matches it as the phase allows.
+    fn enter(&mut self, order: &order::Model) {
+        let dir = order.dir;
+        if !self.book.insert(order) {
+            (self.events)(Event::Refused(order.seq));
+            return;
+        }
+        self.publish(Update::Order(order.dir, order.price, order.quantity));
+        if self.phase == Period::Call {
+            if let Some(imbalance) = self.book.indicative() {
+                self.publish(Update::Imbalance(imbalance));
+            }
+        }
+        if self.phase != Period::Continuous || self.is_halted() {
+            return;
+        }
+        let mut deals = Vec::new();
+        while let Some(deal) = self.book.matches(match dir {
+            Dir::Buy => |_, price| price,
+            Dir::Sell => |price, _| price,
+        }) {
+            let deal = Deal { aggressor: Some(dir), ..deal };
+            self.publish(Update::Trade(Some(dir), deal.price, deal.value.quantity));
+            deals.push(deal);
+        }
+        if !deals.is_empty() {
+            (self.events)(Event::Traded(order.seq, deals));
+        }
+    }
+
+    /// Takes order `seq` off the book, returning what was left of it.
+    fn remove(&mut self, seq: i64) -> Option<i64> {
+        let order = self.book.remove(seq)?;
+        self.publish(Update::Order(order.dir, order.price, -order.quantity));
+        Some(order.quantity)
+    }
+
+    /// Uncrosses the call auction.
+    fn calc(&mut self) -> Option<DealCall> {
+        if self.is_halted() {
+            return None;
+        }
+        let deal = self.book.calc();
+        if let Some(DealCall { price, values }) = &deal {
+            self.publish(Update::Trade(None, *price, values.iter().map(|DealValue {quantity, ..}| *quantity).sum()));
+        }
+        deal
+    }
+}
+
+impl Security {
+    pub fn new(
+        code: Arc<str>,
+        tick: Decimal,
+        phase: Period,
+        alerts: broadcast::Sender<Alert>,
+        tuning: Arc<Engine>,
+        events: Events,
+    ) -> Self {
+        let bc = broadcast::Sender::new(tuning.feed_capacity);
+        let bc_candle = broadcast::Sender::new(tuning.feed_capacity);
+        let shared = Arc::new(Shared {
+            code: code.clone(),
+            que: ArrayQueue::new(tuning.queue_capacity + HEADROOM),
+            reserved: Default::default(),
+            room: Default::default(),
+            stats: Default::default(),
+            halted: Default::default(),
+            closed: Default::default(),
+            picture: watch::Sender::new(Default::default()),
+            alerts,
+            tuning,
+            bc: bc.clone(),
         });
-        deal_maker
+        let matcher = Matcher {
+            shared: shared.clone(),
+            book: Book::new(tick),
+            phase,
+            events,
+            unpublished: 0,
+            failures: VecDeque::new(),
+        };
+        let thread = thread::Builder::new()
+            .name(format!("match {code}"))
+            .spawn(move || matcher.work())
+            .expect("matching thread starts");
+        Self { code, tick, shared, thread: thread.thread().clone(), bc, bc_candle }
     }
 
-    pub async fn place<FutI: Future<Output = ()>>(
-        &self,
-        order: Arc<order::Model>,
-        store: impl FnOnce(Arc<order::Model>) -> FutI,
-    ) {
-        let mut que = self.que.write().await;
-        store(order.clone()).await;
-        que.push_back(order);
-        self.watcher.send(false).unwrap();
-    }
-
-    pub async fn cancel<FutC: Future<Output = ()>>(
-        &self,
-        order: &order::Model,
-        cancel: impl FnOnce(Option<i64>) -> FutC,
-    ) {
-        let mut que = self.que.write().await;
-        cancel(
-            if let Ok(i) = que.binary_search_by_key(&order.seq, |order| order.seq) {
-                que.remove(i).map(|order| order.quantity)
-            } else {
-                let quantity = self.book.write().await.remove(order);
-                if let Some(quantity) = quantity {
-                    self.bc_order
-                    .send((order.dir, order.price, -quantity))
-                    .unwrap_or_default();
+    /// Queues `op` behind those taken before it, in room reserved for it if it was. An op sent
+    /// without room finding the queue full waits for the matching thread to take some.
+    pub async fn send(&self, op: Op) {
+        let mut op = op;
+        loop {
+            // Taken before trying, so no wakeup is missed in between.
+            let notified = self.shared.room.notified();
+            match self.shared.que.push(op) {
+                Ok(()) => break,
+                Err(back) => op = back,
+            }
+            self.thread.unpark();
+            notified.await;
+        }
+        self.shared.stats.peak.fetch_max(self.shared.que.len(), Ordering::Relaxed);
+        self.thread.unpark();
+    }
+
+    /// Holds room in the queue for a command, waiting for it as `overflow` says; refuses the
+    /// command as busy if none is made. Ops queued without room go in regardless.
+    pub async fn reserve(&self) -> Result<Reservation, Error> {
+        let wait = match self.shared.tuning.overflow {
+            Overflow::Reject => Duration::ZERO,
+            Overflow::Block => Duration::from_millis(self.shared.tuning.block_timeout_ms),
+        };
+        let room = async {
+            loop {
+                // Taken before looking, so no wakeup is missed in between.
+                let notified = self.shared.room.notified();
+                if let Some(reservation) = self.shared.take_room() {
+                    return reservation;
                 }
-                quantity
-            },
-        )
-        .await;
+                notified.await;
+            }
+        };
+        match time::timeout(wait, room).await {
+            Ok(reservation) => Ok(reservation),
+            Err(_) => self.shared.take_room().ok_or_else(|| {
+                self.shared.stats.refused.fetch_add(1, Ordering::Relaxed);
+                Error::Busy(self.code.clone())
+            }),
+        }
     }
 
-    pub async fn calc(&self) -> Option<DealCall> {
-        time::sleep(Duration::from_secs(3)).await;
-        self.watcher
-            .subscribe()
-            .wait_for(|&is_empty| is_empty)
-            .await
-            .unwrap();
-        let mut book = self.book.write().await;
+    /// How many ops wait to be matched, and how many have so far.
+    pub fn queue(&self) -> Queue {
+        let stats = &self.shared.stats;
+        Queue {
+            code: self.code.clone(),
+            depth: self.shared.que.len(),
+            reserved: self.shared.reserved.load(Ordering::Relaxed),
+            capacity: self.shared.tuning.queue_capacity,
+            peak: stats.peak.load(Ordering::Relaxed),
+            refused: stats.refused.load(Ordering::Relaxed),
+            taken: stats.taken.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Puts a resting order back on the book, as when restoring it.
+    pub async fn push(&self, order: &order::Model) {
+        self.send(Op::Push(order.clone())).await;
+    }
+
+    /// Picks up the last trade price and the price of the last auction of a restored book, then
+    /// announces its phase and, in the call auction, where it would uncross.
+    pub async fn resume(&self, last_price: Option<Decimal>, price_call: Option<Decimal>) {
+        self.send(Op::Resume { last_price, price_call }).await;
+    }
+
+    /// Takes over a book from a snapshot, then resumes it as it was.
+    pub async fn load(&self, book: Book) {
+        self.send(Op::Load(Box::new(book))).await;
+    }
+
+    /// Runs `f` on the book once the ops queued before are matched, `None` if matching failed
+    /// before it got to it.
+    async fn read<T: Send + 'static>(&self, f: impl FnOnce(&Book) -> T + Send + 'static) -> Option<T> {
         let (tx, rx) = oneshot::channel();
-        let deal;
-        *book = {
-            let mut book = std::mem::take(book.deref_mut());
-            rayon::spawn({
-                || {
-                    let deal = book.calc();
-                    tx.send((book, deal)).unwrap()
-                }
-            });
-            (book, deal) = rx.await.unwrap();
+        self.send(Op::Read(Box::new(move |book| tx.send(f(book)).unwrap_or_default()))).await;
+        rx.await.ok()
+    }
+
+    pub async fn is_consistent(&self) -> bool {
+        self.read(|book| book.is_consistent()).await.unwrap_or_default()
+    }
+
+    /// Every resting order with the sequence of the last tick they reflect.
+    pub async fn orders(&self) -> (u64, Vec<Change>) {
+        self.read(|book| (book.seq, book.orders())).await.unwrap_or_default()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.shared.halted.load(Ordering::Relaxed)
+    }
+
+    /// Rewrites every resting order through `f` once the orders queued before are matched.
+    pub async fn adjust(&self, f: impl Fn(Decimal, i64) -> (Decimal, i64) + Send + 'static) -> Vec<(i64, Decimal, i64)> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Op::Adjust(Box::new(f), tx)).await;
+        rx.await.unwrap_or_default()
+    }
+
+    /// Empties the book once the orders queued before are matched, returning what was on it.
+    pub async fn drain(&self) -> Vec<Change> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Op::Drain(tx)).await;
+        rx.await.unwrap_or_default()
+    }
+
+    /// Copies the book once the orders queued before are matched, `None` if matching failed
+    /// before it got to it.
+    pub async fn snapshot(&self) -> Option<Book> {
+        self.read(|book| {
+            let mut book = book.clone();
+            book.changes.clear();
             book
-        };
-        if let Some(DealCall { price, values }) = &deal {
-            self.bc_deal.send((None, *price, values.iter().map(|DealValue {quantity, ..}| *quantity).sum())).unwrap_or_default();
+        })
+        .await
+    }
+
+    /// The book as last published; while the matching thread is busy, it may trail the ticks
+    /// by up to `PUBLISH_EVERY` ops.
+    pub fn view(&self) -> Arc<Picture> {
+        self.shared.picture.borrow().clone()
+    }
+
+    /// Waits for a picture reflecting at least the tick numbered `seq`.
+    async fn view_at(&self, seq: u64) -> Arc<Picture> {
+        let mut rx = self.shared.picture.subscribe();
+        rx.wait_for(|picture| picture.seq >= seq)
+            .await
+            .map(|picture| picture.clone())
+            .unwrap_or_else(|_| self.view())
+    }
+
+    /// Subscribes before taking the snapshot so no tick is missed in between. Ticks already
+    /// reflected in a snapshot are skipped; a snapshot trailing the first tick after it, a reset
+    /// or a lagging subscriber get a fresh snapshot instead.
+    pub fn feed(self: Arc<Self>) -> impl Stream<Item = Feed> {
+        async_stream::stream! {
+            let mut rx = self.bc.subscribe();
+            let mut picture = Some(self.view());
+            let mut seq = 0;
+            loop {
+                if let Some(picture) = picture.take() {
+                    seq = picture.seq;
+                    yield Feed::Snapshot(picture);
+                }
+                match rx.recv().await {
+                    Ok(tick) if tick.seq <= seq => {}
+                    Ok(Tick { seq: next, .. }) if next > seq + 1 => picture = Some(self.view_at(next).await),
+                    Ok(Tick {
+                        seq: next,
+                        update: Update::Reset,
+                        ..
+                    }) => picture = Some(self.view_at(next).await),
+                    Err(RecvError::Lagged(_)) => picture = Some(self.view()),
+                    Ok(Tick {
+                        seq: next,
+                        update: Update::Imbalance(_) | Update::Phase(_),
+                        ..
+                    }) => seq = next,
+                    Ok(Tick {
+                        seq: next,
+                        update: Update::Order(dir, price, quantity),
+                        ..
+                    }) => {
+                        seq = next;
+                        yield Feed::Order(seq, dir, price, quantity);
+                    }
+                    Ok(Tick {
+                        seq: next,
+                        update: Update::Trade(dir, price, quantity),
+                        ..
+                    }) => {
+                        seq = next;
+                        yield Feed::Trade(seq, dir, price, quantity);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
         }
-        deal
     }
+}
 
-    pub async fn view(&self) -> Picture {
-        self.book.read().await.view()
+impl Drop for Security {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.thread.unpark();
     }
 }
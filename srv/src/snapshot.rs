@@ -0,0 +1,428 @@
+//! Snapshots of the books: every resting order by level and time priority, the working orders
+//! with what was reported on them and the phase, as of a journal entry. Startup takes up the
+//! latest snapshot the store has caught up on and replays only the entries after it.
+//!
+//! A snapshot is a file named after the seq of its entry, holding `SMS\0`, a little endian
+//! `u16` version and the CRC-32 of the body, then the body: the seq, the time in microseconds
+//! since the epoch, the phase, each book with its prices in ticks and each working order.
+
+use chrono::{DateTime, Utc};
+use entity::sea_orm_active_enums::Dir;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tokio::task;
+use tokio::time::{self, Duration, MissedTickBehavior};
+
+use crate::book::{Book, MAX_TICKS};
+use crate::codec::{Corrupt, Reader, Writer};
+use crate::config::SnapshotCommand;
+use crate::period::Period;
+use crate::report::Progress;
+use crate::state::AppState;
+
+const MAGIC: &[u8; 4] = b"SMS\0";
+const VERSION: u16 = 2;
+const HEADER: usize = 10;
+const EXTENSION: &str = "snap";
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// The last journal entry the snapshot reflects.
+    pub seq: i64,
+    pub at: DateTime<Utc>,
+    pub phase: Period,
+    /// By code.
+    pub books: BTreeMap<String, Book>,
+    /// By seq.
+    pub working: BTreeMap<i64, Progress>,
+}
+
+impl Snapshot {
+    /// Writes the snapshot into `dir` under its seq, unless one is there already, returning
+    /// the file written. The file appears whole or not at all.
+    pub fn write(&self, dir: &Path) -> io::Result<Option<PathBuf>> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{:020}.{EXTENSION}", self.seq));
+        if path.exists() {
+            return Ok(None);
+        }
+        let body = self.encode();
+        let mut bytes = Vec::with_capacity(HEADER + body.len());
+        bytes.extend(MAGIC);
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(crc32fast::hash(&body).to_le_bytes());
+        bytes.extend(body);
+        let partial = path.with_extension(format!("{EXTENSION}.tmp"));
+        let mut file = File::create(&partial)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&partial, &path)?;
+        File::open(dir)?.sync_all()?;
+        Ok(Some(path))
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < HEADER || &bytes[..4] != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not a snapshot"));
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("snapshot version {version}, expected {VERSION}"),
+            ));
+        }
+        let crc = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+        let body = &bytes[HEADER..];
+        if crc32fast::hash(body) != crc {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "snapshot fails its checksum",
+            ));
+        }
+        Self::decode(body)
+            .map_err(|Corrupt| io::Error::new(ErrorKind::InvalidData, "snapshot does not decode"))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.i64(self.seq);
+        w.i64(self.at.timestamp_micros());
+        w.period(self.phase);
+        w.len(self.books.len());
+        for (code, book) in &self.books {
+            w.str(code);
+            w.u64(book.seq);
+            w.decimal(book.tick());
+            w.opt_decimal(book.price_call);
+            w.opt_decimal(book.last_price);
+            for dir in [Dir::Buy, Dir::Sell] {
+                let levels = book.levels(dir);
+                w.len(levels.len());
+                for (price, level) in levels {
+                    w.i64(price);
+                    w.len(level.len);
+                    for (seq, quantity) in book.queue(level) {
+                        w.i64(seq);
+                        w.i64(quantity);
+                    }
+                }
+            }
+        }
+        w.len(self.working.len());
+        for progress in self.working.values() {
+            w.i64(progress.id);
+            w.i64(progress.seq);
+            w.opt_str(progress.cl_ord_id.as_deref());
+            w.str(&progress.code);
+            w.dir(progress.dir);
+            w.decimal(progress.price);
+            w.i64(progress.quantity);
+            w.i64(progress.cum);
+            w.decimal(progress.turnover);
+            w.i64(progress.report_seq);
+        }
+        w.0
+    }
+
+    fn decode(body: &[u8]) -> Result<Self, Corrupt> {
+        let mut r = Reader(body);
+        let seq = r.i64()?;
+        let at = DateTime::from_timestamp_micros(r.i64()?).ok_or(Corrupt)?;
+        let phase = r.period()?;
+        let mut books = BTreeMap::new();
+        for _ in 0..r.len(38)? {
+            let code = r.str()?;
+            let seq = r.u64()?;
+            let tick = r.decimal()?;
+            if tick <= Decimal::ZERO {
+                return Err(Corrupt);
+            }
+            let mut book = Book::new(tick);
+            book.seq = seq;
+            book.price_call = r.opt_decimal()?;
+            book.last_price = r.opt_decimal()?;
+            for dir in [Dir::Buy, Dir::Sell] {
+                for _ in 0..r.len(12)? {
+                    let price = r.i64()?;
+                    if !(1..=MAX_TICKS).contains(&price) {
+                        return Err(Corrupt);
+                    }
+                    for _ in 0..r.len(16)? {
+                        let seq = r.i64()?;
+                        let quantity = r.i64()?;
+                        book.rest(seq, dir, price, quantity);
+                    }
+                }
+            }
+            books.insert(code, book);
+        }
+        let mut working = BTreeMap::new();
+        for _ in 0..r.len(78)? {
+            let progress = Progress {
+                id: r.i64()?,
+                seq: r.i64()?,
+                cl_ord_id: r.opt_str()?,
+                code: r.str()?,
+                dir: r.dir()?,
+                price: r.decimal()?,
+                quantity: r.i64()?,
+                cum: r.i64()?,
+                turnover: r.decimal()?,
+                report_seq: r.i64()?,
+            };
+            working.insert(progress.seq, progress);
+        }
+        r.end()?;
+        Ok(Self {
+            seq,
+            at,
+            phase,
+            books,
+            working,
+        })
+    }
+}
+
+/// The snapshots in `dir` by seq, newest first.
+fn list(dir: &Path) -> io::Result<Vec<(i64, PathBuf)>> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(files),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == EXTENSION)
+        {
+            if let Some(seq) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.parse().ok())
+            {
+                files.push((seq, path));
+            }
+        }
+    }
+    files.sort_by(|(a, _), (b, _)| b.cmp(a));
+    Ok(files)
+}
+
+/// The latest snapshot in `dir` of an entry no later than `seq`. Snapshots that cannot be read
+/// are passed over for older ones.
+pub fn latest(dir: &Path, seq: i64) -> io::Result<Option<Snapshot>> {
+    for (at, path) in list(dir)? {
+        if at > seq {
+            continue;
+        }
+        match Snapshot::read(&path) {
+            Ok(snapshot) if snapshot.seq == at => return Ok(Some(snapshot)),
+            Ok(snapshot) => tracing::warn!(
+                "{}: passed over, it holds entry {}",
+                path.display(),
+                snapshot.seq
+            ),
+            Err(err) => tracing::warn!("{}: passed over: {err}", path.display()),
+        }
+    }
+    Ok(None)
+}
+
+/// Removes all but the `keep` latest snapshots in `dir`.
+fn prune(dir: &Path, keep: usize) -> io::Result<()> {
+    for (_, path) in list(dir)?.into_iter().skip(keep) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Takes a snapshot every `snapshot.interval_secs`, as long as the journal moved on.
+pub async fn run(state: AppState) {
+    let config = state.config.snapshot.clone();
+    let mut interval = time::interval(Duration::from_secs(config.interval_secs));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let Some(snapshot) = state.snapshot().await else {
+            tracing::error!("snapshot not taken: a book failed to copy or the store is behind");
+            continue;
+        };
+        let dir = config.dir.clone();
+        let keep = config.keep;
+        let written = task::spawn_blocking(move || {
+            let written = snapshot.write(&dir)?;
+            prune(&dir, keep)?;
+            Ok::<_, io::Error>(written)
+        })
+        .await;
+        match written {
+            Ok(Ok(Some(path))) => tracing::info!("snapshot written to {}", path.display()),
+            Ok(Ok(None)) => {}
+            Ok(Err(err)) => tracing::error!("snapshot not written: {err}"),
+            Err(err) => tracing::error!("snapshot not written: {err}"),
+        }
+    }
+}
+
+/// Reads the snapshot at `path` for the command line, naming it in any error.
+fn open(path: &Path) -> io::Result<Snapshot> {
+    Snapshot::read(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))
+}
+
+/// Carries out a `snapshot` command line.
+pub fn command(command: &SnapshotCommand) -> io::Result<()> {
+    match command {
+        SnapshotCommand::Inspect { file } => {
+            print!("{}", inspect(&open(file)?));
+            Ok(())
+        }
+        SnapshotCommand::Diff { a, b } => {
+            let changes = diff(&open(a)?, &open(b)?);
+            match changes.is_empty() {
+                true => println!("no differences"),
+                false => changes.iter().for_each(|change| println!("{change}")),
+            }
+            Ok(())
+        }
+    }
+}
+
+fn opt(price: Option<Decimal>) -> String {
+    price.map_or_else(|| "-".to_owned(), |price| price.to_string())
+}
+
+fn describe(progress: &Progress) -> String {
+    let mut line = format!(
+        "account {} {:?} {} {} at {}, {} filled, {} reports",
+        progress.id,
+        progress.dir,
+        progress.quantity,
+        progress.code,
+        progress.price,
+        progress.cum,
+        progress.report_seq
+    );
+    if let Some(cl_ord_id) = &progress.cl_ord_id {
+        line += &format!(", client order id {cl_ord_id}");
+    }
+    line
+}
+
+fn inspect(snapshot: &Snapshot) -> String {
+    let mut out = format!(
+        "entry {} at {}, {:?}\n",
+        snapshot.seq, snapshot.at, snapshot.phase
+    );
+    for (code, book) in &snapshot.books {
+        out += &format!(
+            "\n{code}: tick {}, last price {}, auction price {}, prices in ticks of {}\n",
+            book.seq,
+            opt(book.last_price),
+            opt(book.price_call),
+            book.tick()
+        );
+        for (side, dir) in [("offers", Dir::Sell), ("bids", Dir::Buy)] {
+            out += &format!("  {side}\n");
+            // Highest first, offers above bids, as on a ladder.
+            for (price, level) in book.levels(dir).rev() {
+                out += &format!("    {} {} in {}\n", book.price(price), level.sum, level.len);
+                for (seq, quantity) in book.queue(level) {
+                    out += &format!("      #{seq} {quantity}\n");
+                }
+            }
+        }
+    }
+    out += &format!("\n{} working orders\n", snapshot.working.len());
+    for (seq, progress) in &snapshot.working {
+        out += &format!("  #{seq} {}\n", describe(progress));
+    }
+    out
+}
+
+/// Every resting order of a book, by seq, with its side and price.
+fn resting(book: &Book) -> BTreeMap<i64, String> {
+    book.orders()
+        .into_iter()
+        .map(|order| {
+            let line = format!("{:?} {} at {}", order.dir, order.quantity, order.price);
+            (order.seq, line)
+        })
+        .collect()
+}
+
+/// What tells snapshot `a` from snapshot `b`, a line each.
+fn diff(a: &Snapshot, b: &Snapshot) -> Vec<String> {
+    let mut changes = Vec::new();
+    if a.seq != b.seq {
+        changes.push(format!("entry {} -> {}", a.seq, b.seq));
+    }
+    if a.phase != b.phase {
+        changes.push(format!("phase {:?} -> {:?}", a.phase, b.phase));
+    }
+    let empty = Book::default();
+    let codes: BTreeSet<_> = a.books.keys().chain(b.books.keys()).collect();
+    for code in codes {
+        let (before, after) = match (a.books.get(code), b.books.get(code)) {
+            (Some(before), Some(after)) => (before, after),
+            (Some(before), None) => {
+                changes.push(format!("{code}: book gone"));
+                (before, &empty)
+            }
+            (None, Some(after)) => {
+                changes.push(format!("{code}: book new"));
+                (&empty, after)
+            }
+            (None, None) => continue,
+        };
+        if before.last_price != after.last_price {
+            changes.push(format!(
+                "{code}: last price {} -> {}",
+                opt(before.last_price),
+                opt(after.last_price)
+            ));
+        }
+        if before.price_call != after.price_call {
+            changes.push(format!(
+                "{code}: auction price {} -> {}",
+                opt(before.price_call),
+                opt(after.price_call)
+            ));
+        }
+        let mut before = resting(before);
+        for (seq, after) in resting(after) {
+            match before.remove(&seq) {
+                Some(before) if before == after => {}
+                Some(before) => changes.push(format!("{code}: ~ #{seq} {before} -> {after}")),
+                None => changes.push(format!("{code}: + #{seq} {after}")),
+            }
+        }
+        for (seq, before) in before {
+            changes.push(format!("{code}: - #{seq} {before}"));
+        }
+    }
+    let mut before: BTreeMap<_, _> = a
+        .working
+        .iter()
+        .map(|(seq, progress)| (*seq, describe(progress)))
+        .collect();
+    for (seq, progress) in &b.working {
+        let after = describe(progress);
+        match before.remove(seq) {
+            Some(before) if before == after => {}
+            Some(before) => changes.push(format!("working: ~ #{seq} {before} -> {after}")),
+            None => changes.push(format!("working: + #{seq} {after}")),
+        }
+    }
+    for (seq, before) in before {
+        changes.push(format!("working: - #{seq} {before}"));
+    }
+    changes
+}
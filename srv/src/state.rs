@@ -1,192 +1,1035 @@
+use crate::book::Book;
+use crate::config::Config;
 use crate::deal::Deal;
-use crate::msg::{MsgBody, MsgBox};
+use crate::error::{Alert, Error};
+use crate::itch::Itch;
+use crate::journal::{Command, Entry, Journal};
+use crate::listing;
+use crate::msg::{MsgBody, MsgBox, Receiver, Takes};
 use crate::period::Period;
-use crate::security::Security;
-use chrono::Utc;
+use crate::projection::Projector;
+use crate::report::{ExecutionReport, Progress};
+use crate::security::{Event, Op, Reservation, Security};
+use crate::snapshot::{self, Snapshot};
+use crate::storage::{Effect, Orders, Storage};
+use chrono::{DateTime, FixedOffset, Utc};
+use dashmap::mapref::entry::Entry as Slot;
 use dashmap::mapref::one::RefMut;
 use dashmap::DashMap;
-use entity::sea_orm_active_enums::Dir;
-use entity::{order, rec, req};
-use futures::{Stream, StreamExt};
-use implicit_clone::sync::IString;
-use sea_orm::{
-    ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
-    IntoActiveModel, ModelTrait, Select, TransactionTrait,
-};
+use entity::sea_orm_active_enums::{AcStatus, OrdStatus, SecurityStatus};
+use entity::{ac, order, req, security};
+use futures::future::join_all;
+use rust_decimal::Decimal;
+use sea_orm::{DatabaseConnection, EntityTrait};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, oneshot, watch, Mutex};
+
+#[derive(Deserialize)]
+pub struct Place {
+    #[serde(flatten)]
+    pub order: order::Model,
+    pub cl_ord_id: Option<String>,
+}
+
+/// Names an order by its seq or by the client order id it was placed with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum OrderRef {
+    Seq(i64),
+    ClOrdId(String),
+}
+
+impl From<i64> for OrderRef {
+    fn from(seq: i64) -> Self {
+        OrderRef::Seq(seq)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Amend {
+    pub seq: OrderRef,
+    pub price: Decimal,
+    pub quantity: i64,
+    /// The client order id of the replacement.
+    pub cl_ord_id: Option<String>,
+}
+
+/// The seq of a placed order, and whether it was placed by this request or by an earlier one
+/// under the same client order id.
+#[derive(Clone, Copy, Debug)]
+pub enum Placed {
+    New(i64),
+    Existing(i64),
+}
+
+impl Placed {
+    pub fn seq(self) -> i64 {
+        match self {
+            Placed::New(seq) | Placed::Existing(seq) => seq,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
+    /// Reference data: accounts, securities and corporate actions.
     pub db: DatabaseConnection,
+    /// The order flow, as far as it was projected from the journal.
+    pub store: Arc<dyn Storage>,
     pub engine: Arc<DashMap<Arc<str>, Arc<Security>>>,
-    pub period: Arc<RwLock<Period>>,
+    /// The phase of the market as of the last journal entry.
+    pub period: Arc<watch::Sender<Period>>,
     pub msg_box: Arc<MsgBox>,
+    pub itch: Arc<Itch>,
+    /// Orders still working, by seq, with what has been reported on them.
+    pub working: Arc<DashMap<i64, Progress>>,
+    /// Client order ids journaled by each account, which the store may not have caught up on.
+    cl_ord_ids: Arc<DashMap<(i64, String), i64>>,
+    /// Held from appending an entry until its ops are queued, so the books take them in order.
+    journal: Arc<Mutex<Journal>>,
+    pub projector: Arc<Projector>,
+    pub alerts: broadcast::Sender<Alert>,
+    pub config: Arc<Config>,
 }
 
-async fn update_order(conn: &impl ConnectionTrait, model: order::Model) {
-    match model.quantity {
-        0 => {
-            model.delete(conn).await.unwrap();
+impl AppState {
+    /// Picks up the books from the latest snapshot the store has caught up on, or failing that
+    /// the order flow from the store, then replays the journal entries after it.
+    pub async fn restore(
+        config: Arc<Config>,
+        db: DatabaseConnection,
+        store: Arc<dyn Storage>,
+    ) -> Result<Self, Error> {
+        let (mut journal, entries) = Journal::open(&config.journal.path, config.journal.fsync)?;
+        let projected = store.projected().await?;
+        journal.skip_to(std::cmp::max(projected, store.last_seq().await?));
+        let snapshot = snapshot::latest(&config.snapshot.dir, projected)?;
+        // Stores projected before they kept the phase leave it to the journal.
+        let period = match (&snapshot, store.phase().await?) {
+            (Some(snapshot), _) => snapshot.phase,
+            (None, Some(period)) => period,
+            (None, None) => entries
+                .iter()
+                .rev()
+                .filter(|entry| entry.seq <= projected)
+                .find_map(|entry| match entry.command {
+                    Command::Phase(period) => Some(period),
+                    _ => None,
+                })
+                .unwrap_or(config.market.phase),
+        };
+        let engine = Arc::new(DashMap::new());
+        let state = Self {
+            projector: Arc::new(Projector::spawn(
+                store.clone(),
+                engine.clone(),
+                projected,
+                &config.projection,
+            )),
+            db,
+            store,
+            engine,
+            period: Arc::new(watch::Sender::new(period)),
+            msg_box: Arc::new(MsgBox::new(config.inbox.session_capacity)),
+            itch: Itch::new(config.engine.itch_capacity, config.engine.itch_retention),
+            working: Default::default(),
+            cl_ord_ids: Default::default(),
+            journal: Arc::new(Mutex::new(journal)),
+            alerts: broadcast::Sender::new(config.engine.alert_capacity),
+            config,
+        };
+        // Securities delisted since keep their books until the journal gets to the delisting.
+        let mut listed = HashSet::new();
+        for security in security::Entity::find().all(&state.db).await? {
+            state.entry_or_default(Arc::from(security.code.as_str()), security.tick_size);
+            if security.status != SecurityStatus::Delisted {
+                listed.insert(security.code);
+            }
+        }
+        let mut reports = HashMap::new();
+        for msg in state.store.msgs().await? {
+            state.msg_box.resume(msg.ack);
+            if msg.event_type == ExecutionReport::NAME {
+                if let Ok(report) = serde_json::from_value::<ExecutionReport>(msg.data.clone()) {
+                    reports.insert(report.seq, (msg.id, report));
+                }
+            }
+            if msg.delivered_at.is_none() {
+                let id = msg.id;
+                state
+                    .msg_box
+                    .unsent
+                    .entry(id)
+                    .or_default()
+                    .push(MsgBody::from(msg));
+            }
+        }
+        let from = match snapshot {
+            Some(snapshot) => {
+                tracing::info!(
+                    "starting from the snapshot of journal entry {}",
+                    snapshot.seq
+                );
+                for (code, book) in snapshot.books {
+                    let security = state
+                        .entry_or_default(Arc::from(code), book.tick())
+                        .value()
+                        .clone();
+                    security.load(book).await;
+                }
+                for (seq, progress) in snapshot.working {
+                    state.working.insert(seq, progress);
+                }
+                snapshot.seq
+            }
+            None => {
+                for (order, req) in state.store.orders(Orders::All).await? {
+                    let security = state
+                        .engine
+                        .get(order.code.as_str())
+                        .map(|security| security.value().clone());
+                    if let Some(security) = security {
+                        security.push(&order).await;
+                    }
+                    // Orders placed before reports were kept only show their fills in total.
+                    let resumed = reports
+                        .remove(&Some(order.seq))
+                        .and_then(|(id, report)| Progress::resume(id, &report));
+                    let mut progress = match resumed {
+                        Some(progress) => progress,
+                        None => Progress::new(
+                            req.id,
+                            &serde_json::from_value(req.body).unwrap_or(order.clone()),
+                            req.cl_ord_id,
+                        ),
+                    };
+                    // The book is the authority on what is left, also across corporate actions.
+                    if progress.report_seq == 0 {
+                        progress.cum = (progress.quantity - order.quantity).max(0);
+                        progress.turnover = order.price * Decimal::from(progress.cum);
+                    }
+                    progress.seq = order.seq;
+                    progress.price = order.price;
+                    progress.quantity = progress.cum + order.quantity;
+                    let status = match progress.cum {
+                        0 => OrdStatus::New,
+                        _ => OrdStatus::PartiallyFilled,
+                    };
+                    state.projector.after(Effect::Record {
+                        id: req.id,
+                        report: progress.current(status),
+                        created_at: req.created_at,
+                    });
+                    state.working.insert(order.seq, progress);
+                }
+
+                let books: Vec<_> = state
+                    .engine
+                    .iter()
+                    .map(|security| security.value().clone())
+                    .collect();
+                for security in books {
+                    let last = state.store.last_trade(&security.code, false).await?;
+                    let call = state.store.last_trade(&security.code, true).await?;
+                    security
+                        .resume(last.map(|rec| rec.price), call.map(|rec| rec.price))
+                        .await;
+                }
+                projected
+            }
+        };
+
+        let replay: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| entry.seq > from)
+            .collect();
+        if !replay.is_empty() {
+            tracing::info!(
+                "replaying journal entries {} to {}",
+                replay[0].seq,
+                replay[replay.len() - 1].seq
+            );
         }
-        _ => {
-            let mut model = model.into_active_model();
-            model.reset(order::Column::Quantity);
-            model.update(conn).await.unwrap();
+        for entry in &replay {
+            state.apply(entry, None).await;
         }
+        // Books of securities delisted before the entries replayed.
+        state.engine.retain(|code, _| listed.contains(&**code));
+        state.projector.settle().await;
+        state.check().await?;
+        Ok(state.clone())
     }
-}
 
-pub async fn trade(conn: &impl ConnectionTrait, code: String, deal: Deal) -> rec::Model {
-    let buyer_id = req::Entity::find_by_id(deal.value.seq_bid)
-        .one(conn)
-        .await
-        .unwrap()
-        .unwrap()
-        .id;
-    let seller_id = req::Entity::find_by_id(deal.value.seq_offer)
-        .one(conn)
-        .await
-        .unwrap()
-        .unwrap()
-        .id;
-    let mut bid = order::Entity::find_by_id(deal.value.seq_bid)
-        .one(conn)
-        .await
-        .unwrap()
-        .unwrap();
-    let mut offer = order::Entity::find_by_id(deal.value.seq_offer)
-        .one(conn)
-        .await
-        .unwrap()
-        .unwrap();
-    bid.quantity -= deal.value.quantity;
-    update_order(conn, bid).await;
-    offer.quantity -= deal.value.quantity;
-    update_order(conn, offer).await;
-    rec::ActiveModel {
-        code: ActiveValue::Set(code),
-        buyer_id: ActiveValue::Set(buyer_id),
-        seller_id: ActiveValue::Set(seller_id),
-        price: ActiveValue::Set(deal.price),
-        quantity: ActiveValue::Set(deal.value.quantity),
-        ..Default::default()
-    }
-    .insert(conn)
-    .await
-    .unwrap()
-}
+    /// Compares every book with the resting orders in the store and with the orders still
+    /// working, naming each order they disagree on.
+    async fn check(&self) -> Result<(), Error> {
+        let mut stored: HashMap<String, BTreeMap<i64, order::Model>> = HashMap::new();
+        for (order, _) in self.store.orders(Orders::All).await? {
+            stored
+                .entry(order.code.clone())
+                .or_default()
+                .insert(order.seq, order);
+        }
+        let books: Vec<_> = self
+            .engine
+            .iter()
+            .map(|security| security.value().clone())
+            .collect();
+        let mut problems = Vec::new();
+        for security in books {
+            let code = &security.code;
+            if !security.is_consistent().await {
+                problems.push(format!("{code}: the levels do not add up"));
+            }
+            let mut stored = stored.remove(&**code).unwrap_or_default();
+            for order in security.orders().await.1 {
+                let seq = order.seq;
+                match stored.remove(&seq) {
+                    Some(kept)
+                        if (kept.dir, kept.price, kept.quantity)
+                            == (order.dir, order.price, order.quantity) => {}
+                    Some(kept) => problems.push(format!(
+                        "{code}: order {seq} is {:?} {} at {} on the book, {:?} {} at {} in the store",
+                        order.dir, order.quantity, order.price, kept.dir, kept.quantity, kept.price
+                    )),
+                    None => problems.push(format!("{code}: order {seq} is not in the store")),
+                }
+                match self.working.get(&seq) {
+                    Some(progress) if progress.quantity - progress.cum == order.quantity => {}
+                    Some(_) => problems.push(format!(
+                        "{code}: order {seq} has another quantity left than was reported"
+                    )),
+                    None => problems.push(format!("{code}: order {seq} is not working")),
+                }
+            }
+            for seq in stored.keys() {
+                problems.push(format!("{code}: order {seq} is not on the book"));
+            }
+        }
+        for (code, orders) in stored {
+            for seq in orders.keys() {
+                problems.push(format!(
+                    "{code}: order {seq} is kept, but {code} is not traded"
+                ));
+            }
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Inconsistent(problems)),
+        }
+    }
 
-pub fn msg_deal(rec: &rec::Model, deal: Deal) -> [MsgBody; 2] {
-    [
-        MsgBody {
-            name: IString::Static("trade"),
-            data: Arc::new(order::Model {
-                seq: deal.value.seq_bid,
-                code: rec.code.clone(),
-                dir: Dir::Buy,
-                price: rec.price,
-                quantity: rec.quantity,
-            }),
-            happened_at: Utc::now().fixed_offset(),
-        },
-        MsgBody {
-            name: IString::Static("trade"),
-            data: Arc::new(order::Model {
-                seq: deal.value.seq_offer,
-                code: rec.code.clone(),
-                dir: Dir::Sell,
-                price: rec.price,
-                quantity: rec.quantity,
+    /// The book of `code`, if it is traded.
+    fn book(&self, code: &str) -> Option<Arc<Security>> {
+        self.engine
+            .get(code)
+            .map(|security| security.value().clone())
+    }
+
+    /// Carries out a journal entry: queues it with the books it goes to and starts its batch of
+    /// effects. Entries must be applied in seq order, as the journal lock ensures. `reply` is
+    /// told what a cancel or amend took off the book.
+    async fn apply(&self, entry: &Entry, reply: Option<oneshot::Sender<Option<i64>>>) {
+        let seq = entry.seq;
+        let created_at = entry.at.fixed_offset();
+        match entry.command.clone() {
+            Command::Place {
+                id,
+                order,
+                cl_ord_id,
+            } => {
+                if let Some(cl_ord_id) = &cl_ord_id {
+                    self.cl_ord_ids.insert((id, cl_ord_id.clone()), seq);
+                }
+                self.projector.begin(seq, 1);
+                match self.book(&order.code) {
+                    Some(security) => {
+                        self.accept(id, &order, cl_ord_id, created_at);
+                        security.send(Op::Place(Arc::new(order))).await;
+                    }
+                    // Delisted between the check and the journal.
+                    None => {
+                        let report = ExecutionReport {
+                            seq: Some(seq),
+                            ..ExecutionReport::rejected(&order, cl_ord_id, "unknown security")
+                        };
+                        self.deliver(Some(seq), id, report.msg());
+                        self.projector.done(seq);
+                    }
+                }
+            }
+            Command::Cancel {
+                id,
+                seq: order,
+                status,
+            } => {
+                self.projector.begin(seq, 1);
+                let code = self
+                    .working
+                    .get(&order)
+                    .map(|progress| progress.code.clone());
+                match code.and_then(|code| self.book(&code)) {
+                    Some(security) => {
+                        security
+                            .send(Op::Cancel {
+                                seq,
+                                id,
+                                order,
+                                status,
+                                reply,
+                            })
+                            .await
+                    }
+                    None => {
+                        if let Some(reply) = reply {
+                            reply.send(None).unwrap_or_default();
+                        }
+                        self.projector.done(seq);
+                    }
+                }
+            }
+            Command::Amend {
+                id,
+                seq: order,
+                price,
+                quantity,
+                cl_ord_id,
+            } => {
+                // The client order id is taken once the replacement goes on the book.
+                self.projector.begin(seq, 1);
+                let order = self.working.get(&order).map(|progress| progress.order());
+                match order.and_then(|order| Some((self.book(&order.code)?, order))) {
+                    Some((security, order)) => {
+                        let by = Arc::new(order::Model {
+                            seq,
+                            price,
+                            quantity,
+                            ..order.clone()
+                        });
+                        security
+                            .send(Op::Amend {
+                                id,
+                                order: order.seq,
+                                by,
+                                cl_ord_id,
+                                reply,
+                            })
+                            .await
+                    }
+                    None => {
+                        if let Some(reply) = reply {
+                            reply.send(None).unwrap_or_default();
+                        }
+                        self.projector.done(seq);
+                    }
+                }
+            }
+            Command::Phase(period) => {
+                self.period.send_replace(period);
+                let books: Vec<_> = self
+                    .engine
+                    .iter()
+                    .map(|security| security.value().clone())
+                    .collect();
+                self.projector.begin(seq, books.len());
+                self.projector.effect(seq, Effect::Phase(period));
+                for security in books {
+                    security.send(Op::Phase(seq, period)).await;
+                }
+            }
+            Command::Split {
+                code,
+                from,
+                to,
+                tick,
+                lot,
+            } => {
+                self.projector.begin(seq, 1);
+                let ratio = Decimal::from(from) / Decimal::from(to);
+                let adjusted = match self.book(&code) {
+                    Some(security) => {
+                        security
+                            .adjust(move |price, quantity| {
+                                (
+                                    std::cmp::max(tick, (price * ratio / tick).round() * tick),
+                                    quantity * to / from / lot * lot,
+                                )
+                            })
+                            .await
+                    }
+                    None => Vec::new(),
+                };
+                self.projector.effect(
+                    seq,
+                    Effect::Split {
+                        code,
+                        from,
+                        to,
+                        adjusted: adjusted.clone(),
+                    },
+                );
+                for (order, price, quantity) in adjusted {
+                    if quantity > 0 {
+                        if let Some(mut progress) = self.working.get_mut(&order) {
+                            // What was filled counts in new shares too; the money paid stays, so
+                            // the average price follows.
+                            progress.cum = progress.cum * to / from;
+                            progress.price = price;
+                            progress.quantity = progress.cum + quantity;
+                        }
+                    } else {
+                        self.report(seq, order, |progress| progress.report(OrdStatus::Expired));
+                    }
+                }
+                self.projector.done(seq);
+            }
+            Command::Rename { code, new } => {
+                self.projector.begin(seq, 1);
+                let (tick, mut orders) = match self.engine.remove(code.as_str()) {
+                    Some((_, security)) => (security.tick, security.drain().await),
+                    None => (Book::default().tick(), Vec::new()),
+                };
+                self.projector.effect(
+                    seq,
+                    Effect::Rename {
+                        code: code.clone(),
+                        new: new.clone(),
+                    },
+                );
+                for mut progress in self.working.iter_mut() {
+                    if progress.code == code {
+                        progress.code = new.clone();
+                    }
+                }
+                let security = self
+                    .entry_or_default(Arc::from(new.as_str()), tick)
+                    .value()
+                    .clone();
+                orders.sort_by_key(|order| order.seq);
+                for order in orders {
+                    security
+                        .push(&order::Model {
+                            seq: order.seq,
+                            code: new.clone(),
+                            dir: order.dir,
+                            price: order.price,
+                            quantity: order.quantity,
+                        })
+                        .await;
+                }
+                self.projector.done(seq);
+            }
+            Command::Delist { code } => {
+                self.projector.begin(seq, 1);
+                let orders = match self.engine.remove(code.as_str()) {
+                    Some((_, security)) => security.drain().await,
+                    None => Vec::new(),
+                };
+                for order in orders {
+                    self.projector.effect(seq, Effect::Withdraw(order.seq));
+                    self.report(seq, order.seq, |progress| {
+                        progress.report(OrdStatus::Expired)
+                    });
+                }
+                self.projector.done(seq);
+            }
+        }
+    }
+
+    /// Takes in what matching did with a journal entry on the book of `code`.
+    fn on_event(&self, code: &str, event: Event) {
+        match event {
+            Event::Traded(seq, deals) => {
+                self.projector.effect(
+                    seq,
+                    Effect::Trade {
+                        code: code.to_owned(),
+                        deals: deals.clone(),
+                        created_at: Utc::now().fixed_offset(),
+                    },
+                );
+                for deal in deals {
+                    self.report_trade(seq, deal);
+                }
+            }
+            Event::Withdrawn {
+                seq,
+                id,
+                order,
+                status,
+                quantity,
+            } => {
+                // A replacement is its own request.
+                if status != OrdStatus::Replaced {
+                    self.projector.effect(
+                        seq,
+                        Effect::Req(req::Model {
+                            seq,
+                            id,
+                            body: serde_json::json!({
+                                "cancel": {
+                                    "seq": order,
+                                    "quantity": quantity
+                                }
+                            }),
+                            created_at: Utc::now().fixed_offset(),
+                            cl_ord_id: None,
+                        }),
+                    );
+                }
+                self.projector.effect(seq, Effect::Withdraw(order));
+                self.report(seq, order, |progress| progress.report(status));
+            }
+            Event::Replacing {
+                id,
+                order,
+                cl_ord_id,
+            } => {
+                if let Some(cl_ord_id) = &cl_ord_id {
+                    self.cl_ord_ids.insert((id, cl_ord_id.clone()), order.seq);
+                }
+                self.accept(id, &order, cl_ord_id, Utc::now().fixed_offset())
+            }
+            Event::Refused(order) => {
+                // An order pushed back from the store at the start was kept before the floor.
+                let effect = Effect::Withdraw(order);
+                match self.projector.is_projected(order) {
+                    true => self.projector.after(effect),
+                    false => self.projector.effect(order, effect),
+                }
+                self.report(order, order, |progress| ExecutionReport {
+                    text: Some("price beyond the range of the book".into()),
+                    ..progress.report(OrdStatus::Rejected)
+                });
+            }
+            Event::Done(seq) => self.projector.done(seq),
+        }
+    }
+
+    /// Keeps the request placing `order` and the order itself, and reports it as new. Reported
+    /// before the order is queued, so no fill can be reported first.
+    fn accept(
+        &self,
+        id: i64,
+        order: &order::Model,
+        cl_ord_id: Option<String>,
+        created_at: DateTime<FixedOffset>,
+    ) {
+        let seq = order.seq;
+        self.projector.effect(
+            seq,
+            Effect::Req(req::Model {
+                seq,
+                id,
+                body: serde_json::json!(order),
+                created_at,
+                cl_ord_id: cl_ord_id.clone(),
             }),
-            happened_at: Utc::now().fixed_offset(),
-        },
-    ]
-}
+        );
+        self.projector.effect(seq, Effect::Order(order.clone()));
+        self.working
+            .insert(seq, Progress::new(id, order, cl_ord_id));
+        self.report(seq, seq, |progress| progress.report(OrdStatus::New));
+    }
 
-impl AppState {
-    pub async fn restore(db: DatabaseConnection) -> Self {
-        let state = Self {
-            db,
-            engine: Default::default(),
-            period: Arc::new(RwLock::new(Period::Suspense)),
-            msg_box: Default::default(),
+    /// Delivers a message to account `id` if it is online, and keeps it with the effects of
+    /// journal entry `seq`, or behind those begun if none.
+    fn deliver(&self, seq: Option<i64>, id: i64, body: MsgBody) {
+        // Kept already, and handed over from the store if not delivered.
+        if seq.is_some_and(|seq| self.projector.is_projected(seq)) {
+            return;
+        }
+        let (body, delivered) = self.msg_box.send(id, body);
+        let effect = Effect::Msg {
+            id,
+            body,
+            delivered,
         };
-        let mut orders = order::Entity::find().stream(&state.db).await.unwrap();
-        while let Some(Ok(order)) = orders.next().await {
-            let state = state.clone();
-            state
-                .entry_or_default(Arc::from(order.code.clone()))
-                .push(&order)
-                .await;
-        }
-        let mut msgs = entity::msg::Entity::find().stream(&state.db).await.unwrap();
-        while let Some(Ok(msg)) = msgs.next().await {
-            let entity::msg::Model {
+        match seq {
+            Some(seq) => self.projector.effect(seq, effect),
+            None => self.projector.after(effect),
+        }
+    }
+
+    /// Sends a message to account `id`, kept for when it comes online if it is not.
+    pub fn send(&self, id: i64, event: MsgBody) {
+        self.deliver(None, id, event);
+    }
+
+    /// Connects account `id` to its mailbox, handing over what it has not been sent yet.
+    pub async fn online(&self, id: i64, via: &'static str) -> (Receiver, Vec<MsgBody>) {
+        self.online_taking(id, via, Arc::new(|_| true)).await
+    }
+
+    /// Connects account `id` to its mailbox for the messages `takes` picks, handing over those
+    /// it has not been sent yet; the rest wait for another session.
+    pub async fn online_taking(
+        &self,
+        id: i64,
+        via: &'static str,
+        takes: Takes,
+    ) -> (Receiver, Vec<MsgBody>) {
+        let (rx, unsent) = self.msg_box.online(id, via, takes);
+        // The messages are delivered either way; should the store not get to record it, they
+        // are only delivered again after a restart.
+        if !unsent.is_empty() {
+            let acks = unsent.iter().map(|body| body.ack).collect();
+            self.projector.after(Effect::Delivered { id, acks });
+        }
+        (rx, unsent)
+    }
+
+    /// Acknowledges every message of account `id` up to `ack`, once it is kept.
+    pub async fn ack(&self, id: i64, ack: i64) -> Result<(), Error> {
+        self.account(id).await?;
+        if ack <= 0 || ack > self.msg_box.last() {
+            return Err(Error::NotFound("message"));
+        }
+        self.projector.after(Effect::Ack { id, ack });
+        self.projector.flush().await
+    }
+
+    /// Reports on working order `order` to its owner as part of journal entry `seq`, forgetting
+    /// the order once it is done.
+    fn report(&self, seq: i64, order: i64, f: impl FnOnce(&mut Progress) -> ExecutionReport) {
+        let (id, report) = match self.working.entry(order) {
+            Slot::Occupied(mut entry) => {
+                let report = f(entry.get_mut());
+                let id = entry.get().id;
+                if report.status.is_final() {
+                    entry.remove();
+                }
+                (id, report)
+            }
+            Slot::Vacant(_) => return,
+        };
+        self.projector.effect(
+            seq,
+            Effect::Record {
                 id,
-                event_type,
-                data,
-                happened_at,
-                ..
-            } = msg;
-            let name = IString::from(event_type);
-            let data = Arc::new(data);
-            state.msg_box.unsent.entry(id).or_default().push(MsgBody {
-                name,
-                data,
-                happened_at,
-            })
-        }
-        state.clone()
-    }
-
-    pub async fn send(&self, id: i64, event: MsgBody) {
-        if let Some(MsgBody {
-            name,
-            data,
-            happened_at,
-        }) = self.msg_box.send(id, event)
-        {
-            entity::msg::ActiveModel {
-                id: ActiveValue::Set(id),
-                event_type: ActiveValue::Set(name.to_string()),
-                data: ActiveValue::Set(serde_json::json!(data)),
-                happened_at: ActiveValue::Set(happened_at),
-                ..Default::default()
+                report: report.clone(),
+                created_at: Utc::now().fixed_offset(),
+            },
+        );
+        self.deliver(Some(seq), id, report.msg());
+    }
+
+    /// Reports a trade to the owners of both orders.
+    fn report_trade(&self, seq: i64, deal: Deal) {
+        for order in [deal.value.seq_bid, deal.value.seq_offer] {
+            self.report(seq, order, |progress| {
+                progress.fill(deal.price, deal.value.quantity)
+            });
+        }
+    }
+
+    /// Copies the books and the working orders as of the last journal entry, once the store
+    /// has caught up on it; `None` if a book failed to copy or the store did not catch up.
+    pub async fn snapshot(&self) -> Option<Snapshot> {
+        let journal = self.journal.lock().await;
+        let books: Vec<_> = self
+            .engine
+            .iter()
+            .map(|security| security.value().clone())
+            .collect();
+        let copies = join_all(books.iter().map(|security| security.snapshot())).await;
+        let mut snapshot = Snapshot {
+            seq: journal.last(),
+            at: Utc::now(),
+            phase: *self.period.borrow(),
+            books: BTreeMap::new(),
+            working: self
+                .working
+                .iter()
+                .map(|progress| (*progress.key(), progress.value().clone()))
+                .collect(),
+        };
+        drop(journal);
+        for (security, book) in books.iter().zip(copies) {
+            snapshot.books.insert(security.code.to_string(), book?);
+        }
+        self.projector.flush().await.ok()?;
+        Some(snapshot)
+    }
+
+    /// Journals `command` and carries it out.
+    pub async fn submit(&self, command: Command) -> Result<i64, Error> {
+        let mut journal = self.journal.lock().await;
+        let entry = journal.append(Utc::now(), command)?;
+        self.apply(&entry, None).await;
+        Ok(entry.seq)
+    }
+
+    /// Journals `command`, runs `commit` once the journal holds it and carries the command out.
+    /// The command is carried out even when `commit` fails, as replaying the journal would.
+    pub async fn submit_after(
+        &self,
+        command: Command,
+        commit: impl Future<Output = Result<(), Error>>,
+    ) -> Result<i64, Error> {
+        let mut journal = self.journal.lock().await;
+        let entry = journal.append(Utc::now(), command)?;
+        let committed = commit.await;
+        self.apply(&entry, None).await;
+        committed.map(|()| entry.seq)
+    }
+
+    /// Looks up account `id`.
+    pub async fn account(&self, id: i64) -> Result<ac::Model, Error> {
+        ac::Entity::find_by_id(id)
+            .one(&self.db)
+            .await?
+            .ok_or(Error::NotFound("account"))
+    }
+
+    /// Checks that `ac` may place `order` now, holding room for it in the queue of its book.
+    async fn admit(&self, ac: &ac::Model, order: &order::Model) -> Result<Reservation, Error> {
+        if *self.period.borrow() == Period::Suspense {
+            return Err(Error::Forbidden("market is closed"));
+        }
+        if ac.status != AcStatus::Active {
+            return Err(Error::Forbidden("account is not active"));
+        }
+        let listed = security::Entity::find_by_id(order.code.clone())
+            .one(&self.db)
+            .await?
+            .ok_or(Error::NotFound("security"))?;
+        listing::check(&listed, order)?;
+        let engine = self.book(&order.code).ok_or(Error::NotFound("security"))?;
+        if engine.is_halted() {
+            return Err(Error::Halted(engine.code.clone()));
+        }
+        engine.reserve().await
+    }
+
+    /// The seq of the request account `id` made under `cl_ord_id`, journaled or stored.
+    async fn find_cl_ord(&self, id: i64, cl_ord_id: &str) -> Result<Option<i64>, Error> {
+        if let Some(seq) = self.cl_ord_ids.get(&(id, cl_ord_id.to_owned())) {
+            return Ok(Some(*seq));
+        }
+        Ok(self
+            .store
+            .find_cl_ord(id, cl_ord_id)
+            .await?
+            .map(|req| req.seq))
+    }
+
+    /// Places an order. A client order id makes it idempotent: resubmitting under one the account
+    /// already used returns the order placed then instead of placing another.
+    pub async fn place(
+        &self,
+        id: i64,
+        order: order::Model,
+        cl_ord_id: Option<String>,
+    ) -> Result<Placed, Error> {
+        if let Some(cl_ord_id) = &cl_ord_id {
+            if let Some(seq) = self.find_cl_ord(id, cl_ord_id).await? {
+                return Ok(Placed::Existing(seq));
+            }
+        }
+        let ac = self.account(id).await?;
+        let _room = match self.admit(&ac, &order).await {
+            Ok(room) => room,
+            Err(err) => {
+                let report = ExecutionReport::rejected(&order, cl_ord_id, &err.to_string());
+                self.send(id, report.msg());
+                return Err(err);
+            }
+        };
+        let mut journal = self.journal.lock().await;
+        // A concurrent resubmission got there first.
+        if let Some(cl_ord_id) = &cl_ord_id {
+            if let Some(seq) = self.cl_ord_ids.get(&(id, cl_ord_id.clone())) {
+                return Ok(Placed::Existing(*seq));
             }
-            .insert(&self.db)
-            .await
-            .unwrap();
         }
+        let entry = journal.append(
+            Utc::now(),
+            Command::Place {
+                id,
+                order,
+                cl_ord_id,
+            },
+        )?;
+        self.apply(&entry, None).await;
+        Ok(Placed::New(entry.seq))
     }
 
-    pub fn entry_or_default(&self, code: Arc<str>) -> RefMut<'_, Arc<str>, Arc<Security>> {
-        let state = self.clone();
-        self.engine
-            .entry(code.clone())
-            .or_insert(Arc::new(Security::new(self.period.clone(), move |deal| {
-                let code = code.to_string();
-                let state = state.clone();
-                async move {
-                    let txn = state.db.begin().await.unwrap();
-                    let rec = trade(&txn, code, deal).await;
-                    txn.commit().await.unwrap();
-                    let [msg_buyer, msg_seller] = msg_deal(&rec, deal);
-                    state.send(rec.buyer_id, msg_buyer).await;
-                    state.send(rec.seller_id, msg_seller).await;
-                }
-            })))
-    }
-
-    pub async fn stream_query<E: EntityTrait>(
+    /// Resolves a reference to an order of account `id` to its seq.
+    async fn resolve(&self, id: i64, order: OrderRef) -> Result<i64, Error> {
+        match order {
+            OrderRef::Seq(seq) => Ok(seq),
+            OrderRef::ClOrdId(cl_ord_id) => self
+                .find_cl_ord(id, &cl_ord_id)
+                .await?
+                .ok_or(Error::NotFound("order")),
+        }
+    }
+
+    /// Looks up a working order of account `id`.
+    fn own_order(&self, id: i64, seq: i64) -> Result<order::Model, Error> {
+        match self.working.get(&seq) {
+            Some(progress) if progress.id == id => Ok(progress.order()),
+            Some(_) => Err(Error::Forbidden("order belongs to another account")),
+            None => Err(Error::NotFound("order")),
+        }
+    }
+
+    /// The working orders matching `f`.
+    pub fn orders(&self, f: impl Fn(&Progress) -> bool) -> Vec<(i64, order::Model)> {
+        self.working
+            .iter()
+            .filter(|progress| f(progress))
+            .map(|progress| (progress.id, progress.order()))
+            .collect()
+    }
+
+    /// Cancels a resting order on behalf of its owner, returning the quantity taken off the book.
+    pub async fn cancel_order(
         &self,
-        query: Select<E>,
-    ) -> impl Stream<Item = Result<E::Model, DbErr>> {
-        let db = self.db.clone();
-        async_stream::stream! {
-            for await model in query.stream(&db).await.unwrap() {
-                yield model;
+        id: i64,
+        order: impl Into<OrderRef>,
+    ) -> Result<Option<i64>, Error> {
+        if let Period::Call | Period::Suspense = *self.period.borrow() {
+            return Err(Error::Forbidden("orders cannot be canceled now"));
+        }
+        let seq = self.resolve(id, order.into()).await?;
+        let order = self.own_order(id, seq)?;
+        let room = match self.book(&order.code) {
+            Some(engine) => Some(engine.reserve().await?),
+            None => None,
+        };
+        self.take_off(id, order, OrdStatus::Canceled, room).await
+    }
+
+    /// Replaces a resting order with a new price and quantity; the replacement loses time priority.
+    /// Resubmitting under the client order id of an earlier replacement returns that replacement.
+    pub async fn amend(
+        &self,
+        id: i64,
+        order: impl Into<OrderRef>,
+        price: Decimal,
+        quantity: i64,
+        cl_ord_id: Option<String>,
+    ) -> Result<Placed, Error> {
+        if let Some(cl_ord_id) = &cl_ord_id {
+            if let Some(seq) = self.find_cl_ord(id, cl_ord_id).await? {
+                return Ok(Placed::Existing(seq));
             }
         }
+        if let Period::Call | Period::Suspense = *self.period.borrow() {
+            return Err(Error::Forbidden("orders cannot be amended now"));
+        }
+        let seq = self.resolve(id, order.into()).await?;
+        let order = self.own_order(id, seq)?;
+        let replacement = order::Model {
+            price,
+            quantity,
+            ..order.clone()
+        };
+        let room = self.admit(&self.account(id).await?, &replacement).await?;
+        let (tx, rx) = oneshot::channel();
+        let entry = {
+            let mut journal = self.journal.lock().await;
+            if let Some(cl_ord_id) = &cl_ord_id {
+                if let Some(seq) = self.cl_ord_ids.get(&(id, cl_ord_id.clone())) {
+                    return Ok(Placed::Existing(*seq));
+                }
+            }
+            let entry = journal.append(
+                Utc::now(),
+                Command::Amend {
+                    id,
+                    seq,
+                    price,
+                    quantity,
+                    cl_ord_id: cl_ord_id.clone(),
+                },
+            )?;
+            // Held until the book answers, so a repeat is not journaled meanwhile.
+            if let Some(cl_ord_id) = &cl_ord_id {
+                self.cl_ord_ids.insert((id, cl_ord_id.clone()), entry.seq);
+            }
+            self.apply(&entry, Some(tx)).await;
+            drop(room);
+            entry
+        };
+        match rx.await {
+            Ok(Some(_)) => Ok(Placed::New(entry.seq)),
+            Ok(None) => {
+                // Nothing was placed under the client order id after all.
+                if let Some(cl_ord_id) = cl_ord_id {
+                    self.cl_ord_ids.remove(&(id, cl_ord_id));
+                }
+                Err(Error::Gone)
+            }
+            Err(_) => Err(Error::Halted(Arc::from(order.code))),
+        }
+    }
+
+    pub async fn cancel(&self, id: i64, order: order::Model) -> Result<Option<i64>, Error> {
+        self.withdraw(id, order, OrdStatus::Canceled).await
+    }
+
+    /// Takes an order off the book, reporting it with `status`.
+    pub async fn withdraw(
+        &self,
+        id: i64,
+        order: order::Model,
+        status: OrdStatus,
+    ) -> Result<Option<i64>, Error> {
+        self.take_off(id, order, status, None).await
+    }
+
+    /// Takes an order off the book in the room held for it, if any.
+    async fn take_off(
+        &self,
+        id: i64,
+        order: order::Model,
+        status: OrdStatus,
+        room: Option<Reservation>,
+    ) -> Result<Option<i64>, Error> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut journal = self.journal.lock().await;
+            let entry = journal.append(
+                Utc::now(),
+                Command::Cancel {
+                    id,
+                    seq: order.seq,
+                    status,
+                },
+            )?;
+            self.apply(&entry, Some(tx)).await;
+            drop(room);
+        }
+        rx.await.map_err(|_| Error::Halted(Arc::from(order.code)))
+    }
+
+    /// The book of `code`, opened in ticks of `tick` if there is none yet.
+    pub fn entry_or_default(
+        &self,
+        code: Arc<str>,
+        tick: Decimal,
+    ) -> RefMut<'_, Arc<str>, Arc<Security>> {
+        let state = self.clone();
+        self.engine.entry(code.clone()).or_insert_with(|| {
+            let security = Arc::new(Security::new(
+                code.clone(),
+                tick,
+                *self.period.borrow(),
+                self.alerts.clone(),
+                Arc::new(self.config.engine.clone()),
+                {
+                    let code = code.clone();
+                    Arc::new(move |event| state.on_event(&code, event))
+                },
+            ));
+            self.itch.attach(code, &security);
+            security
+        })
     }
 }
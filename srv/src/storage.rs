@@ -0,0 +1,164 @@
+//! Where the order flow is kept: requests, resting orders, trades and their candles, positions,
+//! account messages and order history. Reference data (accounts, securities, corporate actions)
+//! stays in the database whichever storage is used.
+//!
+//! The order flow is written only by the projector, which applies the effects of the journal's
+//! commands in batches; everything else reads.
+
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use entity::{candle, msg, order, order_history, position, rec, req};
+use futures::stream::BoxStream;
+use rust_decimal::Decimal;
+
+use crate::candle::History;
+use crate::deal::Deal;
+use crate::error::Error;
+use crate::history::{self, One};
+use crate::inbox;
+use crate::msg::MsgBody;
+use crate::period::Period;
+use crate::report::ExecutionReport;
+use crate::tape;
+
+mod memory;
+mod orm;
+
+pub use memory::Memory;
+pub use orm::Orm;
+
+/// Which resting orders to list; they come oldest first.
+#[derive(Clone, Copy)]
+pub enum Orders {
+    All,
+    /// Those placed by an account.
+    Of(i64),
+}
+
+/// A change to the order flow.
+#[derive(Clone)]
+pub enum Effect {
+    Req(req::Model),
+    /// An order goes on the book; its request is kept before it.
+    Order(order::Model),
+    /// Order `seq` leaves the book.
+    Withdraw(i64),
+    /// Deals on `code`: what is left of the orders, the positions, the trades and their candles.
+    Trade {
+        code: String,
+        deals: Vec<Deal>,
+        created_at: DateTime<FixedOffset>,
+    },
+    /// `from` shares of `code` become `to`: positions are scaled to whole shares, rounding toward
+    /// zero as the fraction is paid in cash, and each adjusted order `(seq, price, quantity)`
+    /// takes its new price and quantity or leaves the books if none is left.
+    Split {
+        code: String,
+        from: i64,
+        to: i64,
+        adjusted: Vec<(i64, Decimal, i64)>,
+    },
+    /// Orders, trades, positions and order history of `code` move to `new`.
+    Rename {
+        code: String,
+        new: String,
+    },
+    /// Keeps the history of account `id`'s order in step with `report`. A report arriving after
+    /// a later one on the same order leaves the history alone.
+    Record {
+        id: i64,
+        report: ExecutionReport,
+        created_at: DateTime<FixedOffset>,
+    },
+    /// A message for account `id`, delivered already or kept for when it comes online.
+    Msg {
+        id: i64,
+        body: MsgBody,
+        delivered: bool,
+    },
+    /// The messages of account `id` numbered `acks` are delivered.
+    Delivered {
+        id: i64,
+        acks: Vec<i64>,
+    },
+    /// Account `id` acknowledged its messages up to `ack`.
+    Ack {
+        id: i64,
+        ack: i64,
+    },
+    /// Deletes the messages acknowledged before the time given, but for the last message kept,
+    /// which numbering goes on from.
+    Purge(DateTime<FixedOffset>),
+    /// The market moves to a phase, kept with how far the journal was projected.
+    Phase(Period),
+}
+
+/// Effects applied all together or not at all, with the seq of the journal entry they complete.
+pub struct Batch {
+    pub seq: Option<i64>,
+    pub effects: Vec<Effect>,
+}
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Applies `batch`, then counts the journal as projected up to its seq, returning the candles
+    /// its trades went into as they now stand.
+    async fn project(&self, batch: &Batch) -> Result<Vec<candle::Model>, Error>;
+
+    /// The seq of the last journal entry projected, 0 if none was.
+    async fn projected(&self) -> Result<i64, Error>;
+
+    /// The phase of the market as of the last journal entry projected, if one was projected.
+    async fn phase(&self) -> Result<Option<Period>, Error>;
+
+    /// The highest seq a request was kept under.
+    async fn last_seq(&self) -> Result<i64, Error>;
+
+    /// The request account `id` made under `cl_ord_id`.
+    async fn find_cl_ord(&self, id: i64, cl_ord_id: &str) -> Result<Option<req::Model>, Error>;
+
+    /// The requests of account `id`, newest first.
+    fn actions(&self, id: i64) -> BoxStream<'static, Result<req::Model, Error>>;
+
+    /// Resting orders with the requests that placed them.
+    async fn orders(&self, which: Orders) -> Result<Vec<(order::Model, req::Model)>, Error>;
+
+    /// Trades on `code`, newest first.
+    async fn sales(&self, code: &str, filter: &tape::Filter) -> Result<Vec<rec::Model>, Error>;
+
+    /// The last trade on `code`, or the last of its auctions if `auction`.
+    async fn last_trade(&self, code: &str, auction: bool) -> Result<Option<rec::Model>, Error>;
+
+    /// Trades account `id` was party to, newest first.
+    async fn trades_of(&self, id: i64, filter: &tape::Filter) -> Result<Vec<rec::Model>, Error>;
+
+    /// The trades that filled `order`, oldest first.
+    async fn fills(&self, order: &order_history::Model) -> Result<Vec<rec::Model>, Error>;
+
+    /// The candles of `code` over the span of `history`, newest first.
+    async fn candles(&self, code: &str, history: &History) -> Result<Vec<candle::Model>, Error>;
+
+    async fn positions(&self, id: i64) -> Result<Vec<position::Model>, Error>;
+
+    /// Accounts holding `code`, long or short.
+    async fn holders(&self, code: &str) -> Result<Vec<position::Model>, Error>;
+
+    /// Every message kept, by ack.
+    async fn msgs(&self) -> Result<Vec<msg::Model>, Error>;
+
+    /// The messages of account `id`, newest first.
+    async fn inbox(&self, id: i64, filter: &inbox::Filter) -> Result<Vec<msg::Model>, Error>;
+
+    /// The messages of account `id` after `ack`, oldest first.
+    async fn msgs_after(&self, id: i64, ack: i64) -> Result<Vec<msg::Model>, Error>;
+
+    /// One order of an account, by seq or client order id.
+    async fn order(&self, one: One) -> Result<Option<order_history::Model>, Error>;
+
+    /// An account's orders, newest first.
+    async fn order_history(
+        &self,
+        id: i64,
+        filter: &history::Filter,
+    ) -> Result<Vec<order_history::Model>, Error>;
+}
@@ -0,0 +1,534 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use entity::sea_orm_active_enums::Dir;
+use entity::{candle, msg, order, order_history, position, rec, req};
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+use super::{Batch, Effect, Orders, Storage};
+use crate::candle::{bar, fold, History, Span};
+use crate::deal::Deal;
+use crate::error::Error;
+use crate::history::{self, One};
+use crate::inbox;
+use crate::msg::MsgBody;
+use crate::period::Period;
+use crate::report::ExecutionReport;
+use crate::tape;
+
+/// Keeps the order flow in maps, for as long as the process runs.
+#[derive(Default)]
+pub struct Memory {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    reqs: BTreeMap<i64, req::Model>,
+    cl_ord_ids: HashMap<(i64, String), i64>,
+    orders: BTreeMap<i64, order::Model>,
+    recs: BTreeMap<i64, rec::Model>,
+    /// By code, span and start.
+    candles: BTreeMap<(String, i64, DateTime<FixedOffset>), candle::Model>,
+    positions: BTreeMap<(i64, String), i64>,
+    msgs: BTreeMap<i64, msg::Model>,
+    history: BTreeMap<i64, order_history::Model>,
+    projected: i64,
+    phase: Option<Period>,
+}
+
+impl Memory {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Inner {
+    fn insert_req(&mut self, req: req::Model) {
+        if let Some(cl_ord_id) = &req.cl_ord_id {
+            self.cl_ord_ids.insert((req.id, cl_ord_id.clone()), req.seq);
+        }
+        self.reqs.insert(req.seq, req);
+    }
+
+    fn resting(&self, seq: i64) -> Option<(order::Model, i64)> {
+        let order = self.orders.get(&seq)?;
+        let req = self.reqs.get(&seq)?;
+        Some((order.clone(), req.id))
+    }
+
+    /// Checks that every effect of a batch can be applied, so that applying them cannot stop
+    /// halfway.
+    fn check(&self, effects: &[Effect]) -> Result<(), Error> {
+        // What is left of the orders the batch touches, `None` once off the book.
+        let mut left = HashMap::new();
+        for effect in effects {
+            match effect {
+                Effect::Order(order) => {
+                    if !self.reqs.contains_key(&order.seq)
+                        && !effects.iter().any(|effect| match effect {
+                            Effect::Req(req) => req.seq == order.seq,
+                            _ => false,
+                        })
+                    {
+                        return Err(Error::NotFound("request"));
+                    }
+                    left.insert(order.seq, Some(order.quantity));
+                }
+                &Effect::Withdraw(seq) => {
+                    left.insert(seq, None);
+                }
+                Effect::Trade { deals, .. } => {
+                    for deal in deals {
+                        for seq in [deal.value.seq_bid, deal.value.seq_offer] {
+                            let quantity = match left.get(&seq) {
+                                Some(&quantity) => quantity,
+                                None => self.resting(seq).map(|(order, _)| order.quantity),
+                            }
+                            .ok_or(Error::NotFound("order"))?;
+                            left.insert(seq, Some(quantity - deal.value.quantity));
+                        }
+                    }
+                }
+                Effect::Split { adjusted, .. } => {
+                    for &(seq, _, quantity) in adjusted {
+                        left.insert(seq, (quantity > 0).then_some(quantity));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, effect: &Effect, candles: &mut Vec<candle::Model>) {
+        match effect {
+            Effect::Req(req) => self.insert_req(req.clone()),
+            Effect::Order(order) => {
+                self.orders.insert(order.seq, order.clone());
+            }
+            Effect::Withdraw(seq) => {
+                self.orders.remove(seq);
+            }
+            Effect::Trade {
+                code,
+                deals,
+                created_at,
+            } => {
+                for &deal in deals {
+                    let rec = self.trade(code, deal, *created_at);
+                    candles.extend(self.candles(&rec));
+                }
+            }
+            Effect::Split {
+                code,
+                from,
+                to,
+                adjusted,
+            } => self.split(code, *from, *to, adjusted),
+            Effect::Rename { code, new } => self.rename(code, new),
+            Effect::Record {
+                id,
+                report,
+                created_at,
+            } => self.record(*id, report, *created_at),
+            Effect::Msg {
+                id,
+                body,
+                delivered,
+            } => self.insert_msg(*id, body, *delivered),
+            Effect::Delivered { id, acks } => {
+                let now = Utc::now().fixed_offset();
+                for ack in acks {
+                    if let Some(msg) = self.msgs.get_mut(ack) {
+                        if msg.id == *id && msg.delivered_at.is_none() {
+                            msg.delivered_at = Some(now);
+                        }
+                    }
+                }
+            }
+            &Effect::Ack { id, ack } => {
+                let now = Utc::now().fixed_offset();
+                self.msgs
+                    .range_mut(..=ack)
+                    .map(|(_, msg)| msg)
+                    .filter(|msg| msg.id == id && msg.acked_at.is_none())
+                    .for_each(|msg| msg.acked_at = Some(now));
+            }
+            &Effect::Purge(before) => {
+                let last = self.msgs.last_key_value().map(|(ack, _)| *ack);
+                self.msgs.retain(|ack, msg| {
+                    Some(*ack) == last || msg.acked_at.is_none_or(|acked_at| acked_at >= before)
+                });
+            }
+            &Effect::Phase(period) => self.phase = Some(period),
+        }
+    }
+
+    fn trade(&mut self, code: &str, deal: Deal, created_at: DateTime<FixedOffset>) -> rec::Model {
+        let mut party = |seq: i64, quantity: i64| {
+            let id = self.reqs[&seq].id;
+            if let Some(order) = self.orders.get_mut(&seq) {
+                order.quantity -= deal.value.quantity;
+                if order.quantity == 0 {
+                    self.orders.remove(&seq);
+                }
+            }
+            *self.positions.entry((id, code.to_owned())).or_default() += quantity;
+            id
+        };
+        let buyer_id = party(deal.value.seq_bid, deal.value.quantity);
+        let seller_id = party(deal.value.seq_offer, -deal.value.quantity);
+        let rec = rec::Model {
+            ack: self.recs.last_key_value().map_or(1, |(ack, _)| ack + 1),
+            code: code.to_owned(),
+            buyer_id,
+            seller_id,
+            price: deal.price,
+            quantity: deal.value.quantity,
+            created_at,
+            seq_bid: Some(deal.value.seq_bid),
+            seq_offer: Some(deal.value.seq_offer),
+            aggressor: deal.aggressor,
+            auction: deal.aggressor.is_none(),
+        };
+        self.recs.insert(rec.ack, rec.clone());
+        rec
+    }
+
+    /// Folds a trade into the bar of every span it falls in.
+    fn candles(&mut self, rec: &rec::Model) -> Vec<candle::Model> {
+        Span::ALL
+            .into_iter()
+            .map(|span| {
+                let key = (rec.code.clone(), span.secs(), span.start(rec.created_at));
+                self.candles
+                    .entry(key)
+                    .and_modify(|candle| fold(candle, rec))
+                    .or_insert_with(|| bar(span, rec))
+                    .clone()
+            })
+            .collect()
+    }
+
+    fn split(&mut self, code: &str, from: i64, to: i64, adjusted: &[(i64, Decimal, i64)]) {
+        for &(seq, price, quantity) in adjusted {
+            if quantity > 0 {
+                if let Some(order) = self.orders.get_mut(&seq) {
+                    order.price = price;
+                    order.quantity = quantity;
+                }
+            } else {
+                self.orders.remove(&seq);
+            }
+        }
+        for ((_, held), quantity) in self.positions.iter_mut() {
+            if held == code {
+                *quantity = *quantity * to / from;
+            }
+        }
+    }
+
+    fn rename(&mut self, code: &str, new: &str) {
+        let Inner {
+            orders,
+            recs,
+            positions,
+            history,
+            ..
+        } = self;
+        orders
+            .values_mut()
+            .filter(|order| order.code == code)
+            .for_each(|order| order.code = new.to_owned());
+        history
+            .values_mut()
+            .filter(|order| order.code == code)
+            .for_each(|order| order.code = new.to_owned());
+        recs.values_mut()
+            .filter(|rec| rec.code == code)
+            .for_each(|rec| rec.code = new.to_owned());
+        let moved: Vec<_> = positions
+            .keys()
+            .filter(|(_, held)| held == code)
+            .cloned()
+            .collect();
+        for key in moved {
+            if let Some(quantity) = positions.remove(&key) {
+                positions.insert((key.0, new.to_owned()), quantity);
+            }
+        }
+    }
+
+    fn insert_msg(&mut self, id: i64, body: &MsgBody, delivered: bool) {
+        self.msgs.insert(
+            body.ack,
+            msg::Model {
+                ack: body.ack,
+                id,
+                event_type: body.name.to_string(),
+                data: serde_json::json!(body.data),
+                happened_at: body.happened_at,
+                delivered_at: delivered.then(|| Utc::now().fixed_offset()),
+                acked_at: None,
+            },
+        );
+    }
+
+    fn record(&mut self, id: i64, report: &ExecutionReport, created_at: DateTime<FixedOffset>) {
+        let Some(seq) = report.seq else {
+            return;
+        };
+        if let Some(order) = self.history.get(&seq) {
+            if order.report_seq > report.report_seq {
+                return;
+            }
+        }
+        let created_at = self
+            .history
+            .get(&seq)
+            .map_or(created_at, |order| order.created_at);
+        self.history.insert(
+            seq,
+            order_history::Model {
+                seq,
+                id,
+                cl_ord_id: report.cl_ord_id.clone(),
+                code: report.code.clone(),
+                dir: report.dir,
+                price: report.price,
+                quantity: report.quantity,
+                cum_quantity: report.cum_quantity,
+                avg_price: report.avg_price,
+                status: report.status,
+                report_seq: report.report_seq,
+                created_at,
+                updated_at: Utc::now().fixed_offset(),
+            },
+        );
+    }
+
+    fn trades(&self, filter: &tape::Filter, f: impl Fn(&rec::Model) -> bool) -> Vec<rec::Model> {
+        self.recs
+            .values()
+            .rev()
+            .filter(|rec| filter.cursor.is_none_or(|cursor| rec.ack < cursor))
+            .filter(|rec| filter.code.as_ref().is_none_or(|code| &rec.code == code))
+            .filter(|rec| filter.from.is_none_or(|from| rec.created_at >= from))
+            .filter(|rec| filter.to.is_none_or(|to| rec.created_at < to))
+            .filter(|rec| f(rec))
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect()
+    }
+
+    fn positions(&self, f: impl Fn(i64, &str, i64) -> bool) -> Vec<position::Model> {
+        self.positions
+            .iter()
+            .filter(|((id, code), quantity)| f(*id, code, **quantity))
+            .map(|((id, code), quantity)| position::Model {
+                id: *id,
+                code: code.clone(),
+                quantity: *quantity,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for Memory {
+    async fn project(&self, batch: &Batch) -> Result<Vec<candle::Model>, Error> {
+        let mut inner = self.lock();
+        inner.check(&batch.effects)?;
+        let mut candles = Vec::new();
+        for effect in &batch.effects {
+            inner.apply(effect, &mut candles);
+        }
+        if let Some(seq) = batch.seq {
+            inner.projected = seq;
+        }
+        Ok(candles)
+    }
+
+    async fn projected(&self) -> Result<i64, Error> {
+        Ok(self.lock().projected)
+    }
+
+    async fn phase(&self) -> Result<Option<Period>, Error> {
+        Ok(self.lock().phase)
+    }
+
+    async fn last_seq(&self) -> Result<i64, Error> {
+        Ok(self.lock().reqs.last_key_value().map_or(0, |(seq, _)| *seq))
+    }
+
+    async fn find_cl_ord(&self, id: i64, cl_ord_id: &str) -> Result<Option<req::Model>, Error> {
+        let inner = self.lock();
+        Ok(inner
+            .cl_ord_ids
+            .get(&(id, cl_ord_id.to_owned()))
+            .and_then(|seq| inner.reqs.get(seq))
+            .cloned())
+    }
+
+    fn actions(&self, id: i64) -> BoxStream<'static, Result<req::Model, Error>> {
+        let actions: Vec<_> = self
+            .lock()
+            .reqs
+            .values()
+            .rev()
+            .filter(|req| req.id == id)
+            .cloned()
+            .map(Ok)
+            .collect();
+        stream::iter(actions).boxed()
+    }
+
+    async fn orders(&self, which: Orders) -> Result<Vec<(order::Model, req::Model)>, Error> {
+        let inner = self.lock();
+        Ok(inner
+            .orders
+            .values()
+            .filter_map(|order| Some((order.clone(), inner.reqs.get(&order.seq)?.clone())))
+            .filter(|(_, req)| match which {
+                Orders::All => true,
+                Orders::Of(id) => req.id == id,
+            })
+            .collect())
+    }
+
+    async fn sales(&self, code: &str, filter: &tape::Filter) -> Result<Vec<rec::Model>, Error> {
+        Ok(self.lock().trades(filter, |rec| rec.code == code))
+    }
+
+    async fn last_trade(&self, code: &str, auction: bool) -> Result<Option<rec::Model>, Error> {
+        Ok(self
+            .lock()
+            .recs
+            .values()
+            .rev()
+            .find(|rec| rec.code == code && (rec.auction || !auction))
+            .cloned())
+    }
+
+    async fn trades_of(&self, id: i64, filter: &tape::Filter) -> Result<Vec<rec::Model>, Error> {
+        Ok(self
+            .lock()
+            .trades(filter, |rec| rec.buyer_id == id || rec.seller_id == id))
+    }
+
+    async fn fills(&self, order: &order_history::Model) -> Result<Vec<rec::Model>, Error> {
+        Ok(self
+            .lock()
+            .recs
+            .values()
+            .filter(|rec| match order.dir {
+                Dir::Buy => rec.seq_bid == Some(order.seq),
+                Dir::Sell => rec.seq_offer == Some(order.seq),
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn candles(&self, code: &str, history: &History) -> Result<Vec<candle::Model>, Error> {
+        let span = history.span;
+        let from = history.from.map(|from| span.start(from));
+        Ok(self
+            .lock()
+            .candles
+            .values()
+            .rev()
+            .filter(|candle| candle.code == code && candle.span == span.secs())
+            .filter(|candle| from.is_none_or(|from| candle.start >= from))
+            .filter(|candle| history.to.is_none_or(|to| candle.start < to))
+            .take(history.limit() as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn positions(&self, id: i64) -> Result<Vec<position::Model>, Error> {
+        Ok(self.lock().positions(|holder, _, _| holder == id))
+    }
+
+    async fn holders(&self, code: &str) -> Result<Vec<position::Model>, Error> {
+        Ok(self
+            .lock()
+            .positions(|_, held, quantity| held == code && quantity != 0))
+    }
+
+    async fn msgs(&self) -> Result<Vec<msg::Model>, Error> {
+        Ok(self.lock().msgs.values().cloned().collect())
+    }
+
+    async fn inbox(&self, id: i64, filter: &inbox::Filter) -> Result<Vec<msg::Model>, Error> {
+        Ok(self
+            .lock()
+            .msgs
+            .range(..filter.cursor.unwrap_or(i64::MAX))
+            .rev()
+            .map(|(_, msg)| msg)
+            .filter(|msg| msg.id == id)
+            .filter(|msg| {
+                filter
+                    .unacked
+                    .is_none_or(|unacked| msg.acked_at.is_none() == unacked)
+            })
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn msgs_after(&self, id: i64, ack: i64) -> Result<Vec<msg::Model>, Error> {
+        Ok(self
+            .lock()
+            .msgs
+            .range(ack.saturating_add(1)..)
+            .map(|(_, msg)| msg)
+            .filter(|msg| msg.id == id)
+            .cloned()
+            .collect())
+    }
+
+    async fn order(&self, one: One) -> Result<Option<order_history::Model>, Error> {
+        let inner = self.lock();
+        let seq = match (one.seq, one.cl_ord_id) {
+            (Some(seq), _) => seq,
+            (None, Some(cl_ord_id)) => match inner.cl_ord_ids.get(&(one.id, cl_ord_id)) {
+                Some(&seq) => seq,
+                None => return Ok(None),
+            },
+            (None, None) => return Err(Error::Invalid("give seq or cl_ord_id")),
+        };
+        Ok(inner
+            .history
+            .get(&seq)
+            .filter(|order| order.id == one.id)
+            .cloned())
+    }
+
+    async fn order_history(
+        &self,
+        id: i64,
+        filter: &history::Filter,
+    ) -> Result<Vec<order_history::Model>, Error> {
+        Ok(self
+            .lock()
+            .history
+            .values()
+            .rev()
+            .filter(|order| order.id == id)
+            .filter(|order| filter.cursor.is_none_or(|cursor| order.seq < cursor))
+            .filter(|order| filter.status.is_none_or(|status| order.status == status))
+            .filter(|order| filter.code.as_ref().is_none_or(|code| &order.code == code))
+            .filter(|order| filter.from.is_none_or(|from| order.created_at >= from))
+            .filter(|order| filter.to.is_none_or(|to| order.created_at < to))
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+}
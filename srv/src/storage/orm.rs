@@ -0,0 +1,585 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use entity::sea_orm_active_enums::Dir;
+use entity::{candle, msg, order, order_history, position, projection, rec, req};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use rust_decimal::Decimal;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection,
+    DbErr, EntityTrait, IntoActiveModel, ModelTrait, QueryFilter, QueryOrder, QuerySelect, Select,
+    TransactionTrait,
+};
+
+use super::{Batch, Effect, Orders, Storage};
+use crate::candle::History;
+use crate::deal::Deal;
+use crate::error::Error;
+use crate::history::{self, One};
+use crate::inbox;
+use crate::period::Period;
+use crate::report::ExecutionReport;
+use crate::tape;
+
+/// Keeps the order flow in the SeaORM database, Postgres or SQLite.
+pub struct Orm {
+    db: DatabaseConnection,
+}
+
+/// The row of `projection` that follows the journal.
+const JOURNAL: &str = "journal";
+
+impl Orm {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+async fn update_order(conn: &impl ConnectionTrait, model: order::Model) -> Result<(), DbErr> {
+    match model.quantity {
+        0 => {
+            model.delete(conn).await?;
+        }
+        _ => {
+            let mut model = model.into_active_model();
+            model.reset(order::Column::Quantity);
+            model.update(conn).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn update_position(
+    conn: &impl ConnectionTrait,
+    id: i64,
+    code: String,
+    quantity: i64,
+) -> Result<(), DbErr> {
+    position::Entity::insert(position::ActiveModel {
+        id: ActiveValue::Set(id),
+        code: ActiveValue::Set(code),
+        quantity: ActiveValue::Set(quantity),
+    })
+    .on_conflict(
+        OnConflict::columns([position::Column::Id, position::Column::Code])
+            .value(
+                position::Column::Quantity,
+                Expr::col((position::Entity, position::Column::Quantity)).add(quantity),
+            )
+            .to_owned(),
+    )
+    .exec(conn)
+    .await?;
+    Ok(())
+}
+
+async fn resting(
+    conn: &impl ConnectionTrait,
+    seq: i64,
+) -> Result<Option<(order::Model, i64)>, DbErr> {
+    Ok(
+        match order::Entity::find_by_id(seq)
+            .find_also_related(req::Entity)
+            .one(conn)
+            .await?
+        {
+            Some((order, Some(req))) => Some((order, req.id)),
+            _ => None,
+        },
+    )
+}
+
+async fn trade(
+    conn: &impl ConnectionTrait,
+    code: &str,
+    deal: Deal,
+    created_at: DateTime<FixedOffset>,
+) -> Result<rec::Model, Error> {
+    let (mut bid, buyer_id) = resting(conn, deal.value.seq_bid)
+        .await?
+        .ok_or(Error::NotFound("order"))?;
+    let (mut offer, seller_id) = resting(conn, deal.value.seq_offer)
+        .await?
+        .ok_or(Error::NotFound("order"))?;
+    bid.quantity -= deal.value.quantity;
+    update_order(conn, bid).await?;
+    offer.quantity -= deal.value.quantity;
+    update_order(conn, offer).await?;
+    update_position(conn, buyer_id, code.to_owned(), deal.value.quantity).await?;
+    update_position(conn, seller_id, code.to_owned(), -deal.value.quantity).await?;
+    Ok(rec::ActiveModel {
+        code: ActiveValue::Set(code.to_owned()),
+        buyer_id: ActiveValue::Set(buyer_id),
+        seller_id: ActiveValue::Set(seller_id),
+        price: ActiveValue::Set(deal.price),
+        quantity: ActiveValue::Set(deal.value.quantity),
+        created_at: ActiveValue::Set(created_at),
+        seq_bid: ActiveValue::Set(Some(deal.value.seq_bid)),
+        seq_offer: ActiveValue::Set(Some(deal.value.seq_offer)),
+        aggressor: ActiveValue::Set(deal.aggressor),
+        auction: ActiveValue::Set(deal.aggressor.is_none()),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?)
+}
+
+async fn split(
+    conn: &impl ConnectionTrait,
+    code: &str,
+    from: i64,
+    to: i64,
+    adjusted: &[(i64, Decimal, i64)],
+) -> Result<(), DbErr> {
+    for &(seq, price, quantity) in adjusted {
+        if quantity > 0 {
+            order::ActiveModel {
+                seq: ActiveValue::Unchanged(seq),
+                price: ActiveValue::Set(price),
+                quantity: ActiveValue::Set(quantity),
+                ..Default::default()
+            }
+            .update(conn)
+            .await?;
+        } else {
+            order::Entity::delete_by_id(seq).exec(conn).await?;
+        }
+    }
+    position::Entity::update_many()
+        .col_expr(
+            position::Column::Quantity,
+            Expr::col(position::Column::Quantity).mul(to).div(from),
+        )
+        .filter(position::Column::Code.eq(code))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+async fn rename(conn: &impl ConnectionTrait, code: &str, new: &str) -> Result<(), DbErr> {
+    order::Entity::update_many()
+        .col_expr(order::Column::Code, Expr::value(new))
+        .filter(order::Column::Code.eq(code))
+        .exec(conn)
+        .await?;
+    order_history::Entity::update_many()
+        .col_expr(order_history::Column::Code, Expr::value(new))
+        .filter(order_history::Column::Code.eq(code))
+        .exec(conn)
+        .await?;
+    rec::Entity::update_many()
+        .col_expr(rec::Column::Code, Expr::value(new))
+        .filter(rec::Column::Code.eq(code))
+        .exec(conn)
+        .await?;
+    position::Entity::update_many()
+        .col_expr(position::Column::Code, Expr::value(new))
+        .filter(position::Column::Code.eq(code))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+async fn record(
+    conn: &impl ConnectionTrait,
+    id: i64,
+    report: &ExecutionReport,
+    created_at: DateTime<FixedOffset>,
+) -> Result<(), DbErr> {
+    let Some(seq) = report.seq else {
+        return Ok(());
+    };
+    order_history::Entity::insert(order_history::ActiveModel {
+        seq: ActiveValue::Set(seq),
+        id: ActiveValue::Set(id),
+        cl_ord_id: ActiveValue::Set(report.cl_ord_id.clone()),
+        code: ActiveValue::Set(report.code.clone()),
+        dir: ActiveValue::Set(report.dir),
+        price: ActiveValue::Set(report.price),
+        quantity: ActiveValue::Set(report.quantity),
+        cum_quantity: ActiveValue::Set(report.cum_quantity),
+        avg_price: ActiveValue::Set(report.avg_price),
+        status: ActiveValue::Set(report.status),
+        report_seq: ActiveValue::Set(report.report_seq),
+        created_at: ActiveValue::Set(created_at),
+        updated_at: ActiveValue::Set(Utc::now().fixed_offset()),
+    })
+    .on_conflict(
+        OnConflict::column(order_history::Column::Seq)
+            .update_columns([
+                order_history::Column::Code,
+                order_history::Column::Price,
+                order_history::Column::Quantity,
+                order_history::Column::CumQuantity,
+                order_history::Column::AvgPrice,
+                order_history::Column::Status,
+                order_history::Column::ReportSeq,
+                order_history::Column::UpdatedAt,
+            ])
+            .action_and_where(
+                Expr::col((order_history::Entity, order_history::Column::ReportSeq))
+                    .lte(report.report_seq),
+            )
+            .to_owned(),
+    )
+    .exec_without_returning(conn)
+    .await?;
+    Ok(())
+}
+
+async fn delivered(conn: &impl ConnectionTrait, id: i64, acks: &[i64]) -> Result<(), DbErr> {
+    msg::Entity::update_many()
+        .col_expr(
+            msg::Column::DeliveredAt,
+            Expr::value(Utc::now().fixed_offset()),
+        )
+        .filter(msg::Column::Id.eq(id))
+        .filter(msg::Column::Ack.is_in(acks.iter().copied()))
+        .filter(msg::Column::DeliveredAt.is_null())
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+/// Applies `effect`, adding the candles its trades go into to `candles`.
+async fn apply(
+    conn: &impl ConnectionTrait,
+    effect: &Effect,
+    candles: &mut Vec<candle::Model>,
+) -> Result<(), Error> {
+    match effect {
+        Effect::Req(req) => {
+            req.clone().into_active_model().insert(conn).await?;
+        }
+        Effect::Order(order) => {
+            order.clone().into_active_model().insert(conn).await?;
+        }
+        &Effect::Withdraw(seq) => {
+            order::Entity::delete_by_id(seq).exec(conn).await?;
+        }
+        Effect::Trade {
+            code,
+            deals,
+            created_at,
+        } => {
+            for &deal in deals {
+                let rec = trade(conn, code, deal, *created_at).await?;
+                candles.extend(crate::candle::update(conn, &rec).await?);
+            }
+        }
+        Effect::Split {
+            code,
+            from,
+            to,
+            adjusted,
+        } => split(conn, code, *from, *to, adjusted).await?,
+        Effect::Rename { code, new } => rename(conn, code, new).await?,
+        Effect::Record {
+            id,
+            report,
+            created_at,
+        } => record(conn, *id, report, *created_at).await?,
+        Effect::Msg {
+            id,
+            body,
+            delivered,
+        } => {
+            msg::ActiveModel {
+                ack: ActiveValue::Set(body.ack),
+                id: ActiveValue::Set(*id),
+                event_type: ActiveValue::Set(body.name.to_string()),
+                data: ActiveValue::Set(serde_json::json!(body.data)),
+                happened_at: ActiveValue::Set(body.happened_at),
+                delivered_at: ActiveValue::Set(delivered.then(|| Utc::now().fixed_offset())),
+                acked_at: ActiveValue::Set(None),
+            }
+            .insert(conn)
+            .await?;
+        }
+        Effect::Delivered { id, acks } => delivered(conn, *id, acks).await?,
+        &Effect::Ack { id, ack } => {
+            msg::Entity::update_many()
+                .col_expr(msg::Column::AckedAt, Expr::value(Utc::now().fixed_offset()))
+                .filter(msg::Column::Id.eq(id))
+                .filter(msg::Column::Ack.lte(ack))
+                .filter(msg::Column::AckedAt.is_null())
+                .exec(conn)
+                .await?;
+        }
+        &Effect::Purge(before) => {
+            let last = msg::Entity::find()
+                .order_by_desc(msg::Column::Ack)
+                .one(conn)
+                .await?;
+            if let Some(last) = last {
+                msg::Entity::delete_many()
+                    .filter(msg::Column::AckedAt.lt(before))
+                    .filter(msg::Column::Ack.lt(last.ack))
+                    .exec(conn)
+                    .await?;
+            }
+        }
+        // Kept with the projected seq.
+        Effect::Phase(_) => {}
+    }
+    Ok(())
+}
+
+/// Narrows a page of trades by `filter`.
+fn filter_trades(mut select: Select<rec::Entity>, filter: &tape::Filter) -> Select<rec::Entity> {
+    if let Some(code) = &filter.code {
+        select = select.filter(rec::Column::Code.eq(code.clone()));
+    }
+    if let Some(from) = filter.from {
+        select = select.filter(rec::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = filter.to {
+        select = select.filter(rec::Column::CreatedAt.lt(to));
+    }
+    if let Some(cursor) = filter.cursor {
+        select = select.filter(rec::Column::Ack.lt(cursor));
+    }
+    select.order_by_desc(rec::Column::Ack).limit(filter.limit())
+}
+
+#[async_trait]
+impl Storage for Orm {
+    async fn project(&self, batch: &Batch) -> Result<Vec<candle::Model>, Error> {
+        let txn = self.db.begin().await?;
+        let mut candles = Vec::new();
+        for effect in &batch.effects {
+            apply(&txn, effect, &mut candles).await?;
+        }
+        if let Some(seq) = batch.seq {
+            let phase = batch.effects.iter().rev().find_map(|effect| match effect {
+                Effect::Phase(period) => Some(format!("{period:?}")),
+                _ => None,
+            });
+            let mut columns = vec![projection::Column::Seq];
+            if phase.is_some() {
+                columns.push(projection::Column::Phase);
+            }
+            projection::Entity::insert(projection::ActiveModel {
+                name: ActiveValue::Set(JOURNAL.to_owned()),
+                seq: ActiveValue::Set(seq),
+                phase: ActiveValue::Set(phase),
+            })
+            .on_conflict(
+                OnConflict::column(projection::Column::Name)
+                    .update_columns(columns)
+                    .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+        Ok(candles)
+    }
+
+    async fn projected(&self) -> Result<i64, Error> {
+        Ok(projection::Entity::find_by_id(JOURNAL)
+            .one(&self.db)
+            .await?
+            .map_or(0, |projection| projection.seq))
+    }
+
+    async fn phase(&self) -> Result<Option<Period>, Error> {
+        let projection = projection::Entity::find_by_id(JOURNAL)
+            .one(&self.db)
+            .await?;
+        Ok(projection
+            .and_then(|projection| projection.phase)
+            .and_then(|phase| serde_json::from_value(serde_json::Value::String(phase)).ok()))
+    }
+
+    async fn last_seq(&self) -> Result<i64, Error> {
+        Ok(req::Entity::find()
+            .order_by_desc(req::Column::Seq)
+            .one(&self.db)
+            .await?
+            .map_or(0, |req| req.seq))
+    }
+
+    async fn find_cl_ord(&self, id: i64, cl_ord_id: &str) -> Result<Option<req::Model>, Error> {
+        Ok(req::Entity::find()
+            .filter(req::Column::Id.eq(id))
+            .filter(req::Column::ClOrdId.eq(cl_ord_id))
+            .one(&self.db)
+            .await?)
+    }
+
+    fn actions(&self, id: i64) -> BoxStream<'static, Result<req::Model, Error>> {
+        let db = self.db.clone();
+        async_stream::stream! {
+            let query = req::Entity::find()
+                .filter(req::Column::Id.eq(id))
+                .order_by_desc(req::Column::CreatedAt);
+            match query.stream(&db).await {
+                Ok(models) => for await model in models {
+                    yield model.map_err(Error::from);
+                },
+                Err(err) => yield Err(err.into()),
+            }
+        }
+        .boxed()
+    }
+
+    async fn orders(&self, which: Orders) -> Result<Vec<(order::Model, req::Model)>, Error> {
+        let select = order::Entity::find().find_also_related(req::Entity);
+        let select = match which {
+            Orders::All => select,
+            Orders::Of(id) => select.filter(req::Column::Id.eq(id)),
+        };
+        Ok(select
+            .order_by_asc(order::Column::Seq)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .filter_map(|(order, req)| Some((order, req?)))
+            .collect())
+    }
+
+    async fn sales(&self, code: &str, filter: &tape::Filter) -> Result<Vec<rec::Model>, Error> {
+        Ok(filter_trades(
+            rec::Entity::find().filter(rec::Column::Code.eq(code)),
+            filter,
+        )
+        .all(&self.db)
+        .await?)
+    }
+
+    async fn last_trade(&self, code: &str, auction: bool) -> Result<Option<rec::Model>, Error> {
+        let mut select = rec::Entity::find().filter(rec::Column::Code.eq(code));
+        if auction {
+            select = select.filter(rec::Column::Auction.eq(true));
+        }
+        Ok(select.order_by_desc(rec::Column::Ack).one(&self.db).await?)
+    }
+
+    async fn trades_of(&self, id: i64, filter: &tape::Filter) -> Result<Vec<rec::Model>, Error> {
+        Ok(filter_trades(
+            rec::Entity::find().filter(
+                Condition::any()
+                    .add(rec::Column::BuyerId.eq(id))
+                    .add(rec::Column::SellerId.eq(id)),
+            ),
+            filter,
+        )
+        .all(&self.db)
+        .await?)
+    }
+
+    async fn fills(&self, order: &order_history::Model) -> Result<Vec<rec::Model>, Error> {
+        let column = match order.dir {
+            Dir::Buy => rec::Column::SeqBid,
+            Dir::Sell => rec::Column::SeqOffer,
+        };
+        Ok(rec::Entity::find()
+            .filter(column.eq(order.seq))
+            .order_by_asc(rec::Column::Ack)
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn candles(&self, code: &str, history: &History) -> Result<Vec<candle::Model>, Error> {
+        let mut select = candle::Entity::find()
+            .filter(candle::Column::Code.eq(code))
+            .filter(candle::Column::Span.eq(history.span.secs()))
+            .order_by_desc(candle::Column::Start)
+            .limit(history.limit());
+        if let Some(from) = history.from {
+            select = select.filter(candle::Column::Start.gte(history.span.start(from)));
+        }
+        if let Some(to) = history.to {
+            select = select.filter(candle::Column::Start.lt(to));
+        }
+        Ok(select.all(&self.db).await?)
+    }
+
+    async fn positions(&self, id: i64) -> Result<Vec<position::Model>, Error> {
+        Ok(position::Entity::find()
+            .filter(position::Column::Id.eq(id))
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn holders(&self, code: &str) -> Result<Vec<position::Model>, Error> {
+        Ok(position::Entity::find()
+            .filter(position::Column::Code.eq(code))
+            .filter(position::Column::Quantity.ne(0))
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn msgs(&self) -> Result<Vec<msg::Model>, Error> {
+        Ok(msg::Entity::find()
+            .order_by_asc(msg::Column::Ack)
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn inbox(&self, id: i64, filter: &inbox::Filter) -> Result<Vec<msg::Model>, Error> {
+        let mut select = msg::Entity::find().filter(msg::Column::Id.eq(id));
+        if let Some(cursor) = filter.cursor {
+            select = select.filter(msg::Column::Ack.lt(cursor));
+        }
+        select = match filter.unacked {
+            Some(true) => select.filter(msg::Column::AckedAt.is_null()),
+            Some(false) => select.filter(msg::Column::AckedAt.is_not_null()),
+            None => select,
+        };
+        Ok(select
+            .order_by_desc(msg::Column::Ack)
+            .limit(filter.limit())
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn msgs_after(&self, id: i64, ack: i64) -> Result<Vec<msg::Model>, Error> {
+        Ok(msg::Entity::find()
+            .filter(msg::Column::Id.eq(id))
+            .filter(msg::Column::Ack.gt(ack))
+            .order_by_asc(msg::Column::Ack)
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn order(&self, one: One) -> Result<Option<order_history::Model>, Error> {
+        let select = order_history::Entity::find().filter(order_history::Column::Id.eq(one.id));
+        let select = match (one.seq, one.cl_ord_id) {
+            (Some(seq), _) => select.filter(order_history::Column::Seq.eq(seq)),
+            (None, Some(cl_ord_id)) => select.filter(order_history::Column::ClOrdId.eq(cl_ord_id)),
+            (None, None) => return Err(Error::Invalid("give seq or cl_ord_id")),
+        };
+        Ok(select.one(&self.db).await?)
+    }
+
+    async fn order_history(
+        &self,
+        id: i64,
+        filter: &history::Filter,
+    ) -> Result<Vec<order_history::Model>, Error> {
+        let mut select = order_history::Entity::find().filter(order_history::Column::Id.eq(id));
+        if let Some(status) = filter.status {
+            select = select.filter(order_history::Column::Status.eq(status));
+        }
+        if let Some(code) = &filter.code {
+            select = select.filter(order_history::Column::Code.eq(code.clone()));
+        }
+        if let Some(from) = filter.from {
+            select = select.filter(order_history::Column::CreatedAt.gte(from));
+        }
+        if let Some(to) = filter.to {
+            select = select.filter(order_history::Column::CreatedAt.lt(to));
+        }
+        if let Some(cursor) = filter.cursor {
+            select = select.filter(order_history::Column::Seq.lt(cursor));
+        }
+        Ok(select
+            .order_by_desc(order_history::Column::Seq)
+            .limit(filter.limit())
+            .all(&self.db)
+            .await?)
+    }
+}
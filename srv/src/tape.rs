@@ -0,0 +1,165 @@
+use axum::extract::{Json, Path, Query, State};
+use axum::response::sse::{Event, KeepAlive};
+use axum::response::{IntoResponse, Sse};
+use axum::{routing, Router};
+use chrono::{DateTime, FixedOffset, Utc};
+use entity::rec;
+use entity::sea_orm_active_enums::Dir;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::error::Error;
+use crate::security::{Tick, Update};
+use crate::state::AppState;
+
+/// Filters shared by the public tape and an account's own trades.
+/// Pages run from newest to oldest; pass the returned `next` as `cursor` for the following page.
+#[derive(Deserialize)]
+pub struct Filter {
+    pub code: Option<String>,
+    pub from: Option<DateTime<FixedOffset>>,
+    pub to: Option<DateTime<FixedOffset>>,
+    pub cursor: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct Own {
+    pub id: i64,
+}
+
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<i64>,
+}
+
+/// A trade with the counterparties stripped.
+#[derive(Serialize)]
+pub struct Sale {
+    pub ack: i64,
+    pub code: String,
+    pub price: Decimal,
+    pub quantity: i64,
+    pub aggressor: Option<Dir>,
+    pub auction: bool,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+/// A trade seen from one of its counterparties.
+#[derive(Serialize)]
+pub struct Fill {
+    #[serde(flatten)]
+    pub rec: rec::Model,
+    pub dir: Dir,
+}
+
+/// A trade as it happens, flagged with how it was matched.
+#[derive(Serialize)]
+pub struct Print {
+    pub seq: u64,
+    pub price: Decimal,
+    pub quantity: i64,
+    pub aggressor: Option<Dir>,
+    pub auction: bool,
+    pub happened_at: DateTime<FixedOffset>,
+}
+
+impl Filter {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(100).min(1000)
+    }
+}
+
+fn page<T>(recs: Vec<rec::Model>, filter: &Filter, f: impl Fn(rec::Model) -> T) -> Page<T> {
+    let next = (recs.len() as u64 == filter.limit())
+        .then(|| recs.last().map(|rec| rec.ack))
+        .flatten();
+    Page {
+        items: recs.into_iter().map(f).collect(),
+        next,
+    }
+}
+
+async fn sales(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(filter): Query<Filter>,
+) -> Result<impl IntoResponse, Error> {
+    let recs = state.store.sales(&code, &filter).await?;
+    Ok(Json(page(recs, &filter, |rec| Sale {
+        ack: rec.ack,
+        code: rec.code,
+        price: rec.price,
+        quantity: rec.quantity,
+        aggressor: rec.aggressor,
+        auction: rec.auction,
+        created_at: rec.created_at,
+    })))
+}
+
+async fn view_trades(
+    State(state): State<AppState>,
+    Query(Own { id }): Query<Own>,
+    Query(filter): Query<Filter>,
+) -> Result<impl IntoResponse, Error> {
+    let recs = state.store.trades_of(id, &filter).await?;
+    Ok(Json(page(recs, &filter, |rec| Fill {
+        dir: if rec.buyer_id == id {
+            Dir::Buy
+        } else {
+            Dir::Sell
+        },
+        rec,
+    })))
+}
+
+/// Trades on a security as they happen. A subscriber falling behind is told of the gap and
+/// the stream ends, to be picked up again from the tape.
+async fn watch_sales(
+    State(state): State<AppState>,
+    Path(code): Path<Arc<str>>,
+) -> Result<impl IntoResponse, Error> {
+    let security = state
+        .engine
+        .get(&code)
+        .map(|security| security.value().clone())
+        .ok_or(Error::NotFound("security"))?;
+    Ok(Sse::new(async_stream::stream! {
+        let mut rx = security.bc.subscribe();
+        loop {
+            match rx.recv().await {
+                Ok(Tick {
+                    seq,
+                    update: Update::Trade(aggressor, price, quantity),
+                    ..
+                }) => {
+                    yield Event::default().event("sale").id(seq.to_string()).json_data(Print {
+                        seq,
+                        price,
+                        quantity,
+                        aggressor,
+                        auction: aggressor.is_none(),
+                        happened_at: Utc::now().fixed_offset(),
+                    });
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    yield Event::default().event("gap").json_data(skipped);
+                    break;
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+    .keep_alive(KeepAlive::default()))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new()
+        .route("/sales/:code", routing::get(sales))
+        .route("/watch/:code/sales", routing::get(watch_sales))
+        .route("/view_trades", routing::get(view_trades))
+}
@@ -0,0 +1,535 @@
+//! The order flow against each store: what the projector keeps of placing, filling, canceling
+//! and splitting, and what a restart brings back from the journal or from a snapshot.
+
+use chrono::Utc;
+use entity::sea_orm_active_enums::{AcStatus, Dir, OrdStatus, SecurityStatus};
+use entity::{ac, order, position, security};
+use migration::{Migrator, MigratorTrait};
+use rust_decimal::Decimal;
+use sea_orm::{ActiveModelTrait, ActiveValue, ConnectionTrait, Database, DatabaseConnection};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+use crate::candle::{History, Span};
+use crate::config::Config;
+use crate::history::One;
+use crate::journal::Command;
+use crate::period::Period;
+use crate::report::ExecutionReport;
+use crate::snapshot::Snapshot;
+use crate::state::{AppState, OrderRef};
+use crate::storage::{Memory, Orders, Orm, Storage};
+use crate::tape;
+
+const CODE: &str = "TEST";
+
+#[derive(Clone, Copy)]
+enum Backend {
+    Memory,
+    Sqlite,
+}
+
+impl Backend {
+    fn store(self, db: &DatabaseConnection) -> Arc<dyn Storage> {
+        match self {
+            Backend::Memory => Arc::new(Memory::default()),
+            Backend::Sqlite => Arc::new(Orm::new(db.clone())),
+        }
+    }
+}
+
+/// A market in a directory of its own: the database, the journal and the snapshots.
+struct Market {
+    dir: TempDir,
+    db: DatabaseConnection,
+    buyer: i64,
+    seller: i64,
+}
+
+impl Market {
+    async fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let url = format!("sqlite:{}?mode=rwc", dir.path().join("db.sqlite").display());
+        let db = Database::connect(&url).await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let now = Utc::now().fixed_offset();
+        let mut ids = Vec::new();
+        for name in ["buyer", "seller"] {
+            let ac = ac::ActiveModel {
+                pwd: ActiveValue::Set(String::new()),
+                name: ActiveValue::Set(name.to_owned()),
+                status: ActiveValue::Set(AcStatus::Active),
+                created_at: ActiveValue::Set(now),
+                updated_at: ActiveValue::Set(now),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+            ids.push(ac.id);
+        }
+        security::ActiveModel {
+            code: ActiveValue::Set(CODE.to_owned()),
+            name: ActiveValue::Set("Test".to_owned()),
+            currency: ActiveValue::Set("CNY".to_owned()),
+            tick_size: ActiveValue::Set(Decimal::new(1, 2)),
+            lot_size: ActiveValue::Set(100),
+            ref_price: ActiveValue::Set(Some(Decimal::TEN)),
+            price_band: ActiveValue::Set(None),
+            segment: ActiveValue::Set("Main".to_owned()),
+            status: ActiveValue::Set(SecurityStatus::Listed),
+            updated_at: ActiveValue::Set(now),
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        Self {
+            dir,
+            db,
+            buyer: ids[0],
+            seller: ids[1],
+        }
+    }
+
+    fn config(&self) -> Config {
+        let mut config = Config::default();
+        config.journal.path = self.dir.path().join("srv.journal");
+        config.journal.fsync = false;
+        config.snapshot.dir = self.snapshots();
+        config
+    }
+
+    fn snapshots(&self) -> PathBuf {
+        self.dir.path().join("snapshots")
+    }
+
+    async fn start(&self, store: Arc<dyn Storage>) -> AppState {
+        AppState::restore(Arc::new(self.config()), self.db.clone(), store)
+            .await
+            .unwrap()
+    }
+
+    /// Empties the order flow kept in the database, as if it had never been projected.
+    async fn forget(&self) {
+        for table in [
+            "projection",
+            "candle",
+            "msg",
+            "order_history",
+            "rec",
+            "\"order\"",
+            "req",
+            "position",
+        ] {
+            self.db
+                .execute_unprepared(&format!("DELETE FROM {table}"))
+                .await
+                .unwrap();
+        }
+    }
+}
+
+fn order(dir: Dir, price: i64, quantity: i64) -> order::Model {
+    order::Model {
+        seq: 0,
+        code: CODE.to_owned(),
+        dir,
+        price: Decimal::from(price),
+        quantity,
+    }
+}
+
+/// The buyer rests 300 and is filled 100 by the seller.
+async fn trade(market: &Market, state: &AppState) {
+    state
+        .submit(Command::Phase(Period::Continuous))
+        .await
+        .unwrap();
+    state
+        .place(
+            market.buyer,
+            order(Dir::Buy, 10, 300),
+            Some("b1".to_owned()),
+        )
+        .await
+        .unwrap();
+    state
+        .place(market.seller, order(Dir::Sell, 10, 100), None)
+        .await
+        .unwrap();
+    state.projector.flush().await.unwrap();
+}
+
+/// The buyer rests another 500, then the shares split in two while the market is closed.
+async fn split(market: &Market, state: &AppState) {
+    state
+        .place(
+            market.buyer,
+            order(Dir::Buy, 10, 500),
+            Some("b2".to_owned()),
+        )
+        .await
+        .unwrap();
+    state
+        .submit(Command::Phase(Period::Suspense))
+        .await
+        .unwrap();
+    state
+        .submit(Command::Split {
+            code: CODE.to_owned(),
+            from: 1,
+            to: 2,
+            tick: Decimal::new(1, 2),
+            lot: 100,
+        })
+        .await
+        .unwrap();
+    state.projector.flush().await.unwrap();
+}
+
+async fn quantities(state: &AppState) -> Vec<(Decimal, i64)> {
+    state
+        .store
+        .orders(Orders::All)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(order, _)| (order.price, order.quantity))
+        .collect()
+}
+
+/// `(span, volume, turnover)` of the bars of every span, newest first within each.
+async fn bars(state: &AppState) -> Vec<(i64, i64, Decimal)> {
+    let mut bars = Vec::new();
+    for span in Span::ALL {
+        let history = History {
+            span,
+            from: None,
+            to: None,
+            limit: None,
+        };
+        let candles = state.store.candles(CODE, &history).await.unwrap();
+        bars.extend(
+            candles
+                .into_iter()
+                .map(|candle| (candle.span, candle.volume, candle.turnover)),
+        );
+    }
+    bars
+}
+
+async fn held(state: &AppState, id: i64) -> Vec<i64> {
+    state
+        .store
+        .positions(id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|position| position.quantity)
+        .collect()
+}
+
+async fn status(market: &Market, state: &AppState, cl_ord_id: &str) -> (OrdStatus, i64) {
+    let order = state
+        .store
+        .order(One {
+            id: market.buyer,
+            seq: None,
+            cl_ord_id: Some(cl_ord_id.to_owned()),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    (order.status, order.cum_quantity)
+}
+
+/// What a restart must bring back.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    stored: Vec<order::Model>,
+    booked: Vec<(i64, Dir, Decimal, i64)>,
+    positions: Vec<position::Model>,
+    /// `(span, volume, turnover)` of each bar; trades replayed are booked at the time again.
+    candles: Vec<(i64, i64, Decimal)>,
+    /// `(seq, id, price, quantity, cum)` of each working order.
+    working: Vec<(i64, i64, Decimal, i64, i64)>,
+}
+
+async fn outcome(state: &AppState) -> Outcome {
+    let mut positions = state.store.holders(CODE).await.unwrap();
+    positions.sort_by_key(|position| position.id);
+    let mut working: Vec<_> = state
+        .working
+        .iter()
+        .map(|progress| {
+            (
+                progress.seq,
+                progress.id,
+                progress.price,
+                progress.quantity,
+                progress.cum,
+            )
+        })
+        .collect();
+    working.sort();
+    let book = state.engine.get(CODE).unwrap().value().clone();
+    Outcome {
+        stored: state
+            .store
+            .orders(Orders::All)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(order, _)| order)
+            .collect(),
+        booked: book
+            .orders()
+            .await
+            .1
+            .into_iter()
+            .map(|change| (change.seq, change.dir, change.price, change.quantity))
+            .collect(),
+        positions,
+        candles: bars(state).await,
+        working,
+    }
+}
+
+async fn projects_the_order_flow(backend: Backend) {
+    let market = Market::new().await;
+    let state = market.start(backend.store(&market.db)).await;
+
+    trade(&market, &state).await;
+    assert_eq!(quantities(&state).await, [(Decimal::TEN, 200)]);
+    assert_eq!(held(&state, market.buyer).await, [100]);
+    assert_eq!(held(&state, market.seller).await, [-100]);
+    assert_eq!(
+        bars(&state).await,
+        Span::ALL.map(|span| (span.secs(), 100, Decimal::from(1000)))
+    );
+    let filter = tape::Filter {
+        code: None,
+        from: None,
+        to: None,
+        cursor: None,
+        limit: None,
+    };
+    let sales = state.store.sales(CODE, &filter).await.unwrap();
+    assert_eq!(
+        sales
+            .iter()
+            .map(|rec| (rec.buyer_id, rec.seller_id, rec.price, rec.quantity))
+            .collect::<Vec<_>>(),
+        [(market.buyer, market.seller, Decimal::TEN, 100)]
+    );
+    assert_eq!(
+        status(&market, &state, "b1").await,
+        (OrdStatus::PartiallyFilled, 100)
+    );
+
+    let canceled = state
+        .cancel_order(market.buyer, OrderRef::ClOrdId("b1".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(canceled, Some(200));
+    state.projector.flush().await.unwrap();
+    assert_eq!(quantities(&state).await, []);
+    assert_eq!(
+        status(&market, &state, "b1").await,
+        (OrdStatus::Canceled, 100)
+    );
+
+    split(&market, &state).await;
+    assert_eq!(quantities(&state).await, [(Decimal::new(500, 2), 1000)]);
+    assert_eq!(held(&state, market.buyer).await, [200]);
+    assert_eq!(held(&state, market.seller).await, [-200]);
+    let (_, b2) = state.orders(|progress| progress.cl_ord_id.as_deref() == Some("b2"))[0].clone();
+    assert_eq!((b2.price, b2.quantity), (Decimal::new(500, 2), 1000));
+}
+
+async fn replays_the_journal(backend: Backend) {
+    let market = Market::new().await;
+    let state = market.start(backend.store(&market.db)).await;
+    trade(&market, &state).await;
+    split(&market, &state).await;
+    let before = outcome(&state).await;
+
+    // A store that kept nothing takes everything from the journal.
+    market.forget().await;
+    let state = market.start(backend.store(&market.db)).await;
+    assert_eq!(outcome(&state).await, before);
+    assert_eq!(*state.period.borrow(), Period::Suspense);
+}
+
+async fn restarts_from_a_snapshot(backend: Backend) {
+    let market = Market::new().await;
+    let store = backend.store(&market.db);
+    let state = market.start(store.clone()).await;
+    trade(&market, &state).await;
+
+    let snapshot = state.snapshot().await.unwrap();
+    let path = snapshot.write(&market.snapshots()).unwrap().unwrap();
+    let read = Snapshot::read(&path).unwrap();
+    assert_eq!((read.seq, read.phase), (snapshot.seq, snapshot.phase));
+    assert_eq!(
+        read.working.keys().collect::<Vec<_>>(),
+        snapshot.working.keys().collect::<Vec<_>>()
+    );
+    for (code, book) in &snapshot.books {
+        let orders = |book: &crate::book::Book| {
+            book.orders()
+                .into_iter()
+                .map(|change| (change.seq, change.dir, change.price, change.quantity))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(orders(&read.books[code]), orders(book));
+        assert_eq!(read.books[code].seq, book.seq);
+    }
+
+    // The store kept up, so the books come from the snapshot and the later entries from the
+    // journal.
+    split(&market, &state).await;
+    let before = outcome(&state).await;
+    let state = market.start(store).await;
+    assert_eq!(outcome(&state).await, before);
+}
+
+/// Effects that find the projection queue full wait their turn, whichever the runtime.
+async fn projects_through_a_full_queue(backend: Backend) {
+    let market = Market::new().await;
+    let mut config = market.config();
+    config.projection.capacity = 1;
+    let state = AppState::restore(
+        Arc::new(config),
+        market.db.clone(),
+        backend.store(&market.db),
+    )
+    .await
+    .unwrap();
+    trade(&market, &state).await;
+    split(&market, &state).await;
+    assert_eq!(
+        quantities(&state).await,
+        [(Decimal::new(500, 2), 400), (Decimal::new(500, 2), 1000)]
+    );
+    assert_eq!(held(&state, market.buyer).await, [200]);
+    assert_eq!(held(&state, market.seller).await, [-200]);
+}
+
+/// A delisting expires what rests on the book and turns away what is journaled after it, the
+/// same again when the journal is replayed.
+async fn delists(backend: Backend) {
+    let market = Market::new().await;
+    let state = market.start(backend.store(&market.db)).await;
+    trade(&market, &state).await;
+    security::ActiveModel {
+        code: ActiveValue::Set(CODE.to_owned()),
+        status: ActiveValue::Set(SecurityStatus::Delisted),
+        ..Default::default()
+    }
+    .update(&market.db)
+    .await
+    .unwrap();
+    state
+        .submit(Command::Delist {
+            code: CODE.to_owned(),
+        })
+        .await
+        .unwrap();
+    // Checked against the rules before the delisting, journaled after it.
+    let late = state
+        .submit(Command::Place {
+            id: market.seller,
+            order: order(Dir::Sell, 10, 100),
+            cl_ord_id: None,
+        })
+        .await
+        .unwrap();
+    state.projector.flush().await.unwrap();
+
+    delisted(&market, &state, late).await;
+
+    // Nothing kept, so the books follow the journal through the delisting.
+    market.forget().await;
+    let state = market.start(backend.store(&market.db)).await;
+    delisted(&market, &state, late).await;
+}
+
+/// The book is gone, its order expired and order `late` rejected.
+async fn delisted(market: &Market, state: &AppState, late: i64) {
+    assert!(state.engine.get(CODE).is_none());
+    assert!(state.working.is_empty());
+    assert_eq!(quantities(state).await, []);
+    assert_eq!(status(market, state, "b1").await, (OrdStatus::Expired, 100));
+    let rejected: Vec<_> = state
+        .store
+        .msgs()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|msg| msg.id == market.seller)
+        .filter_map(|msg| serde_json::from_value::<ExecutionReport>(msg.data).ok())
+        .filter(|report| report.seq == Some(late))
+        .map(|report| (report.status, report.text))
+        .collect();
+    assert_eq!(
+        rejected,
+        [(OrdStatus::Rejected, Some("unknown security".to_owned()))]
+    );
+}
+
+#[tokio::test]
+async fn memory_projects_the_order_flow() {
+    projects_the_order_flow(Backend::Memory).await;
+}
+
+#[tokio::test]
+async fn sqlite_projects_the_order_flow() {
+    projects_the_order_flow(Backend::Sqlite).await;
+}
+
+#[tokio::test]
+async fn memory_replays_the_journal() {
+    replays_the_journal(Backend::Memory).await;
+}
+
+#[tokio::test]
+async fn sqlite_replays_the_journal() {
+    replays_the_journal(Backend::Sqlite).await;
+}
+
+#[tokio::test]
+async fn memory_restarts_from_a_snapshot() {
+    restarts_from_a_snapshot(Backend::Memory).await;
+}
+
+#[tokio::test]
+async fn sqlite_restarts_from_a_snapshot() {
+    restarts_from_a_snapshot(Backend::Sqlite).await;
+}
+
+#[tokio::test]
+async fn memory_projects_through_a_full_queue() {
+    projects_through_a_full_queue(Backend::Memory).await;
+}
+
+#[tokio::test]
+async fn sqlite_projects_through_a_full_queue() {
+    projects_through_a_full_queue(Backend::Sqlite).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn memory_projects_through_a_full_queue_on_threads() {
+    projects_through_a_full_queue(Backend::Memory).await;
+}
+
+#[tokio::test]
+async fn memory_delists() {
+    delists(Backend::Memory).await;
+}
+
+#[tokio::test]
+async fn sqlite_delists() {
+    delists(Backend::Sqlite).await;
+}
@@ -0,0 +1,324 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::{routing, Router};
+use entity::sea_orm_active_enums::{AcStatus, Dir};
+use entity::{ac, order};
+use futures::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use sea_orm::EntityTrait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::task::JoinHandle;
+
+use crate::book::Picture;
+use crate::error::Error;
+use crate::msg::MsgBody;
+use crate::security::Feed;
+use crate::state::{AppState, OrderRef};
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum Request {
+    Auth {
+        id: i64,
+        pwd: String,
+    },
+    Subscribe {
+        code: Arc<str>,
+    },
+    Unsubscribe {
+        code: Arc<str>,
+    },
+    Place {
+        code: String,
+        dir: Dir,
+        price: Decimal,
+        quantity: i64,
+        cl_ord_id: Option<String>,
+    },
+    Amend {
+        seq: OrderRef,
+        price: Decimal,
+        quantity: i64,
+        cl_ord_id: Option<String>,
+    },
+    Cancel {
+        seq: OrderRef,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub enum Reply {
+    Authed {
+        id: i64,
+    },
+    Subscribed {
+        code: Arc<str>,
+    },
+    Unsubscribed {
+        code: Arc<str>,
+    },
+    Accepted {
+        seq: i64,
+    },
+    Canceled {
+        seq: OrderRef,
+        quantity: Option<i64>,
+    },
+    Rejected {
+        status: u16,
+        code: &'static str,
+        reason: String,
+    },
+    Snapshot {
+        code: Arc<str>,
+        picture: Arc<Picture>,
+    },
+    Order {
+        code: Arc<str>,
+        seq: u64,
+        dir: Dir,
+        price: Decimal,
+        quantity: i64,
+    },
+    Trade {
+        code: Arc<str>,
+        seq: u64,
+        dir: Option<Dir>,
+        price: Decimal,
+        quantity: i64,
+    },
+    /// An execution report or other account message, as also sent on `/msg`.
+    Report(MsgBody),
+    /// No more messages follow, the session having fallen too far behind; authenticate again to
+    /// get them, and `/inbox` for those missed.
+    Dropped,
+}
+
+impl From<Error> for Reply {
+    fn from(err: Error) -> Self {
+        Reply::Rejected {
+            status: err.status().as_u16(),
+            code: err.code(),
+            reason: err.to_string(),
+        }
+    }
+}
+
+struct Session {
+    state: AppState,
+    /// Replies on their way out; a client not reading them holds up the session.
+    tx: Sender<Reply>,
+    id: Option<i64>,
+    inbox: Option<JoinHandle<()>>,
+    subs: HashMap<Arc<str>, JoinHandle<()>>,
+}
+
+impl Session {
+    async fn handle(&mut self, request: Request) -> Reply {
+        match request {
+            Request::Auth { id, pwd } => self.auth(id, pwd).await.unwrap_or_else(Reply::from),
+            Request::Subscribe { code } => self.subscribe(code),
+            Request::Unsubscribe { code } => match self.subs.remove(&code) {
+                Some(task) => {
+                    task.abort();
+                    Reply::Unsubscribed { code }
+                }
+                None => Error::NotFound("subscription").into(),
+            },
+            Request::Place {
+                code,
+                dir,
+                price,
+                quantity,
+                cl_ord_id,
+            } => match self.id {
+                Some(id) => self
+                    .state
+                    .place(
+                        id,
+                        order::Model {
+                            seq: 0,
+                            code,
+                            dir,
+                            price,
+                            quantity,
+                        },
+                        cl_ord_id,
+                    )
+                    .await
+                    .map_or_else(Reply::from, |placed| Reply::Accepted { seq: placed.seq() }),
+                None => Error::Unauthorized.into(),
+            },
+            Request::Amend {
+                seq,
+                price,
+                quantity,
+                cl_ord_id,
+            } => match self.id {
+                Some(id) => self
+                    .state
+                    .amend(id, seq, price, quantity, cl_ord_id)
+                    .await
+                    .map_or_else(Reply::from, |placed| Reply::Accepted { seq: placed.seq() }),
+                None => Error::Unauthorized.into(),
+            },
+            Request::Cancel { seq } => match self.id {
+                Some(id) => self
+                    .state
+                    .cancel_order(id, seq.clone())
+                    .await
+                    .map_or_else(Reply::from, |quantity| Reply::Canceled { seq, quantity }),
+                None => Error::Unauthorized.into(),
+            },
+        }
+    }
+
+    async fn auth(&mut self, id: i64, pwd: String) -> Result<Reply, Error> {
+        match ac::Entity::find_by_id(id).one(&self.state.db).await? {
+            Some(model) if crate::ac::verify(&self.state, &model, &pwd).await => {
+                if model.status == AcStatus::Closed {
+                    return Err(Error::Forbidden("account is closed"));
+                }
+                let (mut rx, unsent) = self.state.online(id, "ws").await;
+                let replies = self.tx.clone();
+                if let Some(inbox) = self.inbox.replace(tokio::spawn(async move {
+                    for body in unsent {
+                        if replies.send(Reply::Report(body)).await.is_err() {
+                            return;
+                        }
+                    }
+                    while let Some(body) = rx.recv().await {
+                        if replies.send(Reply::Report(body)).await.is_err() {
+                            return;
+                        }
+                    }
+                    replies.send(Reply::Dropped).await.unwrap_or_default();
+                })) {
+                    inbox.abort();
+                }
+                self.id = Some(id);
+                Ok(Reply::Authed { id })
+            }
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    fn subscribe(&mut self, code: Arc<str>) -> Reply {
+        let Some(security) = self
+            .state
+            .engine
+            .get(&code)
+            .map(|security| security.value().clone())
+        else {
+            return Error::NotFound("security").into();
+        };
+        let replies = self.tx.clone();
+        let task = tokio::spawn({
+            let code = code.clone();
+            async move {
+                let mut feed = std::pin::pin!(security.feed());
+                while let Some(feed) = feed.next().await {
+                    let code = code.clone();
+                    let reply = match feed {
+                        Feed::Snapshot(picture) => Reply::Snapshot { code, picture },
+                        Feed::Order(seq, dir, price, quantity) => Reply::Order {
+                            code,
+                            seq,
+                            dir,
+                            price,
+                            quantity,
+                        },
+                        Feed::Trade(seq, dir, price, quantity) => Reply::Trade {
+                            code,
+                            seq,
+                            dir,
+                            price,
+                            quantity,
+                        },
+                    };
+                    if replies.send(reply).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        if let Some(task) = self.subs.insert(code.clone(), task) {
+            task.abort();
+        }
+        Reply::Subscribed { code }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(inbox) = self.inbox.take() {
+            inbox.abort();
+        }
+        for (_, task) in self.subs.drain() {
+            task.abort();
+        }
+    }
+}
+
+async fn session(state: AppState, socket: WebSocket) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::channel::<Reply>(state.config.inbox.session_capacity);
+    let writer = tokio::spawn(async move {
+        while let Some(reply) = rx.recv().await {
+            let text = match serde_json::to_string(&reply) {
+                Ok(text) => text,
+                Err(err) => {
+                    tracing::error!("websocket session ended, a reply did not serialize: {err}");
+                    break;
+                }
+            };
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+        // Ends the session on the client's side too, so the reader stops.
+        sink.close().await.unwrap_or_default();
+    });
+    let mut session = Session {
+        state,
+        tx,
+        id: None,
+        inbox: None,
+        subs: HashMap::new(),
+    };
+    while let Some(Ok(message)) = stream.next().await {
+        match message {
+            Message::Text(text) => {
+                let reply = match serde_json::from_str(&text) {
+                    Ok(request) => session.handle(request).await,
+                    Err(err) => Reply::Rejected {
+                        status: StatusCode::BAD_REQUEST.as_u16(),
+                        code: "bad_request",
+                        reason: err.to_string(),
+                    },
+                };
+                if session.tx.send(reply).await.is_err() {
+                    break;
+                }
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    drop(session);
+    writer.abort();
+}
+
+async fn ws(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> impl IntoResponse {
+    upgrade.on_upgrade(move |socket| session(state, socket))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new().route("/ws", routing::get(ws))
+}
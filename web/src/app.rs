@@ -1,5 +1,6 @@
 use yew::prelude::*;
 use yew_router::{BrowserRouter, Switch};
+use crate::candle::Chart;
 use crate::security::Security;
 
 use crate::route::Route;
@@ -17,5 +18,11 @@ fn switch(routes: Route) -> Html {
     let url = AttrValue::Static("/api");
     match routes {
         Route::Board { code } => html! { <Security api={format!("{}/watch/{}", url, code)} /> },
+        Route::Candle { code, span } => html! {
+            <Chart
+                history={format!("{}/candle/{}?span={}", url, code, span)}
+                live={format!("{}/candle/{}/live?span={}", url, code, span)}
+            />
+        },
     }
 }
\ No newline at end of file
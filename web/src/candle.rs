@@ -0,0 +1,148 @@
+use futures::StreamExt;
+use gloo_net::eventsource::futures::EventSource;
+use gloo_net::http::Request;
+use gloo_utils::format::JsValueSerdeExt;
+use plotters::prelude::{
+    CandleStick, ChartBuilder, Color, IntoDrawingArea, SVGBackend, GREEN, RED,
+};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use yew::{html, AttrValue, Component, Context, Html, Properties};
+
+#[derive(PartialEq, Properties)]
+pub struct Props {
+    pub history: AttrValue,
+    pub live: AttrValue,
+}
+
+pub enum Msg {
+    History(Vec<Bar>),
+    Bar(Bar),
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Bar {
+    start: String,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: i64,
+    vwap: Decimal,
+}
+
+#[derive(Default)]
+pub struct Chart {
+    bars: Vec<Bar>,
+    es: Option<EventSource>,
+}
+
+impl Component for Chart {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let history = ctx.props().history.clone();
+        ctx.link().send_future(async move {
+            Msg::History(
+                Request::get(&history)
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap(),
+            )
+        });
+        let mut es = EventSource::new(&ctx.props().live).unwrap();
+        ctx.link()
+            .send_stream(es.subscribe("candle").unwrap().map(Result::ok).filter_map(
+                |event| async {
+                    event.map(|(_, event)| {
+                        Msg::Bar(
+                            serde_json::from_str(&event.data().into_serde::<String>().unwrap())
+                                .unwrap(),
+                        )
+                    })
+                },
+            ));
+
+        Self {
+            bars: Vec::new(),
+            es: Some(es),
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::History(bars) => {
+                let live = std::mem::replace(&mut self.bars, bars);
+                for bar in live {
+                    self.merge(bar);
+                }
+            }
+            Msg::Bar(bar) => self.merge(bar),
+        }
+        true
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some(es) = self.es.take() {
+            es.close();
+        }
+    }
+
+    fn view(&self, _ctx: &Context<Self>) -> Html {
+        let mut svg = String::new();
+        let low = self.bars.iter().map(|bar| bar.low).min();
+        let high = self.bars.iter().map(|bar| bar.high).max();
+        if let (Some(low), Some(high)) = (low, high) {
+            let drawing_area = SVGBackend::with_string(&mut svg, (640, 480)).into_drawing_area();
+            let mut chart = ChartBuilder::on(&drawing_area)
+                .margin(5)
+                .x_label_area_size(60)
+                .y_label_area_size(30)
+                .build_cartesian_2d(
+                    -1..self.bars.len() as i32,
+                    low.to_f64().unwrap() - 0.02..high.to_f64().unwrap() + 0.02,
+                )
+                .unwrap();
+            chart.configure_mesh().draw().unwrap();
+            chart
+                .draw_series(self.bars.iter().enumerate().map(|(i, bar)| {
+                    CandleStick::new(
+                        i as i32,
+                        bar.open.to_f64().unwrap(),
+                        bar.high.to_f64().unwrap(),
+                        bar.low.to_f64().unwrap(),
+                        bar.close.to_f64().unwrap(),
+                        GREEN.filled(),
+                        RED,
+                        8,
+                    )
+                }))
+                .unwrap();
+        }
+        let svg = Html::from_html_unchecked(svg.into());
+        html! {
+            <div>
+                <p>{svg}</p>
+
+                <p><ol>
+                    {for self.bars.iter().rev().map(|bar| format!("{}: O {} H {} L {} C {} V {} VWAP {}\n", bar.start, bar.open, bar.high, bar.low, bar.close, bar.volume, bar.vwap))}
+                </ol></p>
+            </div>
+        }
+    }
+}
+
+impl Chart {
+    fn merge(&mut self, bar: Bar) {
+        match self.bars.last_mut() {
+            Some(last) if last.start == bar.start => *last = bar,
+            Some(last) if last.start > bar.start => {}
+            _ => self.bars.push(bar),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod app;
+mod candle;
 mod route;
 mod security;
 
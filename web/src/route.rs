@@ -5,4 +5,6 @@ use yew_router::Routable;
 pub enum Route {
     #[at("/:code/picture")]
     Board { code: AttrValue },
+    #[at("/:code/candle/:span")]
+    Candle { code: AttrValue, span: AttrValue },
 }
\ No newline at end of file
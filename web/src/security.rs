@@ -24,9 +24,9 @@ pub struct Props {
 }
 
 pub enum Msg {
-    Init((Security, EventSource)),
-    Trade((Option<Dir>, Decimal, i64)),
-    Order((Dir, Decimal, i64)),
+    Snapshot(Picture),
+    Trade((u64, (Option<Dir>, Decimal, i64))),
+    Order((u64, (Dir, Decimal, i64))),
 }
 
 #[derive(Deserialize, Default)]
@@ -34,6 +34,7 @@ pub struct Picture {
     bids: BTreeMap<Decimal, i64>,
     offers: BTreeMap<Decimal, i64>,
     price_call: Option<f64>,
+    seq: u64,
 }
 
 #[derive(Default)]
@@ -48,46 +49,36 @@ impl Component for Security {
     type Properties = Props;
 
     fn create(ctx: &Context<Self>) -> Self {
-        let api = ctx.props().api.clone();
-        ctx.link().send_future(async move {
-            let mut es = EventSource::new(&api).unwrap();
-            Msg::Init({
-                let mut pic = Picture::default();
-                let mut init = es.subscribe("message").unwrap();
-                if let Some(Ok((_, event))) = init.next().await {
-                    pic = serde_json::from_str(&event.data().into_serde::<String>().unwrap()).unwrap();
-                }
-                (
-                    Self {
-                        pic,
-                        recs: Vec::new(),
-                        es: None,
-                    },
-                    es,
-                )
-            })
-        });
+        let mut es = EventSource::new(&ctx.props().api).unwrap();
+        ctx.link()
+            .send_stream(es.subscribe("snapshot").unwrap().map(Result::ok).filter_map(|event| async {
+                event.map(|(_, event)| Msg::Snapshot(serde_json::from_str(&event.data().into_serde::<String>().unwrap()).unwrap()))
+            }));
+        ctx.link()
+            .send_stream(es.subscribe("trade").unwrap().map(Result::ok).filter_map(|event| async {
+                event.map(|(_, event)| Msg::Trade(serde_json::from_str(&event.data().into_serde::<String>().unwrap()).unwrap()))
+            }));
+        ctx.link()
+            .send_stream(es.subscribe("order").unwrap().map(Result::ok).filter_map(|event| async {
+                event.map(|(_, event)| Msg::Order(serde_json::from_str(&event.data().into_serde::<String>().unwrap()).unwrap()))
+            }));
 
-        Self::default()
+        Self {
+            es: Some(es),
+            ..Self::default()
+        }
     }
 
-    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::Init((security, mut es)) => {
-                ctx.link()
-                    .send_stream(es.subscribe("trade").unwrap().map(Result::ok).filter_map(|event| async {
-                        event.map(|(_, event)| Msg::Trade(serde_json::from_str(&event.data().into_serde::<String>().unwrap()).unwrap()))
-                    }));
-                ctx.link()
-                    .send_stream(es.subscribe("order").unwrap().map(Result::ok).filter_map(|event| async {
-                        event.map(|(_, event)| Msg::Order(serde_json::from_str(&event.data().into_serde::<String>().unwrap()).unwrap()))
-                    }));
-                *self = security;
-                self.es = Some(es);
+            Msg::Snapshot(pic) => {
+                self.pic = pic;
             }
-            Msg::Trade((dir, price, vol)) => {
-                
-                if let Some(_) = dir {
+            Msg::Trade((seq, _)) | Msg::Order((seq, _)) if seq <= self.pic.seq => return false,
+            Msg::Trade((seq, (dir, price, vol))) => {
+                self.pic.seq = seq;
+
+                if dir.is_some() {
                     let mut bid = self.pic.bids.last_entry().unwrap();
                     *bid.get_mut() -= vol;
                     if *bid.get() == 0 {
@@ -138,7 +129,8 @@ impl Component for Security {
                 self.recs
                     .push(serde_json::to_string(&("Trade", dir, price, vol)).unwrap().into());
             }
-            Msg::Order((dir, price, count)) => {
+            Msg::Order((seq, (dir, price, count))) => {
+                self.pic.seq = seq;
                 match match dir {
                     Dir::Buy => self.pic.bids.entry(price),
                     Dir::Sell => self.pic.offers.entry(price),
@@ -163,6 +155,12 @@ impl Component for Security {
         true
     }
 
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some(es) = self.es.take() {
+            es.close();
+        }
+    }
+
     fn view(&self, _ctx: &Context<Self>) -> Html {
         let min = match (
             self.pic.bids.first_key_value(),
@@ -209,9 +207,9 @@ impl Component for Security {
                 )
                 .unwrap();
             chart.configure_mesh().draw().unwrap();
-            chart.draw_series(bids.iter().map(|&c| Circle::new(c, 3, &GREEN))).unwrap();
+            chart.draw_series(bids.iter().map(|&c| Circle::new(c, 3, GREEN))).unwrap();
             chart.draw_series(LineSeries::new(bids, &GREEN)).unwrap();
-            chart.draw_series(offers.iter().map(|&c| Circle::new(c, 3, &RED))).unwrap();
+            chart.draw_series(offers.iter().map(|&c| Circle::new(c, 3, RED))).unwrap();
             chart.draw_series(LineSeries::new(offers, &RED)).unwrap();
         }
         let svg = Html::from_html_unchecked(svg.into());